@@ -1,23 +1,151 @@
 use crate::{
     core::{
         configuration::Configuration,
-        input::{processors::parse_puzzle_format, read_source_content},
+        input::{
+            diagnostics::{Diagnostic, diagnose_source},
+            executable::ExecutableSource,
+            format::FormatRegistry,
+            interactive::InteractiveBuilder,
+            json::write_json_puzzle_format,
+            network::NetworkSource,
+            parse_source,
+            processors::{Field, Leaf, StepDecoderFn, StepsFile, read_steps_file},
+            span::Span,
+            writer::write_puzzle_format,
+        },
         state::{StateInfo, StateProxy},
-        step::StepAction,
+        step::{StepAction, binary::StepContainer, caption::StepCaption},
     },
-    domains::DomainRegistry,
+    domains::{
+        DomainRegistry, StepEntries, StepEntry, StepParsers, check_container, decode_step,
+        file_step_entries, parse_entries, step_entries,
+    },
+    engine::registry::Registry,
     error::{ParseError, SolveError},
 };
-use std::path::PathBuf;
+use indexmap::IndexMap;
+use std::{
+    collections::HashMap,
+    ops::{Deref, Range},
+    path::PathBuf,
+    sync::{Arc, OnceLock},
+};
 
-#[derive(Default, Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Default, Debug, Clone, PartialEq)]
 /// Metadata about a puzzle and its parts
 pub struct Metadata {
     pub title: String,
     pub description: Option<String>,
     pub author: Option<String>,
     pub difficulty: Option<u8>,
-    // pub configuration: Configuration,
+    /// Free-form `meta.<key>` entries, such as `tags`, `source`, `date` or `language`
+    pub meta: IndexMap<String, Field>,
+    /// The puzzle-wide `config`, parent of every part's configuration
+    pub configuration: Arc<Configuration>,
+}
+
+impl Metadata {
+    /// Value of a `meta.<key>` entry holding a single value.
+    pub fn meta(&self, key: &str) -> Option<&str> {
+        self.meta.get(key).and_then(Field::as_leaf)
+    }
+
+    /// The `meta.tags` entry, written either as a list or as comma-separated values.
+    pub fn tags(&self) -> Vec<String> {
+        meta_tags(&self.meta)
+    }
+}
+
+pub(crate) fn meta_tags(meta: &IndexMap<String, Field>) -> Vec<String> {
+    match meta.get("tags") {
+        Some(Field::Leaf(leaf)) => leaf
+            .value
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_string)
+            .collect(),
+        Some(Field::List(items)) => items
+            .iter()
+            .filter_map(Field::as_leaf)
+            .map(|tag| tag.trim().to_string())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// The steps of a part, as found in the source and before any parsing.
+#[derive(Debug, Clone)]
+pub enum RawSteps {
+    /// A single `|`-separated list, as written in the key-path format
+    Delimited(Leaf),
+    /// One entry per step: a leaf is parsed from its string form, a node is handed to the step
+    /// type's structured parser
+    List(Vec<Field>),
+    /// A `steps_file`, holding either one step per line or a binary [`StepContainer`]. It is only
+    /// read when the steps are needed
+    File { path: PathBuf, span: Span },
+}
+
+impl RawSteps {
+    /// Span of the whole list, for diagnostics.
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            RawSteps::Delimited(leaf) => Some(&leaf.span),
+            RawSteps::List(items) => items.first().and_then(Field::first_key_span),
+            RawSteps::File { span, .. } => Some(span),
+        }
+    }
+
+    /// Counts the steps without parsing them, once repetitions, loops and macros are expanded.
+    /// A step group counts as a single step. A steps file is read, but its steps are not parsed.
+    pub fn count(&self, configuration: &Configuration) -> Result<usize, ParseError> {
+        match self {
+            RawSteps::File { path, span } => {
+                match read_steps_file(path).map_err(|error| error.at(span.clone()))? {
+                    StepsFile::Text(source) => Ok(file_step_entries(&source, configuration)?
+                        .frame_starts()
+                        .len()),
+                    StepsFile::Binary(container) => Ok(container.len()),
+                }
+            }
+            raw_steps => Ok(step_entries(raw_steps, configuration)?.frame_starts().len()),
+        }
+    }
+}
+
+/// Where the steps are written is left out, like for [`Leaf`].
+impl PartialEq for RawSteps {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (RawSteps::Delimited(a), RawSteps::Delimited(b)) => a == b,
+            (RawSteps::List(a), RawSteps::List(b)) => a == b,
+            (RawSteps::File { path: a, .. }, RawSteps::File { path: b, .. }) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// The lines of a steps file holding a step.
+pub(crate) fn steps_file_lines(content: &str) -> impl Iterator<Item = &str> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+}
+
+/// The string form of the steps, with list entries joined by `|`.
+impl std::fmt::Display for RawSteps {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RawSteps::Delimited(leaf) => write!(f, "{}", leaf.value),
+            RawSteps::List(items) => {
+                let items: Vec<&str> = items.iter().filter_map(Field::as_leaf).collect();
+                write!(f, "{}", items.join(" | "))
+            }
+            RawSteps::File { path, .. } => write!(f, "<{}>", path.display()),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -26,30 +154,73 @@ pub struct RawPartMetadata {
     pub id: String,
     pub display_name: String,
     pub description: Option<String>,
-    pub raw_steps_string: String,
+    pub raw_steps: RawSteps,
     pub configuration: Configuration,
     // this is maybe the wrong type, as in it should already be
     // transformed into a step-related state but we'll see. Like, maybe a Box<dyn VisualizationState>
     // that is the initial state
     pub input_data: String,
     pub raw_step_type_id: String,
+    pub step_type_span: Span,
+    /// The `bookmarks` of the part, with where they are written
+    pub bookmarks: Vec<(Bookmark, Span)>,
+    /// The part whose final state this part starts from, `input_data` being empty then
+    pub input_from: Option<Leaf>,
+    /// The `input_file` the input was read from, as written in the source
+    pub input_file: Option<String>,
+    /// The `steps_file` the steps are read from, as written in the source
+    pub steps_file: Option<String>,
 }
 pub fn parse_part_info(
     part: RawPartMetadata,
     registry: &DomainRegistry,
 ) -> Result<PartInfo, ParseError> {
-    let step_type_id: &'static str = registry.step_type_to_id(&part.raw_step_type_id)?;
+    let step_type_id: &'static str = registry
+        .step_type_to_id(&part.raw_step_type_id)
+        .map_err(|err| err.at(part.step_type_span.clone()))?;
+    let steps_file = part.steps_file.or_else(|| match &part.raw_steps {
+        RawSteps::File { path, .. } => Some(path.display().to_string()),
+        _ => None,
+    });
     Ok(PartInfo {
         step_type_id,
-        steps: registry.parse_steps(step_type_id, &part.raw_steps_string)?,
+        step_parsers: registry.step_parsers(step_type_id)?,
+        raw_steps: part.raw_steps,
+        steps: OnceLock::new(),
         id: part.id,
         display_name: part.display_name,
         description: part.description,
         input_data: part.input_data,
-        configuration: part.configuration,
+        configuration: part
+            .configuration
+            .with_expansion_limits(registry.expansion_limits()),
+        configured_bookmarks: part.bookmarks,
+        input_from: part.input_from.map(|leaf| leaf.value),
+        input_file: part.input_file,
+        steps_file,
+        final_state: OnceLock::new(),
     })
 }
 
+/// A named position within the steps of a part, such as "loop 1 starts". `step` is the step
+/// the engine is at once the bookmark is reached, before that step is played.
+///
+/// Bookmarks are written as `part.<id>.bookmarks.<name>: <step>`, or among the steps as a
+/// `#name` entry marking the position it is written at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bookmark {
+    pub name: String,
+    pub step: usize,
+}
+
+/// The steps from a bookmark up to the next one. Steps before the first bookmark make a
+/// chapter without a name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chapter {
+    pub name: Option<String>,
+    pub steps: Range<usize>,
+}
+
 #[derive(Debug)]
 /// Information about a solvable part of a puzzle
 pub struct PartInfo {
@@ -57,13 +228,346 @@ pub struct PartInfo {
     pub display_name: String,
     pub description: Option<String>,
     pub configuration: Configuration,
-    pub(crate) steps: Vec<Box<dyn StepAction>>,
+    pub(crate) raw_steps: RawSteps,
+    pub(crate) step_parsers: StepParsers,
+    /// Parsed from `raw_steps` on first use, which is usually when the part gets selected
+    pub(crate) steps: OnceLock<PartSteps>,
     // this is maybe the wrong type, but for different reasons than before, because we might need
     // some type markings to make sure a single string type can transfer to multiple types of
     // states. Oh well
     pub(crate) input_data: String,
     pub step_type_id: &'static str,
+    /// Bookmarks given by the `bookmarks` key, the ones written among the steps are only known
+    /// once they are parsed
+    pub(crate) configured_bookmarks: Vec<(Bookmark, Span)>,
+    /// The part whose final state this part starts from, instead of parsing `input_data`
+    pub input_from: Option<String>,
+    /// Where `input_data` was read from, written back in place of the input
+    pub(crate) input_file: Option<String>,
+    /// Where the steps are read from, written back in place of the steps
+    pub(crate) steps_file: Option<String>,
+    /// The final state of the part with its state type id, played once for the parts starting
+    /// from it
+    pub(crate) final_state: OnceLock<(&'static str, Box<dyn StateProxy>)>,
+}
+
+impl PartInfo {
+    /// The steps of the part, parsed the first time they are asked for. Failures are not cached,
+    /// the steps are parsed again on the next call.
+    /// Steps of a binary steps file are left encoded, see [`PartSteps`].
+    pub(crate) fn steps(&self) -> Result<&PartSteps, ParseError> {
+        if let Some(steps) = self.steps.get() {
+            return Ok(steps);
+        }
+        let steps = match &self.raw_steps {
+            RawSteps::File { path, span } => {
+                match read_steps_file(path).map_err(|error| error.at(span.clone()))? {
+                    StepsFile::Binary(container) => {
+                        check_container(&self.step_parsers, &container, span)?;
+                        let bookmarks = self.resolve_bookmarks(Vec::new(), container.len())?;
+                        PartSteps::Binary {
+                            container,
+                            decoder: self.step_parsers.decoder,
+                            span: span.clone(),
+                            bookmarks,
+                        }
+                    }
+                    StepsFile::Text(source) => {
+                        self.parse_entries(file_step_entries(&source, &self.configuration)?)?
+                    }
+                }
+            }
+            raw_steps => self.parse_entries(step_entries(raw_steps, &self.configuration)?)?,
+        };
+        Ok(self.steps.get_or_init(|| steps))
+    }
+
+    fn parse_entries(&self, mut entries: StepEntries) -> Result<PartSteps, ParseError> {
+        let frames = entries.frame_starts();
+        let mut inline = Vec::with_capacity(entries.markers.len());
+        for marker in entries.markers {
+            if entries
+                .steps
+                .get(marker.before)
+                .is_some_and(StepEntry::grouped)
+            {
+                return Err(ParseError::InvalidFormat(format!(
+                    "bookmark '{}' is inside a step group",
+                    marker.name
+                ))
+                .at(marker.span));
+            }
+            let bookmark = Bookmark {
+                name: marker.name,
+                step: frames.partition_point(|&start| start < marker.before),
+            };
+            inline.push((bookmark, marker.span));
+        }
+        let bookmarks = self.resolve_bookmarks(inline, frames.len())?;
+        let captions = entries
+            .steps
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, entry)| Some((index, entry.take_caption()?)))
+            .collect();
+        let steps = parse_entries(self.step_parsers, entries.steps, &self.configuration)
+            .collect::<Result<_, _>>()?;
+        Ok(PartSteps::Parsed {
+            steps,
+            frames,
+            captions,
+            bookmarks,
+        })
+    }
+
+    /// Every bookmark of the part, sorted by step. Names are unique, and bookmarks cannot be
+    /// past the last of the `len` steps.
+    fn resolve_bookmarks(
+        &self,
+        inline: Vec<(Bookmark, Span)>,
+        len: usize,
+    ) -> Result<Vec<Bookmark>, ParseError> {
+        let mut bookmarks: Vec<Bookmark> = Vec::new();
+        for (bookmark, span) in self.configured_bookmarks.iter().cloned().chain(inline) {
+            if bookmark.step > len {
+                return Err(ParseError::InvalidFormat(format!(
+                    "bookmark '{}' is at step {}, past the {len} steps of the part",
+                    bookmark.name, bookmark.step
+                ))
+                .at(span));
+            }
+            if bookmarks.iter().any(|other| other.name == bookmark.name) {
+                return Err(ParseError::InvalidFormat(format!(
+                    "duplicate bookmark '{}'",
+                    bookmark.name
+                ))
+                .at(span));
+            }
+            bookmarks.push(bookmark);
+        }
+        bookmarks.sort_by_key(|bookmark| bookmark.step);
+        Ok(bookmarks)
+    }
+
+    /// The bookmarks of the part sorted by step, which needs its steps to be parsed.
+    pub fn bookmarks(&self) -> Result<&[Bookmark], ParseError> {
+        self.steps().map(PartSteps::bookmarks)
+    }
+
+    /// The chapters the bookmarks split the steps into, covering every step in order.
+    pub fn chapters(&self) -> Result<Vec<Chapter>, ParseError> {
+        let steps = self.steps()?;
+        let bookmarks = steps.bookmarks();
+        let mut chapters = Vec::with_capacity(bookmarks.len() + 1);
+        let first = bookmarks
+            .first()
+            .map_or(steps.len(), |bookmark| bookmark.step);
+        if first > 0 {
+            chapters.push(Chapter {
+                name: None,
+                steps: 0..first,
+            });
+        }
+        for (index, bookmark) in bookmarks.iter().enumerate() {
+            let end = bookmarks
+                .get(index + 1)
+                .map_or(steps.len(), |next| next.step);
+            chapters.push(Chapter {
+                name: Some(bookmark.name.clone()),
+                steps: bookmark.step..end,
+            });
+        }
+        Ok(chapters)
+    }
+
+    /// Whether the steps have been parsed yet.
+    pub fn steps_parsed(&self) -> bool {
+        self.steps.get().is_some()
+    }
+}
+
+/// Steps are compared through their string form, captions, bookmarks and how they are grouped,
+/// or as found in the source if they do not parse.
+impl PartialEq for PartInfo {
+    fn eq(&self, other: &Self) -> bool {
+        let same_steps = match (self.steps(), other.steps()) {
+            (Ok(a), Ok(b))
+                if !a.same_frames(b)
+                    || a.captions() != b.captions()
+                    || a.bookmarks() != b.bookmarks() =>
+            {
+                false
+            }
+            (Ok(a), Ok(b)) => match (a.all(), b.all()) {
+                (Ok(steps), Ok(other_steps)) => {
+                    steps.len() == other_steps.len()
+                        && steps
+                            .iter()
+                            .zip(other_steps.iter())
+                            .all(|(a, b)| a.to_str() == b.to_str())
+                }
+                _ => self.raw_steps == other.raw_steps,
+            },
+            _ => self.raw_steps == other.raw_steps,
+        };
+        self.id == other.id
+            && self.display_name == other.display_name
+            && self.description == other.description
+            && self.configuration == other.configuration
+            && self.input_data == other.input_data
+            && self.input_from == other.input_from
+            && self.step_type_id == other.step_type_id
+            && same_steps
+    }
 }
+/// The steps of a part, once read.
+///
+/// Steps are played frame by frame: a frame is a single step, or every step of a group, which
+/// are applied together. Step counts and indices seen from the engine are frame ones, while
+/// [`PartSteps::range`] and [`PartSteps::all`] go over single steps.
+#[derive(Debug)]
+pub(crate) enum PartSteps {
+    Parsed {
+        steps: Vec<Box<dyn StepAction>>,
+        /// Index of the first step of every frame
+        frames: Vec<usize>,
+        /// Captions of the steps that have one, by step index
+        captions: HashMap<usize, StepCaption>,
+        bookmarks: Vec<Bookmark>,
+    },
+    /// Steps of a binary steps file, only decoded when they are stepped through, so that very
+    /// long timelines are never held as boxed steps all at once. Every record is a frame, and
+    /// records carry no caption.
+    Binary {
+        container: StepContainer,
+        decoder: StepDecoderFn,
+        span: Span,
+        bookmarks: Vec<Bookmark>,
+    },
+}
+
+impl PartSteps {
+    /// The number of frames.
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            PartSteps::Parsed { frames, .. } => frames.len(),
+            PartSteps::Binary { container, .. } => container.len(),
+        }
+    }
+
+    /// The number of single steps.
+    pub(crate) fn step_count(&self) -> usize {
+        match self {
+            PartSteps::Parsed { steps, .. } => steps.len(),
+            PartSteps::Binary { container, .. } => container.len(),
+        }
+    }
+
+    /// Index of the first step of the frame at `frame`, or the step count past the last frame.
+    pub(crate) fn frame_start(&self, frame: usize) -> usize {
+        match self {
+            PartSteps::Parsed { steps, frames, .. } => {
+                frames.get(frame).copied().unwrap_or(steps.len())
+            }
+            PartSteps::Binary { .. } => frame,
+        }
+    }
+
+    /// The steps of the frame at `frame`.
+    pub(crate) fn frame(&self, frame: usize) -> Range<usize> {
+        self.frame_start(frame)..self.frame_start(frame + 1)
+    }
+
+    /// Bookmarks by frame index, sorted.
+    pub(crate) fn bookmarks(&self) -> &[Bookmark] {
+        match self {
+            PartSteps::Parsed { bookmarks, .. } | PartSteps::Binary { bookmarks, .. } => bookmarks,
+        }
+    }
+
+    /// The caption of the step at `index`.
+    pub(crate) fn step_caption(&self, index: usize) -> Option<&StepCaption> {
+        match self {
+            PartSteps::Parsed { captions, .. } => captions.get(&index),
+            PartSteps::Binary { .. } => None,
+        }
+    }
+
+    /// The caption of the frame at `frame`, the first one of its steps have.
+    pub(crate) fn caption(&self, frame: usize) -> Option<&StepCaption> {
+        self.frame(frame).find_map(|index| self.step_caption(index))
+    }
+
+    fn captions(&self) -> Option<&HashMap<usize, StepCaption>> {
+        match self {
+            PartSteps::Parsed { captions, .. } => Some(captions),
+            PartSteps::Binary { .. } => None,
+        }
+    }
+
+    fn same_frames(&self, other: &PartSteps) -> bool {
+        self.len() == other.len() && (0..self.len()).all(|i| self.frame(i) == other.frame(i))
+    }
+
+    /// The steps within `range`, decoded if need be.
+    pub(crate) fn range(&self, range: Range<usize>) -> Result<StepSlice<'_>, ParseError> {
+        match self {
+            PartSteps::Parsed { steps, .. } => Ok(StepSlice::Borrowed(&steps[range])),
+            PartSteps::Binary {
+                container,
+                decoder,
+                span,
+                ..
+            } => range
+                .map(|index| decode_step(*decoder, container, index, span))
+                .collect::<Result<_, _>>()
+                .map(StepSlice::Decoded),
+        }
+    }
+
+    /// Every step. Steps of a binary steps file are all decoded, and dropped with the slice.
+    pub(crate) fn all(&self) -> Result<StepSlice<'_>, ParseError> {
+        self.range(0..self.step_count())
+    }
+
+    /// The steps of every frame in their string form, followed by their caption, as written
+    /// back into a source.
+    pub(crate) fn written_frames(
+        &self,
+        configuration: &Configuration,
+    ) -> Result<Vec<Vec<String>>, ParseError> {
+        let steps = self.all()?;
+        let written = |index: usize| {
+            let step = steps[index].to_str_with(configuration);
+            match self.step_caption(index) {
+                Some(caption) => format!("{step} {caption}"),
+                None => step,
+            }
+        };
+        Ok((0..self.len())
+            .map(|frame| self.frame(frame).map(written).collect())
+            .collect())
+    }
+}
+
+/// Steps handed out by [`PartSteps`], borrowed when they were parsed, decoded for the caller
+/// when they come from a binary steps file.
+pub(crate) enum StepSlice<'a> {
+    Borrowed(&'a [Box<dyn StepAction>]),
+    Decoded(Vec<Box<dyn StepAction>>),
+}
+
+impl Deref for StepSlice<'_> {
+    type Target = [Box<dyn StepAction>];
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            StepSlice::Borrowed(steps) => steps,
+            StepSlice::Decoded(steps) => steps,
+        }
+    }
+}
+
 #[derive(Debug)]
 /// The current, active state of the puzzle. This is decided by which part is selected.
 pub struct Current {
@@ -77,17 +581,6 @@ pub(crate) struct State {
     pub inner: Box<dyn StateProxy>,
     pub info: StateInfo,
 }
-impl State {
-    pub(crate) fn reset(
-        &mut self,
-        raw_state_input: &str,
-        configuration: &Configuration,
-    ) -> Result<(), ParseError> {
-        self.inner = (self.info.factory)(raw_state_input, configuration)?;
-        Ok(())
-    }
-}
-
 impl<'a> Current {
     pub fn current_part(&self, parts: &'a [PartInfo]) -> Option<&'a PartInfo> {
         let current_part_id = &self.part_id;
@@ -109,8 +602,16 @@ pub struct AlgorithmInstance {
     // pub current_part_id: String,
 }
 
+/// Two instances are equal when they hold the same puzzle, wherever they are being viewed at.
+impl PartialEq for AlgorithmInstance {
+    fn eq(&self, other: &Self) -> bool {
+        self.metadata == other.metadata && self.parts == other.parts
+    }
+}
+
 impl AlgorithmInstance {
-    /// Create a puzzle instance from a source
+    /// Create a puzzle instance from a source. The steps of each part are only parsed when the
+    /// part is first selected.
     pub fn from_source(
         source: PuzzleSource,
         registry: &DomainRegistry,
@@ -118,8 +619,21 @@ impl AlgorithmInstance {
     where
         Self: Sized,
     {
-        let raw_content = read_source_content(source)?;
-        let (metadata, parts) = parse_puzzle_format(&raw_content)?;
+        Self::from_source_with_formats(source, registry, &FormatRegistry::default())
+    }
+
+    /// Same as [`from_source`](Self::from_source), reading the source with one of `formats`.
+    pub fn from_source_with_formats(
+        source: PuzzleSource,
+        registry: &DomainRegistry,
+        formats: &FormatRegistry,
+    ) -> Result<AlgorithmInstance, ParseError> {
+        // Typed lines are checked one by one, which only key-path lines can be
+        if let PuzzleSource::Interactive = source {
+            let mut builder = InteractiveBuilder::new(registry);
+            return builder.run(std::io::stdin().lock(), std::io::stderr());
+        }
+        let (metadata, parts) = parse_source(source, formats)?;
         let mut parsed_parts = Vec::with_capacity(parts.len());
         for part in parts.into_iter() {
             parsed_parts.push(parse_part_info(part, registry)?);
@@ -135,6 +649,32 @@ impl AlgorithmInstance {
             // steps: Vec::new(),
         })
     }
+    /// Same as [`from_source`](Self::from_source), but parses the steps of every part right away
+    /// instead of when each part is selected, failing on the first invalid step. Meant for
+    /// validation tools; [`diagnose`](Self::diagnose) reports every problem instead.
+    pub fn from_source_strict(
+        source: PuzzleSource,
+        registry: &DomainRegistry,
+    ) -> Result<AlgorithmInstance, ParseError> {
+        let instance = Self::from_source(source, registry)?;
+        instance.parse_all_steps()?;
+        Ok(instance)
+    }
+
+    /// Parses the steps of every part that has not been parsed yet. Steps of binary steps files
+    /// are decoded once to be checked, and left encoded.
+    pub fn parse_all_steps(&self) -> Result<(), ParseError> {
+        for part in &self.parts {
+            part.steps()?.all()?;
+        }
+        Ok(())
+    }
+
+    /// Checks a source without loading it, collecting every error and warning instead of
+    /// stopping at the first error.
+    pub fn diagnose(source: PuzzleSource, registry: &Registry) -> Vec<Diagnostic> {
+        diagnose_source(source, registry)
+    }
     /// Get currently active part
     pub fn current_part(&self) -> Option<&PartInfo> {
         let current_part_id = self.current.as_ref().map(|current| &current.part_id)?;
@@ -145,6 +685,16 @@ impl AlgorithmInstance {
         self.parts.iter().find(|p| p.id == part_id)
     }
 
+    /// Encodes the puzzle back into the key-path format, see [`write_puzzle_format`].
+    pub fn to_puzzle_format(&self) -> String {
+        write_puzzle_format(&self.metadata, &self.parts)
+    }
+
+    /// Encodes the puzzle as JSON, see [`write_json_puzzle_format`].
+    pub fn to_json_format(&self) -> String {
+        write_json_puzzle_format(&self.metadata, &self.parts)
+    }
+
     /// Shortand of `from_source()` when handling specifically file paths.
     pub fn from_file(_path: PathBuf) -> Result<Self, ParseError> {
         todo!()
@@ -160,13 +710,47 @@ impl AlgorithmInstance {
 /// Raw input data from various sources
 pub enum PuzzleSource {
     File(PathBuf),
+    /// A directory of puzzle files, read as a
+    /// [`PuzzleCollection`](crate::core::input::collection::PuzzleCollection)
+    Directory(PathBuf),
     String(String),
-    Executable(PathBuf, Vec<String>), // path + args
+    Executable(ExecutableSource),
     InlineCode(String),
-    Network(String),
+    Network(NetworkSource),
     Interactive,
 }
 
+impl From<ExecutableSource> for PuzzleSource {
+    fn from(executable: ExecutableSource) -> Self {
+        PuzzleSource::Executable(executable)
+    }
+}
+
+impl From<NetworkSource> for PuzzleSource {
+    fn from(network: NetworkSource) -> Self {
+        PuzzleSource::Network(network)
+    }
+}
+
+impl PuzzleSource {
+    /// Name given to the source in diagnostics, if it has a meaningful one.
+    pub fn name(&self) -> Option<String> {
+        match self {
+            PuzzleSource::File(path) | PuzzleSource::Directory(path) => {
+                Some(path.display().to_string())
+            }
+            // Not the program's path, the output of a program is no file to include paths from
+            PuzzleSource::Executable(executable) => {
+                Some(format!("output of {}", executable.path.display()))
+            }
+            PuzzleSource::Network(network) => Some(network.url.clone()),
+            PuzzleSource::String(_) | PuzzleSource::InlineCode(_) | PuzzleSource::Interactive => {
+                None
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::domains::create_registry;
@@ -205,8 +789,231 @@ mod test {
             create_registry().domain_registry(),
         );
         println!("{result:?}");
-        assert!(matches!(result, Err(ParseError::UnknownStepType { .. })));
+        assert!(matches!(
+            result.as_ref().map_err(ParseError::inner),
+            Err(ParseError::UnknownStepType { .. })
+        ));
     }
+    #[test]
+    fn test_invalid_step_reports_location() {
+        let content = "title: Steps\n\
+            part.a.name: A\n\
+            part.a.step_type: text_step\n\
+            part.a.input: a_b\n\
+            part.a.steps: 0__x____ | 1__y____ | z__w____\n";
+
+        let result = AlgorithmInstance::from_source_strict(
+            PuzzleSource::String(content.to_string()),
+            create_registry().domain_registry(),
+        );
+        let Err(ParseError::InvalidStep { index, span, .. }) = &result else {
+            panic!("expected an invalid step, got {result:?}");
+        };
+        assert_eq!(*index, 2);
+        assert_eq!((span.line, span.column), (5, 37));
+        let message = result.unwrap_err().to_string();
+        assert!(message.ends_with("^^^^^^^^"), "{message}");
+    }
+
+    #[test]
+    fn test_steps_are_parsed_lazily() {
+        let mut content = String::from("title: Large\n");
+        for part in 0..3 {
+            let steps: Vec<String> = (0..50).map(|i| format!("{i}__s{i}____")).collect();
+            content += &format!(
+                "part.p{part}.name: Part {part}\npart.p{part}.step_type: text_step\n\
+                 part.p{part}.input: a_b\npart.p{part}.steps: {}\n",
+                steps.join(" | ")
+            );
+        }
+        let registry = create_registry();
+        let source = || PuzzleSource::String(content.clone());
+        let lazy = AlgorithmInstance::from_source(source(), registry.domain_registry()).unwrap();
+        let strict =
+            AlgorithmInstance::from_source_strict(source(), registry.domain_registry()).unwrap();
+        assert!(lazy.parts.iter().all(|part| part.steps.get().is_none()));
+        assert!(strict.parts.iter().all(|part| part.steps.get().is_some()));
+
+        let part = lazy.get_part("p1").unwrap();
+        assert_eq!(part.steps().unwrap().len(), 50);
+        let parsed: Vec<bool> = lazy
+            .parts
+            .iter()
+            .map(|part| part.steps.get().is_some())
+            .collect();
+        assert_eq!(parsed, [false, true, false]);
+        assert_eq!(lazy, strict);
+    }
+
+    #[test]
+    fn test_escapes_and_configured_separators() {
+        let content = r"title: Separators
+            part.a.name: Escaped
+            part.a.step_type: text_step
+            part.a.input: a_b
+            part.a.steps: 0__a\|b\_c____ | 1__x____
+            part.b.name: Configured
+            part.b.step_type: text_step
+            part.b.input: a;b
+            part.b.config.step_separator: ,
+            part.b.config.field_separator: /
+            part.b.config.token_separator: ;
+            part.b.steps: 0/a|b_c// , 1/x//";
+
+        let instance = AlgorithmInstance::from_source(
+            PuzzleSource::String(content.to_string()),
+            create_registry().domain_registry(),
+        )
+        .unwrap();
+        for part in &instance.parts {
+            let steps: Vec<String> = part
+                .steps()
+                .unwrap()
+                .all()
+                .unwrap()
+                .iter()
+                .map(|s| s.to_str())
+                .collect();
+            assert_eq!(steps, [r"0__a\|b\_c____", "1__x____"]);
+        }
+        assert!(instance.to_puzzle_format().contains("0/a|b_c// , 1/x//"));
+    }
+
+    #[test]
+    fn test_step_groups() {
+        let content = r"title: Groups
+            part.a.name: A
+            part.a.step_type: text_step
+            part.a.input: a_b_c
+            part.a.steps: 0__x____ | [1__y____ | 2__z____] | 2*([0__a____ | 1__b____])";
+
+        let instance = AlgorithmInstance::from_source(
+            PuzzleSource::String(content.to_string()),
+            create_registry().domain_registry(),
+        )
+        .unwrap();
+        let steps = instance.parts[0].steps().unwrap();
+        assert_eq!(steps.len(), 4);
+        assert_eq!(steps.step_count(), 7);
+        let frames: Vec<_> = (0..=steps.len()).map(|i| steps.frame_start(i)).collect();
+        assert_eq!(frames, [0, 1, 3, 5, 7]);
+
+        let written = instance.to_puzzle_format();
+        assert!(written.contains("0__x____ | [1__y____ | 2__z____] | [0__a____ | 1__b____]"));
+        for written in [written, instance.to_json_format()] {
+            let reloaded = AlgorithmInstance::from_source(
+                PuzzleSource::String(written),
+                create_registry().domain_registry(),
+            )
+            .unwrap();
+            assert!(reloaded.parts == instance.parts);
+        }
+    }
+
+    #[test]
+    fn test_step_captions_round_trip() {
+        let content = r#"title: Captions
+            part.a.name: A
+            part.a.step_type: text_step
+            part.a.input: a_b_c
+            part.a.steps: 0__x____ @"say \"x\"" @"a note" | [1__y____ @"both" | 2__z____]"#;
+
+        let instance = AlgorithmInstance::from_source(
+            PuzzleSource::String(content.to_string()),
+            create_registry().domain_registry(),
+        )
+        .unwrap();
+        let steps = instance.parts[0].steps().unwrap();
+        assert_eq!(steps.caption(0).unwrap().caption, r#"say "x""#);
+        assert_eq!(steps.caption(1).unwrap().caption, "both");
+
+        let written = instance.to_puzzle_format();
+        assert!(written.contains(r#"0__x____ @"say \"x\"" @"a note" | [1__y____ @"both""#));
+        for written in [written, instance.to_json_format()] {
+            let reloaded = AlgorithmInstance::from_source(
+                PuzzleSource::String(written),
+                create_registry().domain_registry(),
+            )
+            .unwrap();
+            assert!(reloaded.parts == instance.parts);
+        }
+    }
+
+    #[test]
+    fn test_bookmarks_and_chapters() {
+        let content = r"title: Bookmarks
+            part.a.name: A
+            part.a.step_type: text_step
+            part.a.input: a_b_c
+            part.a.bookmarks.middle: 2
+            part.a.steps: 0__x____ | #loop | for i in 0..2: [1__{i}____ | 2__{i}____] | #answer found";
+
+        let instance = AlgorithmInstance::from_source(
+            PuzzleSource::String(content.to_string()),
+            create_registry().domain_registry(),
+        )
+        .unwrap();
+        let part = &instance.parts[0];
+        let bookmarks: Vec<(&str, usize)> = part
+            .bookmarks()
+            .unwrap()
+            .iter()
+            .map(|bookmark| (bookmark.name.as_str(), bookmark.step))
+            .collect();
+        assert_eq!(bookmarks, [("loop", 1), ("middle", 2), ("answer found", 3)]);
+        let chapters = part.chapters().unwrap();
+        let chapters: Vec<(Option<&str>, Range<usize>)> = chapters
+            .iter()
+            .map(|chapter| (chapter.name.as_deref(), chapter.steps.clone()))
+            .collect();
+        assert_eq!(
+            chapters,
+            [
+                (None, 0..1),
+                (Some("loop"), 1..2),
+                (Some("middle"), 2..3),
+                (Some("answer found"), 3..3)
+            ]
+        );
+
+        for written in [instance.to_puzzle_format(), instance.to_json_format()] {
+            let reloaded = AlgorithmInstance::from_source(
+                PuzzleSource::String(written),
+                create_registry().domain_registry(),
+            )
+            .unwrap();
+            assert!(reloaded.parts == instance.parts);
+        }
+
+        for (bookmarks, steps) in [
+            ("part.a.bookmarks.late: 3", "0__x____ | 1__y____"),
+            ("part.a.bookmarks.twice: 0", "#twice | 0__x____"),
+            ("part.a.bookmarks.nope: x", "0__x____"),
+            ("", "[0__x____ | #inside | 1__y____]"),
+        ] {
+            let content = format!(
+                "title: Bookmarks\n\
+                part.a.name: A\n\
+                part.a.step_type: text_step\n\
+                part.a.input: a_b\n\
+                {bookmarks}\n\
+                part.a.steps: {steps}\n"
+            );
+            let result = AlgorithmInstance::from_source(
+                PuzzleSource::String(content),
+                create_registry().domain_registry(),
+            )
+            .and_then(|instance| instance.parts[0].bookmarks().map(<[_]>::len));
+            assert!(
+                matches!(
+                    result.as_ref().map_err(ParseError::inner),
+                    Err(ParseError::InvalidFormat(_))
+                ),
+                "{bookmarks} {steps}: {result:?}"
+            );
+        }
+    }
+
     #[test]
     fn test_missing_puzzle_fields() {
         let content_list = vec![
@@ -251,7 +1058,10 @@ mod test {
         );
         println!("{result:?}");
         // TODO: we could also test for correct fmt
-        assert!(matches!(result, Err(ParseError::MissingPartField(_, _))));
+        assert!(matches!(
+            result.as_ref().map_err(ParseError::inner),
+            Err(ParseError::MissingPartField(_, _))
+        ));
     }
 
     #[test]
@@ -278,6 +1088,9 @@ mod test {
         );
         println!("{result:?}");
         // TODO: we could also test for correct fmt
-        assert!(matches!(result, Err(ParseError::InvalidPartStructure(_))));
+        assert!(matches!(
+            result.as_ref().map_err(ParseError::inner),
+            Err(ParseError::InvalidPartStructure(_))
+        ));
     }
 }
@@ -1,48 +1,438 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{fmt, str::FromStr, sync::Arc, time::Duration};
 
-use crate::core::input::processors::Field;
+use indexmap::IndexMap;
+
+use crate::{
+    core::{
+        input::processors::{Field, Leaf},
+        step::expand::ExpansionLimits,
+    },
+    error::ConfigError,
+};
 
 #[derive(Default, Debug, Clone)]
 pub struct Configuration {
-    current: HashMap<String, Field>,
+    current: IndexMap<String, Field>,
     parent: Option<Arc<Configuration>>,
+    /// Step list macros, the `macro.<name>` entries of a puzzle
+    macros: IndexMap<String, Leaf>,
+    /// Set by the loader, puzzles cannot raise them
+    expansion_limits: Option<ExpansionLimits>,
+}
+
+/// Configurations are equal when they hold the same entries, whatever they were loaded with.
+impl PartialEq for Configuration {
+    fn eq(&self, other: &Self) -> bool {
+        self.current == other.current && self.parent == other.parent && self.macros == other.macros
+    }
+}
+
+/// The level of a [`Configuration`] chain a value was found at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigLevel {
+    /// Set in a part's own `config`.
+    Part,
+    /// Set in the puzzle-wide `config`, the root of the chain.
+    Puzzle,
+}
+
+impl fmt::Display for ConfigLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigLevel::Part => write!(f, "part"),
+            ConfigLevel::Puzzle => write!(f, "puzzle"),
+        }
+    }
+}
+
+/// An RGBA color, written `#rgb`, `#rrggbb`, `#rrggbbaa` or as one of a few common names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b, a: 255 }
+    }
 }
+
+impl FromStr for Color {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let Some(hex) = value.strip_prefix('#') else {
+            return match value.to_ascii_lowercase().as_str() {
+                "black" => Ok(Color::rgb(0, 0, 0)),
+                "white" => Ok(Color::rgb(255, 255, 255)),
+                "red" => Ok(Color::rgb(255, 0, 0)),
+                "green" => Ok(Color::rgb(0, 128, 0)),
+                "blue" => Ok(Color::rgb(0, 0, 255)),
+                "yellow" => Ok(Color::rgb(255, 255, 0)),
+                "gray" | "grey" => Ok(Color::rgb(128, 128, 128)),
+                "transparent" => Ok(Color {
+                    a: 0,
+                    ..Color::rgb(0, 0, 0)
+                }),
+                _ => Err(()),
+            };
+        };
+        if !hex.is_ascii() {
+            return Err(());
+        }
+        let channel = |i: usize, width: usize| {
+            let digits = hex.get(i * width..(i + 1) * width).ok_or(())?;
+            let value = u8::from_str_radix(digits, 16).map_err(|_| ())?;
+            // `#abc` is `#aabbcc`
+            Ok(if width == 1 { value * 17 } else { value })
+        };
+        match hex.len() {
+            3 => Ok(Color::rgb(channel(0, 1)?, channel(1, 1)?, channel(2, 1)?)),
+            6 => Ok(Color::rgb(channel(0, 2)?, channel(1, 2)?, channel(2, 2)?)),
+            8 => Ok(Color {
+                a: channel(3, 2)?,
+                ..Color::rgb(channel(0, 2)?, channel(1, 2)?, channel(2, 2)?)
+            }),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)?;
+        if self.a != 255 {
+            write!(f, "{:02x}", self.a)?;
+        }
+        Ok(())
+    }
+}
+
 impl Configuration {
-    pub fn new(map: HashMap<String, Field>) -> Self {
+    pub fn new(map: IndexMap<String, Field>) -> Self {
         Self {
             current: map,
             parent: None,
+            macros: IndexMap::new(),
+            expansion_limits: None,
         }
     }
 
-    pub fn with_parent(map: HashMap<String, Field>, parent: Option<Arc<Configuration>>) -> Self {
+    pub fn with_parent(map: IndexMap<String, Field>, parent: Option<Arc<Configuration>>) -> Self {
         Self {
             current: map,
             parent,
+            macros: IndexMap::new(),
+            expansion_limits: None,
         }
     }
 
+    pub fn with_macros(mut self, macros: IndexMap<String, Leaf>) -> Self {
+        self.macros = macros;
+        self
+    }
+
+    /// The step list macro called `name`, looked up like [`get`](Self::get). See
+    /// [`expand_steps`](crate::core::step::expand::expand_steps).
+    pub fn get_macro(&self, name: &str) -> Option<&Leaf> {
+        self.macros
+            .get(name)
+            .or_else(|| self.parent.as_ref()?.get_macro(name))
+    }
+
+    pub fn with_expansion_limits(mut self, limits: ExpansionLimits) -> Self {
+        self.expansion_limits = Some(limits);
+        self
+    }
+
+    /// The limits step lists are expanded within, looked up like [`get`](Self::get).
+    pub fn expansion_limits(&self) -> ExpansionLimits {
+        self.expansion_limits
+            .or_else(|| Some(self.parent.as_ref()?.expansion_limits()))
+            .unwrap_or_default()
+    }
+
+    /// The macros defined at this level.
+    pub fn macros(&self) -> impl Iterator<Item = (&String, &Leaf)> {
+        self.macros.iter()
+    }
+
     pub fn get(&self, key: &str) -> Option<&Field> {
+        self.get_with_level(key).map(|(field, _)| field)
+    }
+
+    /// Looks a key up like [`get`](Self::get), along with the level it was found at.
+    pub fn get_with_level(&self, key: &str) -> Option<(&Field, ConfigLevel)> {
         // Check current level
         if let Some(value) = self.current.get(key) {
-            return Some(value);
+            return Some((value, self.level()));
         }
 
         // Recursively check parent
-        self.parent.as_ref().and_then(|p| p.get(key))
+        self.parent.as_ref().and_then(|p| p.get_with_level(key))
+    }
+
+    /// Value of a key holding a single value, looked up like [`get`](Self::get).
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(Field::as_leaf)
+    }
+
+    /// Any value implementing `FromStr`, such as integers of a given width.
+    pub fn get_parsed<T: FromStr>(&self, key: &str) -> Result<T, ConfigError> {
+        let expected = format!("a value of type {}", std::any::type_name::<T>());
+        self.convert(key, &expected, |value| value.parse().ok())
+    }
+
+    pub fn get_parsed_or<T: FromStr>(&self, key: &str, default: T) -> Result<T, ConfigError> {
+        or_default(self.get_parsed(key), default)
+    }
+
+    pub fn get_integer(&self, key: &str) -> Result<i64, ConfigError> {
+        self.convert(key, "an integer", |value| value.parse().ok())
+    }
+
+    pub fn get_integer_or(&self, key: &str, default: i64) -> Result<i64, ConfigError> {
+        or_default(self.get_integer(key), default)
+    }
+
+    pub fn get_float(&self, key: &str) -> Result<f64, ConfigError> {
+        self.convert(key, "a number", |value| value.parse().ok())
+    }
+
+    pub fn get_float_or(&self, key: &str, default: f64) -> Result<f64, ConfigError> {
+        or_default(self.get_float(key), default)
     }
 
-    // pub fn get_string(&self, key: &str) -> Option<String> {
-    //     self.get(key).and_then(|field| {
-    //         if let Field::Leaf(value) = field {
-    //             Some(value.clone())
-    //         } else {
-    //             None
-    //         }
-    //     })
-    // }
+    /// `true`/`false`, also accepting `yes`/`no` and `on`/`off`.
+    pub fn get_bool(&self, key: &str) -> Result<bool, ConfigError> {
+        self.convert(key, "true or false", parse_bool)
+    }
+
+    pub fn get_bool_or(&self, key: &str, default: bool) -> Result<bool, ConfigError> {
+        or_default(self.get_bool(key), default)
+    }
+
+    pub fn get_color(&self, key: &str) -> Result<Color, ConfigError> {
+        self.convert(key, "a color", |value| value.parse().ok())
+    }
+
+    pub fn get_color_or(&self, key: &str, default: Color) -> Result<Color, ConfigError> {
+        or_default(self.get_color(key), default)
+    }
+
+    /// A duration with its unit, as in `500ms`, `1.5s`, `2m` or `1h`. Bare numbers are
+    /// milliseconds.
+    pub fn get_duration(&self, key: &str) -> Result<Duration, ConfigError> {
+        self.convert(key, "a duration", parse_duration)
+    }
+
+    pub fn get_duration_or(&self, key: &str, default: Duration) -> Result<Duration, ConfigError> {
+        or_default(self.get_duration(key), default)
+    }
+
+    /// One of `variants`, compared case-insensitively.
+    pub fn get_enum<'a>(&self, key: &str, variants: &[&'a str]) -> Result<&'a str, ConfigError> {
+        let expected = format!("one of {}", variants.join(", "));
+        self.convert(key, &expected, |value| parse_enum(value, variants))
+    }
+
+    pub fn get_enum_or<'a>(
+        &self,
+        key: &str,
+        variants: &[&'a str],
+        default: &'a str,
+    ) -> Result<&'a str, ConfigError> {
+        or_default(self.get_enum(key, variants), default)
+    }
+
+    /// A list, written either as a list field or as a single comma-separated value.
+    pub fn get_list(&self, key: &str) -> Result<Vec<String>, ConfigError> {
+        let (field, level) = self
+            .get_with_level(key)
+            .ok_or_else(|| ConfigError::missing(key))?;
+        let invalid = || ConfigError::invalid(key, level, "a list", field);
+        match field {
+            Field::Leaf(leaf) => Ok(leaf
+                .value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect()),
+            Field::List(items) => items
+                .iter()
+                .map(|item| item.as_leaf().map(|value| value.trim().to_string()))
+                .collect::<Option<_>>()
+                .ok_or_else(invalid),
+            Field::Node(_) => Err(invalid()),
+        }
+    }
+
+    pub fn get_list_or(&self, key: &str, default: Vec<String>) -> Result<Vec<String>, ConfigError> {
+        or_default(self.get_list(key), default)
+    }
+
+    /// Every key in effect, with the value and level it resolves to. Keys overridden by a lower
+    /// level only appear once, with their overriding value.
+    pub fn effective_entries(&self) -> impl Iterator<Item = (&String, &Field, ConfigLevel)> {
+        let mut merged: IndexMap<&String, (&Field, ConfigLevel)> = IndexMap::new();
+        let mut level = Some(self);
+        while let Some(configuration) = level {
+            for (key, field) in &configuration.current {
+                merged.entry(key).or_insert((field, configuration.level()));
+            }
+            level = configuration.parent.as_deref();
+        }
+        merged
+            .into_iter()
+            .map(|(key, (field, level))| (key, field, level))
+    }
+
+    /// Entries set at this level, without the inherited ones
+    pub fn entries(&self) -> impl Iterator<Item = (&String, &Field)> {
+        self.current.iter()
+    }
+
+    pub fn parent(&self) -> Option<&Arc<Configuration>> {
+        self.parent.as_ref()
+    }
 
     pub fn insert(&mut self, key: String, value: Field) {
         self.current.insert(key, value);
     }
+
+    fn level(&self) -> ConfigLevel {
+        match self.parent {
+            Some(_) => ConfigLevel::Part,
+            None => ConfigLevel::Puzzle,
+        }
+    }
+
+    fn convert<T>(
+        &self,
+        key: &str,
+        expected: &str,
+        convert: impl FnOnce(&str) -> Option<T>,
+    ) -> Result<T, ConfigError> {
+        let (field, level) = self
+            .get_with_level(key)
+            .ok_or_else(|| ConfigError::missing(key))?;
+        field
+            .as_leaf()
+            .and_then(|value| convert(value.trim()))
+            .ok_or_else(|| ConfigError::invalid(key, level, expected, field))
+    }
+}
+
+/// Falls back to `default` when the key is missing, but not when its value is invalid.
+fn or_default<T>(result: Result<T, ConfigError>, default: T) -> Result<T, ConfigError> {
+    match result {
+        Err(error) if error.is_missing() => Ok(default),
+        result => result,
+    }
+}
+
+// The parsers below are shared with the configuration schema, so that an entry the schema
+// accepts is one its getter reads.
+
+pub(crate) fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" => Some(true),
+        "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+pub(crate) fn parse_enum<'a>(value: &str, variants: &[&'a str]) -> Option<&'a str> {
+    variants
+        .iter()
+        .find(|variant| variant.eq_ignore_ascii_case(value))
+        .copied()
+}
+
+pub(crate) fn parse_duration(value: &str) -> Option<Duration> {
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: f64 = amount.parse().ok()?;
+    let seconds = match unit.trim() {
+        "" | "ms" => amount / 1000.0,
+        "s" => amount,
+        "m" | "min" => amount * 60.0,
+        "h" => amount * 3600.0,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn configuration() -> Configuration {
+        let puzzle = Configuration::new(IndexMap::from([
+            ("rows".to_string(), Field::leaf("4")),
+            ("speed".to_string(), Field::leaf("fast")),
+            ("delay".to_string(), Field::leaf("1.5s")),
+        ]));
+        Configuration::with_parent(
+            IndexMap::from([
+                ("rows".to_string(), Field::leaf("two")),
+                ("highlight".to_string(), Field::leaf("#f80")),
+                ("labels".to_string(), Field::leaf("a, b,c")),
+                ("wrap".to_string(), Field::leaf("yes")),
+            ]),
+            Some(Arc::new(puzzle)),
+        )
+    }
+
+    #[test]
+    fn test_typed_getters() {
+        let configuration = configuration();
+        assert_eq!(
+            configuration.get_color("highlight").unwrap(),
+            Color::rgb(255, 136, 0)
+        );
+        assert_eq!(configuration.get_list("labels").unwrap(), ["a", "b", "c"]);
+        assert!(configuration.get_bool("wrap").unwrap());
+        assert_eq!(
+            configuration.get_duration("delay").unwrap(),
+            Duration::from_millis(1500)
+        );
+        assert_eq!(
+            configuration.get_enum("speed", &["slow", "Fast"]).unwrap(),
+            "Fast"
+        );
+        assert_eq!(configuration.get_integer_or("columns", 3).unwrap(), 3);
+        assert_eq!(configuration.get_float_or("scale", 0.5).unwrap(), 0.5);
+
+        let error = configuration.get_integer_or("rows", 3).unwrap_err();
+        assert_eq!(error.key, "rows");
+        assert_eq!(error.level, Some(ConfigLevel::Part));
+        let error = configuration.get_parsed::<u8>("speed").unwrap_err();
+        assert_eq!(error.level, Some(ConfigLevel::Puzzle));
+        assert!(
+            configuration
+                .get_integer("columns")
+                .unwrap_err()
+                .is_missing()
+        );
+    }
+
+    #[test]
+    fn test_effective_entries() {
+        let configuration = configuration();
+        let entries: Vec<(&str, &str, ConfigLevel)> = configuration
+            .effective_entries()
+            .map(|(key, field, level)| (key.as_str(), field.as_leaf().unwrap(), level))
+            .collect();
+        assert_eq!(entries.len(), 6);
+        assert_eq!(entries[0], ("rows", "two", ConfigLevel::Part));
+        assert_eq!(entries[4], ("speed", "fast", ConfigLevel::Puzzle));
+    }
 }
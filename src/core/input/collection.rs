@@ -0,0 +1,373 @@
+use std::{
+    cmp::Ordering,
+    fs,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    algorithm::PuzzleSource,
+    core::input::{
+        PuzzleMetadata,
+        format::FormatRegistry,
+        get_metadata_from_source_with_formats,
+        processors::{Field, parse_puzzle_tree, resolve_relative},
+    },
+    engine::{VisualizationEngine, registry::Registry},
+    error::ParseError,
+};
+
+// ============================================================================
+// PUZZLE COLLECTIONS
+// ============================================================================
+
+/// Name of the index file listing the puzzles of a directory.
+pub const INDEX_FILE: &str = "index.txt";
+
+/// A library of puzzles, such as a directory per year with a puzzle file per day. Only the
+/// metadata of each puzzle is read, steps are left for when an entry is opened.
+///
+/// A directory lists its puzzles in an [`INDEX_FILE`] written in the key-path format:
+///
+/// ```text
+/// title: Advent 2024
+/// puzzle.day01: day01.txt
+/// puzzle.day02: day02/puzzle.json
+/// ```
+///
+/// Paths are relative to the index, and entries keep the order of the index. Without an index,
+/// every file of the directory is an entry named after the file, in the order of file names.
+#[derive(Debug, Default)]
+pub struct PuzzleCollection {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub entries: Vec<CollectionEntry>,
+    /// Files whose metadata could not be read, left out of `entries`
+    pub skipped: Vec<(PathBuf, ParseError)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CollectionEntry {
+    pub id: String,
+    pub path: PathBuf,
+    pub metadata: PuzzleMetadata,
+}
+
+/// Which entries of a collection to keep. Unset criteria keep every entry.
+#[derive(Debug, Clone, Default)]
+pub struct CollectionFilter {
+    /// Text the title must contain, ignoring case
+    pub title: Option<String>,
+    /// Text the author must contain, ignoring case
+    pub author: Option<String>,
+    /// Entries without a difficulty are left out when set
+    pub difficulty: Option<RangeInclusive<u8>>,
+    /// Tags every entry must have, ignoring case
+    pub tags: Vec<String>,
+}
+
+/// How to order the entries of a collection. Entries missing the value come last, and ties keep
+/// the order of the collection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Title,
+    Author,
+    Difficulty,
+    /// By the sorted list of tags, bringing together the puzzles sharing their first tags
+    Tags,
+}
+
+impl PuzzleCollection {
+    /// Reads a [`PuzzleSource::Directory`], or a [`PuzzleSource::File`] being an index.
+    pub fn from_source(source: PuzzleSource) -> Result<Self, ParseError> {
+        Self::from_source_with_formats(source, &FormatRegistry::default())
+    }
+
+    /// Same as [`from_source`](Self::from_source), reading puzzles written in one of `formats`.
+    pub fn from_source_with_formats(
+        source: PuzzleSource,
+        formats: &FormatRegistry,
+    ) -> Result<Self, ParseError> {
+        match source {
+            PuzzleSource::Directory(dir) => {
+                let index = dir.join(INDEX_FILE);
+                if index.is_file() {
+                    return Self::from_index(&index, formats);
+                }
+                Self::from_files(&dir, formats)
+            }
+            PuzzleSource::File(index) => Self::from_index(&index, formats),
+            source => Err(ParseError::InvalidFormat(format!(
+                "a collection is read from a directory or an index file, not from {}",
+                source.name().unwrap_or_else(|| "this source".to_string())
+            ))),
+        }
+    }
+
+    fn from_index(index: &Path, formats: &FormatRegistry) -> Result<Self, ParseError> {
+        let content = fs::read_to_string(index)?;
+        let root = parse_puzzle_tree(&content, Some(index.display().to_string()))?;
+        let leaf = |key| root.get_path(&[key]).and_then(Field::as_leaf);
+        let mut collection = PuzzleCollection {
+            title: leaf("title").map(str::to_string),
+            description: leaf("description").map(str::to_string),
+            ..Default::default()
+        };
+        let Some(Field::Node(puzzles)) = root.get_path(&["puzzle"]) else {
+            return Ok(collection);
+        };
+        for (id, field) in puzzles {
+            let Field::Leaf(leaf) = field else {
+                let span = field.first_key_span().cloned().unwrap_or_default();
+                return Err(ParseError::InvalidFormat(format!(
+                    "puzzle '{id}' should be a file path"
+                ))
+                .at(span));
+            };
+            let path = resolve_relative(leaf.span.source.as_deref(), leaf.value.trim())
+                .map_err(|error| error.at(leaf.span.clone()))?;
+            collection.add(id.clone(), path, formats);
+        }
+        Ok(collection)
+    }
+
+    fn from_files(dir: &Path, formats: &FormatRegistry) -> Result<Self, ParseError> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if !hidden && entry.file_type()?.is_file() {
+                paths.push(entry.path());
+            }
+        }
+        paths.sort();
+        let mut collection = PuzzleCollection::default();
+        for path in paths {
+            let id = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            collection.add(id, path, formats);
+        }
+        Ok(collection)
+    }
+
+    fn add(&mut self, id: String, path: PathBuf, formats: &FormatRegistry) {
+        let source = PuzzleSource::File(path.clone());
+        match get_metadata_from_source_with_formats(source, formats) {
+            Ok(metadata) => self.entries.push(CollectionEntry { id, path, metadata }),
+            Err(error) => self.skipped.push((path, error)),
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&CollectionEntry> {
+        self.entries.iter().find(|entry| entry.id == id)
+    }
+
+    /// The entries kept by `filter`, ordered by `sort` or else in the order of the collection.
+    pub fn query(&self, filter: &CollectionFilter, sort: Option<SortKey>) -> Vec<&CollectionEntry> {
+        let mut entries: Vec<&CollectionEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| filter.matches(&entry.metadata))
+            .collect();
+        if let Some(key) = sort {
+            entries.sort_by(|a, b| key.compare(&a.metadata, &b.metadata));
+        }
+        entries
+    }
+}
+
+impl CollectionEntry {
+    /// Loads the puzzle into an engine with the built-in domains.
+    pub fn open(&self) -> Result<VisualizationEngine, ParseError> {
+        VisualizationEngine::from_source(PuzzleSource::File(self.path.clone()))
+    }
+
+    /// Same as [`open`](Self::open), with the domains and renderers of `registry`.
+    pub fn open_with_registry(
+        &self,
+        registry: Registry,
+    ) -> Result<VisualizationEngine, ParseError> {
+        VisualizationEngine::from_source_with_registry(
+            PuzzleSource::File(self.path.clone()),
+            registry,
+        )
+    }
+}
+
+impl CollectionFilter {
+    pub fn matches(&self, metadata: &PuzzleMetadata) -> bool {
+        let contains = |value: Option<&str>, wanted: &Option<String>| match wanted {
+            Some(wanted) => {
+                value.is_some_and(|value| value.to_lowercase().contains(&wanted.to_lowercase()))
+            }
+            None => true,
+        };
+        let tags = metadata.tags();
+        contains(Some(&metadata.title), &self.title)
+            && contains(metadata.author.as_deref(), &self.author)
+            && self.difficulty.as_ref().is_none_or(|range| {
+                metadata
+                    .difficulty
+                    .is_some_and(|difficulty| range.contains(&difficulty))
+            })
+            && self
+                .tags
+                .iter()
+                .all(|wanted| tags.iter().any(|tag| tag.eq_ignore_ascii_case(wanted)))
+    }
+}
+
+impl SortKey {
+    fn compare(self, a: &PuzzleMetadata, b: &PuzzleMetadata) -> Ordering {
+        match self {
+            SortKey::Title => a.title.to_lowercase().cmp(&b.title.to_lowercase()),
+            SortKey::Author => missing_last(
+                a.author.as_ref().map(|author| author.to_lowercase()),
+                b.author.as_ref().map(|author| author.to_lowercase()),
+            ),
+            SortKey::Difficulty => missing_last(a.difficulty, b.difficulty),
+            SortKey::Tags => {
+                let tags = |metadata: &PuzzleMetadata| {
+                    let mut tags = metadata.tags();
+                    tags.sort();
+                    Some(tags).filter(|tags| !tags.is_empty())
+                };
+                missing_last(tags(a), tags(b))
+            }
+        }
+    }
+}
+
+fn missing_last<T: Ord>(a: Option<T>, b: Option<T>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn puzzle(title: &str, extra: &str) -> String {
+        format!(
+            "title: {title}\n{extra}\n\
+             part.a.name: A\n\
+             part.a.step_type: text\n\
+             part.a.input: a b\n\
+             part.a.steps: 0__x____\n"
+        )
+    }
+
+    fn library() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let files = [
+            (
+                "day01.txt",
+                puzzle(
+                    "Sonar Sweep",
+                    "author: Eric\ndifficulty: 1\nmeta.tags: easy, counting",
+                ),
+            ),
+            (
+                "day02.txt",
+                puzzle("Dive", "difficulty: 3\nmeta.tags: parsing"),
+            ),
+            (
+                "day03.txt",
+                puzzle(
+                    "binary diagnostic",
+                    "author: ada\nmeta.tags: counting, bits",
+                ),
+            ),
+            ("broken.txt", "title: Broken\npart.a.name: A\n".to_string()),
+            (".hidden", puzzle("Hidden", "")),
+        ];
+        for (name, content) in files {
+            fs::write(dir.path().join(name), content).unwrap();
+        }
+        fs::create_dir(dir.path().join("nested")).unwrap();
+        dir
+    }
+
+    fn ids(entries: Vec<&CollectionEntry>) -> Vec<&str> {
+        entries.into_iter().map(|entry| entry.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_collection_from_directory() {
+        let dir = library();
+        let collection =
+            PuzzleCollection::from_source(PuzzleSource::Directory(dir.path().to_path_buf()))
+                .unwrap();
+        assert_eq!(collection.title, None);
+        assert_eq!(
+            ids(collection.entries.iter().collect()),
+            ["day01", "day02", "day03"]
+        );
+        assert_eq!(collection.skipped.len(), 1);
+        assert!(collection.skipped[0].0.ends_with("broken.txt"));
+        let entry = collection.get("day02").unwrap();
+        assert_eq!(entry.metadata.title, "Dive");
+        assert_eq!(entry.metadata.parts_info[0].step_count, 1);
+
+        let all = CollectionFilter::default();
+        let sorted = |key| ids(collection.query(&all, Some(key)));
+        assert_eq!(sorted(SortKey::Title), ["day03", "day02", "day01"]);
+        assert_eq!(sorted(SortKey::Author), ["day03", "day01", "day02"]);
+        assert_eq!(sorted(SortKey::Difficulty), ["day01", "day02", "day03"]);
+        assert_eq!(sorted(SortKey::Tags), ["day03", "day01", "day02"]);
+
+        let query = |filter: CollectionFilter| ids(collection.query(&filter, None));
+        let filter = CollectionFilter {
+            tags: vec!["Counting".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(query(filter.clone()), ["day01", "day03"]);
+        let filter = CollectionFilter {
+            author: Some("ERIC".to_string()),
+            ..filter
+        };
+        assert_eq!(query(filter), ["day01"]);
+        let filter = CollectionFilter {
+            difficulty: Some(2..=5),
+            ..Default::default()
+        };
+        assert_eq!(query(filter), ["day02"]);
+        let filter = CollectionFilter {
+            title: Some("dIv".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(query(filter), ["day02"]);
+    }
+
+    #[test]
+    fn test_collection_from_index() {
+        let dir = library();
+        fs::write(
+            dir.path().join(INDEX_FILE),
+            "title: Advent\n\
+             puzzle.third: day03.txt\n\
+             puzzle.first: day01.txt\n\
+             puzzle.gone: missing.txt\n",
+        )
+        .unwrap();
+        let directory = PuzzleSource::Directory(dir.path().to_path_buf());
+        let collection = PuzzleCollection::from_source(directory).unwrap();
+        assert_eq!(collection.title.as_deref(), Some("Advent"));
+        assert_eq!(ids(collection.entries.iter().collect()), ["third", "first"]);
+        assert_eq!(collection.entries[0].metadata.title, "binary diagnostic");
+        assert!(
+            matches!(collection.skipped[..], [(ref path, ParseError::IoError(_))] if path.ends_with("missing.txt"))
+        );
+
+        let index = PuzzleSource::File(dir.path().join(INDEX_FILE));
+        let from_index = PuzzleCollection::from_source(index).unwrap();
+        assert_eq!(from_index.entries.len(), 2);
+
+        let other = PuzzleCollection::from_source(PuzzleSource::String(String::new()));
+        assert!(other.is_err());
+    }
+}
@@ -0,0 +1,486 @@
+use std::{collections::HashSet, fmt, sync::Arc};
+
+use crate::{
+    algorithm::{PuzzleSource, RawPartMetadata, parse_part_info},
+    core::{
+        input::{
+            processors::{
+                Field, Leaf, PART_KEYS, TOP_LEVEL_KEYS, check_input_sources, extract_config,
+                extract_macros, extract_part_order, extract_single_part_with_steps,
+            },
+            read_source_bytes,
+            references::resolve_references,
+            span::Span,
+        },
+        split::Separators,
+        state::schema::PartConfigProblems,
+    },
+    engine::registry::Registry,
+    error::ParseError,
+};
+
+// ============================================================================
+// DIAGNOSTICS
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug)]
+pub enum DiagnosticKind {
+    /// The puzzle cannot be loaded because of this error.
+    Error(ParseError),
+    /// A key at the root of the puzzle that nothing reads.
+    UnknownKey(String),
+    /// A key under `part.<id>` that nothing reads.
+    UnknownPartField { part: String, field: String },
+    /// A `config` entry that none of the states a part can use declares.
+    UnusedConfig { key: String, part: Option<String> },
+}
+
+/// A single problem found while checking a puzzle.
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub span: Option<Span>,
+    /// A close match for a misspelled key or step type.
+    pub suggestion: Option<String>,
+}
+
+impl Diagnostic {
+    pub(crate) fn error(error: ParseError) -> Self {
+        Self {
+            span: error.span().cloned(),
+            kind: DiagnosticKind::Error(error),
+            suggestion: None,
+        }
+    }
+
+    pub(crate) fn warning(
+        kind: DiagnosticKind,
+        span: &Span,
+        candidates: &[&str],
+        typo: &str,
+    ) -> Self {
+        Self {
+            kind,
+            span: Some(span.clone()),
+            suggestion: suggest(typo, candidates.iter().copied()),
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self.kind {
+            DiagnosticKind::Error(_) => Severity::Error,
+            _ => Severity::Warning,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            DiagnosticKind::Error(error) => write!(f, "error: {error}")?,
+            kind => {
+                match kind {
+                    DiagnosticKind::UnknownKey(key) => write!(f, "warning: unknown key '{key}'")?,
+                    DiagnosticKind::UnknownPartField { part, field } => {
+                        write!(f, "warning: unknown field '{field}' in part {part}")?
+                    }
+                    DiagnosticKind::UnusedConfig {
+                        key,
+                        part: Some(part),
+                    } => write!(
+                        f,
+                        "warning: configuration entry '{key}' of part {part} is never used"
+                    )?,
+                    DiagnosticKind::UnusedConfig { key, part: None } => {
+                        write!(f, "warning: configuration entry '{key}' is never used")?
+                    }
+                    DiagnosticKind::Error(_) => unreachable!(),
+                }
+                if let Some(snippet) = self.span.as_ref().and_then(Span::snippet) {
+                    write!(f, "\n{snippet}")?;
+                }
+            }
+        }
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, "\n  = help: did you mean '{suggestion}'?")?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks a whole puzzle, going through every part and every step instead of stopping at the
+/// first error. The puzzle loads if none of the returned diagnostics is an error.
+pub fn diagnose_source(source: PuzzleSource, registry: &Registry) -> Vec<Diagnostic> {
+    let name = source.name();
+    let content = match read_source_bytes(source) {
+        Ok(content) => content,
+        Err(error) => return vec![Diagnostic::error(error)],
+    };
+    let (format, content) = match registry.format_registry().detect(name.as_deref(), &content) {
+        Ok(detected) => detected,
+        Err(error) => return vec![Diagnostic::error(error)],
+    };
+    match format.parse_tree(&content, name.clone()) {
+        Some(Ok(root)) => diagnose_tree(&root, registry),
+        Some(Err(error)) => vec![Diagnostic::error(error)],
+        // Without a tree, only the parts the format reads can be checked
+        None => match format.parse(&content, name) {
+            Ok((_, parts)) => {
+                let mut diagnostics = Vec::new();
+                for part in &parts {
+                    check_steps(part, registry, &mut diagnostics);
+                }
+                diagnostics
+            }
+            Err(error) => vec![Diagnostic::error(error)],
+        },
+    }
+}
+
+pub(crate) fn diagnose_tree(root: &Field, registry: &Registry) -> Vec<Diagnostic> {
+    let (root, errors) = resolve_references(root);
+    let root = &root;
+    let mut diagnostics: Vec<Diagnostic> = errors.into_iter().map(Diagnostic::error).collect();
+    let Field::Node(root_map) = root else {
+        return diagnostics;
+    };
+    check_keys(root_map.iter(), TOP_LEVEL_KEYS, &mut diagnostics, |key| {
+        DiagnosticKind::UnknownKey(key.to_string())
+    });
+
+    // Built like the configuration of a loaded puzzle, so that steps can call its macros
+    let macros = extract_macros(root).unwrap_or_else(|error| {
+        diagnostics.push(Diagnostic::error(error));
+        Default::default()
+    });
+    let root_config = Arc::new(extract_config(root, None).with_macros(macros));
+    // Configuration keys read by the states of at least one part
+    let mut used_by_any_part = HashSet::new();
+    // The `input_from` of every part, checked once they are all known
+    let mut input_sources: Vec<(String, Option<Leaf>)> = Vec::new();
+
+    if let Some(Field::Node(part_map)) = root.get_path(&["part"]) {
+        for (part_id, part_field) in part_map {
+            if let Field::Node(fields) = part_field {
+                check_keys(fields.iter(), PART_KEYS, &mut diagnostics, |field| {
+                    DiagnosticKind::UnknownPartField {
+                        part: part_id.clone(),
+                        field: field.to_string(),
+                    }
+                });
+            }
+            if let Err(error) = extract_part_order(part_field) {
+                diagnostics.push(Diagnostic::error(error));
+            }
+            let part = match extract_single_part_with_steps(part_id, part_field, &root_config) {
+                Ok(part) => part,
+                Err(error) => {
+                    diagnostics.push(Diagnostic::error(error));
+                    continue;
+                }
+            };
+            input_sources.push((part.id.clone(), part.input_from.clone()));
+            let found = diagnostics.len();
+            let Some(step_type_id) = check_steps(&part, registry, &mut diagnostics) else {
+                continue;
+            };
+            let steps_parse = diagnostics.len() == found;
+
+            let states = registry.state_registry().get(step_type_id);
+            if let Some(state) = states.into_iter().flatten().find(|state| state.is_default) {
+                let problems = state.validate(&part.configuration);
+                if !problems.is_empty() {
+                    let span = problems.iter().find_map(|problem| problem.span()).cloned();
+                    let error = ParseError::InvalidConfiguration(vec![PartConfigProblems {
+                        part: part_id.clone(),
+                        state: state.type_id,
+                        problems,
+                    }]);
+                    diagnostics.push(Diagnostic::error(match span {
+                        Some(span) => error.at(span),
+                        None => error,
+                    }));
+                }
+            }
+            let used: HashSet<&str> = states
+                .into_iter()
+                .flatten()
+                .flat_map(|state| {
+                    let described = state.config_schema.iter().map(|field| field.name);
+                    state
+                        .required_config_fields
+                        .iter()
+                        .copied()
+                        .chain(described)
+                })
+                .chain(SEPARATOR_KEYS)
+                .collect();
+            if let Some(Field::Node(config)) = part_field.get_path(&["config"]) {
+                for (key, field) in config.iter().filter(|(k, _)| !used.contains(k.as_str())) {
+                    diagnostics.push(Diagnostic {
+                        kind: DiagnosticKind::UnusedConfig {
+                            key: key.clone(),
+                            part: Some(part_id.clone()),
+                        },
+                        span: field.first_key_span().cloned(),
+                        suggestion: suggest(key, used.iter().copied()),
+                    });
+                }
+            }
+            used_by_any_part.extend(used);
+            if steps_parse {
+                check_bookmarks(part, registry, &mut diagnostics);
+            }
+        }
+    }
+
+    let sources: Vec<(&str, Option<&Leaf>)> = input_sources
+        .iter()
+        .map(|(id, source)| (id.as_str(), source.as_ref()))
+        .collect();
+    if let Err(error) = check_input_sources(&sources) {
+        diagnostics.push(Diagnostic::error(error));
+    }
+
+    if let Some(Field::Node(config)) = root.get_path(&["config"]) {
+        for (key, field) in config
+            .iter()
+            .filter(|(k, _)| !used_by_any_part.contains(k.as_str()))
+        {
+            diagnostics.push(Diagnostic {
+                kind: DiagnosticKind::UnusedConfig {
+                    key: key.clone(),
+                    part: None,
+                },
+                span: field.first_key_span().cloned(),
+                suggestion: suggest(key, used_by_any_part.iter().copied()),
+            });
+        }
+    }
+
+    diagnostics.sort_by_key(|d| d.span.as_ref().map(|span| span.start));
+    diagnostics
+}
+
+/// Checks the step type and every step of a part, returning the step type if it is known.
+fn check_steps(
+    part: &RawPartMetadata,
+    registry: &Registry,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Option<&'static str> {
+    let domain = registry.domain_registry();
+    let step_type_id = match domain.step_type_to_id(&part.raw_step_type_id) {
+        Ok(id) => id,
+        Err(error) => {
+            let supported = domain.get_supported_types();
+            let mut diagnostic = Diagnostic::error(error.at(part.step_type_span.clone()));
+            diagnostic.suggestion =
+                suggest(&part.raw_step_type_id, supported.iter().map(String::as_str));
+            diagnostics.push(diagnostic);
+            return None;
+        }
+    };
+    diagnostics.extend(
+        domain
+            .check_steps(step_type_id, &part.raw_steps, &part.configuration)
+            .into_iter()
+            .map(Diagnostic::error),
+    );
+    Some(step_type_id)
+}
+
+/// Checks the bookmarks of a part whose steps parse, which must be within the steps and not share
+/// a name, as they are checked when the part is loaded.
+fn check_bookmarks(part: RawPartMetadata, registry: &Registry, diagnostics: &mut Vec<Diagnostic>) {
+    let bookmarks = parse_part_info(part, registry.domain_registry())
+        .and_then(|part| part.bookmarks().map(|_| ()));
+    if let Err(error) = bookmarks {
+        diagnostics.push(Diagnostic::error(error));
+    }
+}
+
+/// Configuration keys read by the step list itself, whatever the state.
+const SEPARATOR_KEYS: [&str; 3] = [
+    Separators::STEP_KEY,
+    Separators::FIELD_KEY,
+    Separators::TOKEN_KEY,
+];
+
+fn check_keys<'a>(
+    fields: impl Iterator<Item = (&'a String, &'a Field)>,
+    known: &[&str],
+    diagnostics: &mut Vec<Diagnostic>,
+    kind: impl Fn(&str) -> DiagnosticKind,
+) {
+    for (key, field) in fields.filter(|(key, _)| !known.contains(&key.as_str())) {
+        if let Some(span) = field.first_key_span() {
+            diagnostics.push(Diagnostic::warning(kind(key), span, known, key));
+        }
+    }
+}
+
+/// Returns the candidate closest to `typo`, if it is close enough to be a plausible misspelling.
+pub(crate) fn suggest<'a>(typo: &str, candidates: impl Iterator<Item = &'a str>) -> Option<String> {
+    let max_distance = typo.chars().count().div_ceil(3);
+    candidates
+        .map(|candidate| (edit_distance(typo, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+/// Levenshtein distance between two strings, counted in chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::domains::create_registry;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("step_typ", "step_type"), 1);
+        assert_eq!(edit_distance("titel", "title"), 2);
+        assert_eq!(
+            suggest("step_typ", PART_KEYS.iter().copied()).as_deref(),
+            Some("step_type")
+        );
+        assert_eq!(suggest("zzz", PART_KEYS.iter().copied()), None);
+    }
+
+    #[test]
+    fn test_macros_are_known() {
+        let content = "\
+title: Macros
+macro.twice: 0__a____ | 1__b____
+part.a.name: A
+part.a.step_type: text
+part.a.input: hello_world
+part.a.steps: twice! | 0__c____
+";
+        let diagnostics = diagnose_source(
+            PuzzleSource::String(content.to_string()),
+            &create_registry(),
+        );
+        assert!(
+            diagnostics.iter().all(|d| d.severity() != Severity::Error),
+            "{diagnostics:?}"
+        );
+    }
+
+    #[test]
+    fn test_part_order_is_checked() {
+        let content = "\
+title: Order
+part.a.name: A
+part.a.step_type: text
+part.a.input: hello
+part.a.steps: 0__c____
+part.a.order: first
+";
+        let diagnostics = diagnose_source(
+            PuzzleSource::String(content.to_string()),
+            &create_registry(),
+        );
+        assert_eq!(diagnostics.len(), 1, "{diagnostics:?}");
+        let message = diagnostics[0].to_string();
+        assert!(
+            message.contains("part order must be an integer"),
+            "{message}"
+        );
+        assert_eq!(diagnostics[0].span.as_ref().unwrap().line, 6);
+    }
+
+    #[test]
+    fn test_bookmarks_are_checked() {
+        let diagnose = |bookmarks: &str| {
+            let content = format!(
+                "title: Bookmarks\n\
+                 part.a.name: A\n\
+                 part.a.step_type: text\n\
+                 part.a.input: hello\n\
+                 part.a.steps: #start | 0__c____ | 0__d____\n\
+                 {bookmarks}"
+            );
+            diagnose_source(PuzzleSource::String(content), &create_registry())
+        };
+        assert!(diagnose("part.a.bookmarks.end: 2\n").is_empty());
+
+        let diagnostics = diagnose("part.a.bookmarks.end: 3\n");
+        assert_eq!(diagnostics.len(), 1, "{diagnostics:?}");
+        assert!(diagnostics[0].to_string().contains("past the 2 steps"));
+        assert_eq!(diagnostics[0].span.as_ref().unwrap().line, 6);
+
+        let diagnostics = diagnose("part.a.bookmarks.start: 1\n");
+        assert_eq!(diagnostics.len(), 1, "{diagnostics:?}");
+        assert!(
+            diagnostics[0]
+                .to_string()
+                .contains("duplicate bookmark 'start'")
+        );
+    }
+
+    #[test]
+    fn test_collects_every_problem() {
+        let content = r#"
+        titel: Test Puzzle
+        config.unused_everywhere: 1
+        part.a.name: A
+        part.a.step_typ: text_step
+        part.a.input: hello
+        part.a.steps: 0__a____
+        part.b.name: B
+        part.b.step_type: text_stpe
+        part.b.input: hello
+        part.b.steps: 0__a____
+        part.c.name: C
+        part.c.step_type: text_step
+        part.c.input: hello
+        part.c.steps: x__a____ | 0__a____ | y__b____
+        part.c.config.colour: red
+    "#;
+        let diagnostics = diagnose_source(
+            PuzzleSource::String(content.to_string()),
+            &create_registry(),
+        );
+        let errors = diagnostics
+            .iter()
+            .filter(|d| d.severity() == Severity::Error)
+            .count();
+        assert_eq!(errors, 4);
+
+        let suggestions: Vec<_> = diagnostics
+            .iter()
+            .filter_map(|d| d.suggestion.as_deref())
+            .collect();
+        assert!(suggestions.contains(&"title"));
+        assert!(suggestions.contains(&"step_type"));
+        assert!(suggestions.contains(&"text_step"));
+        assert!(diagnostics.iter().any(|d| matches!(
+            &d.kind,
+            DiagnosticKind::UnusedConfig { key, part: Some(_) } if key == "colour"
+        )));
+        assert!(diagnostics.iter().any(|d| matches!(
+            &d.kind,
+            DiagnosticKind::UnusedConfig { key, part: None } if key == "unused_everywhere"
+        )));
+    }
+}
@@ -0,0 +1,430 @@
+use std::{
+    collections::VecDeque,
+    io::{Read, Write},
+    path::PathBuf,
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::error::{ExecutableError, ExecutableErrorKind, ParseError};
+
+// ============================================================================
+// EXECUTABLE SOURCES
+// ============================================================================
+
+/// Bytes of stderr kept for errors, the end of it being what usually explains a failure.
+pub const STDERR_TAIL: usize = 2048;
+/// How often a running program is checked for its exit, timeout or output limit.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+/// How long the output of a killed program is waited for, processes it started may hold its
+/// pipes open well after it.
+const KILL_GRACE: Duration = Duration::from_millis(200);
+
+/// A program printing a puzzle on its standard output, such as a solver.
+///
+/// The program fails the read if it exits with a non-zero status, outlives its timeout or prints
+/// more than its output limit. Without options, it inherits the environment and gets no input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecutableSource {
+    pub path: PathBuf,
+    pub args: Vec<String>,
+    pub timeout: Option<Duration>,
+    /// Written to the program's standard input, which is closed right after
+    pub stdin: Option<Vec<u8>>,
+    pub current_dir: Option<PathBuf>,
+    pub env: Environment,
+    /// Bytes of standard output after which the program is stopped
+    pub max_output: Option<usize>,
+}
+
+/// The environment variables a program gets.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Environment {
+    /// Every variable of the current process
+    #[default]
+    Inherit,
+    /// None at all
+    Cleared,
+    /// Only the named variables of the current process, those set being passed on
+    Allowed(Vec<String>),
+}
+
+impl From<PathBuf> for ExecutableSource {
+    fn from(path: PathBuf) -> Self {
+        Self::new(path)
+    }
+}
+
+impl ExecutableSource {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            args: Vec::new(),
+            timeout: None,
+            stdin: None,
+            current_dir: None,
+            env: Environment::Inherit,
+            max_output: None,
+        }
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn stdin(mut self, input: impl Into<Vec<u8>>) -> Self {
+        self.stdin = Some(input.into());
+        self
+    }
+
+    pub fn current_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.current_dir = Some(dir.into());
+        self
+    }
+
+    pub fn env(mut self, env: Environment) -> Self {
+        self.env = env;
+        self
+    }
+
+    pub fn max_output(mut self, bytes: usize) -> Self {
+        self.max_output = Some(bytes);
+        self
+    }
+
+    /// Runs the program to completion and returns its standard output.
+    pub fn run(&self) -> Result<Vec<u8>, ParseError> {
+        let mut child = self.command().spawn()?;
+        if let Some((mut pipe, input)) = child.stdin.take().zip(self.stdin.clone()) {
+            // A program exiting without reading its input is not an error of its own
+            thread::spawn(move || {
+                let _ = pipe.write_all(&input);
+            });
+        }
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let exceeded = Arc::new(AtomicBool::new(false));
+        let stdout = child
+            .stdout
+            .take()
+            .map(|pipe| read_limited(pipe, self.max_output, exceeded.clone()));
+        let stderr = child.stderr.take().map(read_tail);
+
+        let status = self.wait(&mut child, &exceeded, deadline)?;
+        let killed = matches!(status, Waited::TimedOut(_)) || exceeded.load(Ordering::Relaxed);
+        let stdout = receive(stdout, killed, deadline);
+        let stderr = receive(stderr, killed, deadline);
+        // The program is gone, but processes it started still hold its pipes past the deadline
+        let status = match (status, self.timeout) {
+            (Waited::Exited(_), Some(timeout)) if stdout.is_none() || stderr.is_none() => {
+                Waited::TimedOut(timeout)
+            }
+            (status, _) => status,
+        };
+        let stdout = stdout.unwrap_or_default();
+        let stderr = String::from_utf8_lossy(&stderr.unwrap_or_default()).into_owned();
+
+        let kind = match status {
+            Waited::Exited(_) if exceeded.load(Ordering::Relaxed) => {
+                ExecutableErrorKind::OutputTooLarge {
+                    limit: self.max_output.unwrap_or_default(),
+                }
+            }
+            Waited::Exited(status) if status.success() => return Ok(stdout),
+            Waited::Exited(status) => ExecutableErrorKind::Failed {
+                code: status.code(),
+                stderr,
+            },
+            Waited::TimedOut(timeout) => ExecutableErrorKind::TimedOut { timeout, stderr },
+        };
+        Err(ParseError::Executable(ExecutableError {
+            program: self.path.display().to_string(),
+            kind,
+        }))
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new(&self.path);
+        command
+            .args(&self.args)
+            .stdin(match self.stdin {
+                Some(_) => Stdio::piped(),
+                None => Stdio::null(),
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(dir) = &self.current_dir {
+            command.current_dir(dir);
+        }
+        match &self.env {
+            Environment::Inherit => {}
+            Environment::Cleared => {
+                command.env_clear();
+            }
+            Environment::Allowed(names) => {
+                command.env_clear();
+                for name in names {
+                    if let Some(value) = std::env::var_os(name) {
+                        command.env(name, value);
+                    }
+                }
+            }
+        }
+        command
+    }
+
+    /// Waits for the program, killing it once out of time or once its output went over the limit.
+    fn wait(
+        &self,
+        child: &mut Child,
+        exceeded: &AtomicBool,
+        deadline: Option<Instant>,
+    ) -> Result<Waited, ParseError> {
+        let deadline = deadline.zip(self.timeout);
+        loop {
+            if exceeded.load(Ordering::Relaxed) {
+                let _ = child.kill();
+                return Ok(Waited::Exited(child.wait()?));
+            }
+            if let Some(status) = child.try_wait()? {
+                return Ok(Waited::Exited(status));
+            }
+            if let Some((deadline, timeout)) = deadline
+                && Instant::now() >= deadline
+            {
+                let _ = child.kill();
+                child.wait()?;
+                return Ok(Waited::TimedOut(timeout));
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+enum Waited {
+    Exited(ExitStatus),
+    TimedOut(Duration),
+}
+
+/// Reads `pipe` to its end, or until more than `limit` bytes came, raising `exceeded` then.
+fn read_limited(
+    mut pipe: impl Read + Send + 'static,
+    limit: Option<usize>,
+    exceeded: Arc<AtomicBool>,
+) -> Receiver<Vec<u8>> {
+    spawn_reader(move || {
+        let mut out = Vec::new();
+        let mut buffer = [0; 8192];
+        while let Ok(read @ 1..) = pipe.read(&mut buffer) {
+            out.extend_from_slice(&buffer[..read]);
+            if limit.is_some_and(|limit| out.len() > limit) {
+                exceeded.store(true, Ordering::Relaxed);
+                break;
+            }
+        }
+        out
+    })
+}
+
+/// Reads `pipe` to its end, keeping its last [`STDERR_TAIL`] bytes.
+fn read_tail(mut pipe: impl Read + Send + 'static) -> Receiver<Vec<u8>> {
+    spawn_reader(move || {
+        let mut tail = VecDeque::with_capacity(STDERR_TAIL);
+        let mut buffer = [0; 4096];
+        while let Ok(read @ 1..) = pipe.read(&mut buffer) {
+            tail.extend(&buffer[..read]);
+            let excess = tail.len().saturating_sub(STDERR_TAIL);
+            tail.drain(..excess);
+        }
+        tail.into()
+    })
+}
+
+fn spawn_reader(read: impl FnOnce() -> Vec<u8> + Send + 'static) -> Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || sender.send(read()));
+    receiver
+}
+
+/// What a reader got, `None` if the pipe was still open at the deadline.
+fn receive(
+    reader: Option<Receiver<Vec<u8>>>,
+    killed: bool,
+    deadline: Option<Instant>,
+) -> Option<Vec<u8>> {
+    let Some(reader) = reader else {
+        return Some(Vec::new());
+    };
+    let read = match (killed, deadline) {
+        (true, _) => reader.recv_timeout(KILL_GRACE),
+        (false, Some(deadline)) => {
+            reader.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        }
+        (false, None) => reader.recv().map_err(|_| RecvTimeoutError::Disconnected),
+    };
+    match read {
+        Ok(read) => Some(read),
+        Err(RecvTimeoutError::Timeout) if !killed => None,
+        Err(_) => Some(Vec::new()),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::{fs, os::unix::fs::PermissionsExt, path::Path};
+
+    use super::*;
+    use crate::{
+        algorithm::PuzzleSource,
+        core::input::{format::FormatRegistry, parse_source, read_source_content},
+    };
+
+    /// Writes a shell script standing in for a solver.
+    fn script(dir: &Path, name: &str, body: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, format!("#!/bin/sh\n{body}\n")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    fn error_kind(result: Result<Vec<u8>, ParseError>) -> ExecutableErrorKind {
+        match result {
+            Err(ParseError::Executable(error)) => error.kind,
+            other => panic!("expected an executable error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_executable_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let echo = script(dir.path(), "echo.sh", r#"echo "title: $1"; cat"#);
+        let output = ExecutableSource::new(&echo)
+            .args(["Solved"])
+            .stdin("part.a.name: A\n")
+            .run()
+            .unwrap();
+        assert_eq!(output, b"title: Solved\npart.a.name: A\n");
+        let source = PuzzleSource::Executable(ExecutableSource::new(&echo).args(["Solved"]));
+        assert_eq!(read_source_content(source).unwrap(), "title: Solved\n");
+        let source = PuzzleSource::from(ExecutableSource::from(echo.clone()));
+        assert_eq!(read_source_content(source).unwrap(), "title: \n");
+
+        let pwd = script(dir.path(), "pwd.sh", "pwd");
+        let output = ExecutableSource::new(&pwd)
+            .current_dir(dir.path())
+            .run()
+            .unwrap();
+        let printed = PathBuf::from(String::from_utf8(output).unwrap().trim());
+        assert_eq!(
+            printed.canonicalize().unwrap(),
+            dir.path().canonicalize().unwrap()
+        );
+
+        // SAFETY: no other test reads or writes this variable
+        unsafe { std::env::set_var("STORYFRAME_TEST_SECRET", "hidden") };
+        let env = script(dir.path(), "env.sh", r#"echo "[$STORYFRAME_TEST_SECRET]""#);
+        let run = |env_policy| ExecutableSource::new(&env).env(env_policy).run().unwrap();
+        assert_eq!(run(Environment::Inherit), b"[hidden]\n");
+        assert_eq!(run(Environment::Cleared), b"[]\n");
+        assert_eq!(
+            run(Environment::Allowed(vec!["STORYFRAME_TEST_SECRET".into()])),
+            b"[hidden]\n"
+        );
+        assert_eq!(run(Environment::Allowed(vec!["HOME".into()])), b"[]\n");
+    }
+
+    #[test]
+    fn test_executable_output_includes_absolute_paths_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let part = dir.path().join("part.sf");
+        fs::write(
+            &part,
+            "part.a.name: A\npart.a.step_type: text_step\npart.a.input: a\npart.a.steps: 0__x____\n",
+        )
+        .unwrap();
+        let parse = |include: &str| {
+            let body = format!("printf 'title: Solved\\ninclude: {include}\\n'");
+            let solver = script(dir.path(), "solver.sh", &body);
+            parse_source(
+                PuzzleSource::Executable(ExecutableSource::new(solver)),
+                &FormatRegistry::default(),
+            )
+        };
+
+        // Output is not read from the directory of the program
+        let err = parse("part.sf").unwrap_err();
+        assert!(matches!(err.inner(), ParseError::Include { path, .. } if path == "part.sf"));
+        assert!(err.span().unwrap().source_name().starts_with("output of "));
+        let (_, parts) = parse(&part.display().to_string()).unwrap();
+        assert_eq!(parts[0].id, "a");
+    }
+
+    #[test]
+    fn test_executable_source_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let failing = script(
+            dir.path(),
+            "fail.sh",
+            "i=0; while [ $i -lt 500 ]; do echo \"noise $i\" >&2; i=$((i+1)); done\n\
+             echo 'no solution found' >&2; exit 3",
+        );
+        let error = ExecutableSource::new(&failing).run().unwrap_err();
+        assert!(error.to_string().contains("exited with code 3"), "{error}");
+        match error_kind(Err(error)) {
+            ExecutableErrorKind::Failed { code, stderr } => {
+                assert_eq!(code, Some(3));
+                assert!(stderr.ends_with("no solution found\n"));
+                assert!(stderr.len() <= STDERR_TAIL);
+            }
+            kind => panic!("unexpected {kind:?}"),
+        }
+
+        let slow = script(dir.path(), "slow.sh", "echo started >&2; exec sleep 10");
+        let started = Instant::now();
+        let kind = error_kind(
+            ExecutableSource::new(&slow)
+                .timeout(Duration::from_millis(200))
+                .run(),
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(
+            matches!(kind, ExecutableErrorKind::TimedOut { stderr, .. } if stderr == "started\n")
+        );
+
+        // Exits at once, leaving a process that keeps its output open
+        let forking = script(dir.path(), "forking.sh", "echo 'title: Early'; sleep 10 &");
+        let started = Instant::now();
+        let kind = error_kind(
+            ExecutableSource::new(&forking)
+                .timeout(Duration::from_millis(200))
+                .run(),
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(matches!(kind, ExecutableErrorKind::TimedOut { .. }));
+
+        let chatty = script(dir.path(), "chatty.sh", "exec yes");
+        let kind = error_kind(ExecutableSource::new(&chatty).max_output(1000).run());
+        assert!(matches!(
+            kind,
+            ExecutableErrorKind::OutputTooLarge { limit: 1000 }
+        ));
+
+        let missing = ExecutableSource::new(dir.path().join("missing")).run();
+        assert!(matches!(missing, Err(ParseError::IoError(_))));
+    }
+}
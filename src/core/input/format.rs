@@ -0,0 +1,330 @@
+use std::path::Path;
+
+use crate::{
+    algorithm::{Metadata, RawPartMetadata},
+    core::input::{
+        json::parse_json_tree,
+        processors::{Field, extract_puzzle, parse_puzzle_tree},
+    },
+    error::ParseError,
+};
+
+// ============================================================================
+// PUZZLE FORMATS
+// ============================================================================
+
+/// Key of the optional first line naming the format of a source, as in `format: json`.
+const FORMAT_HEADER: &str = "format:";
+
+/// A way of writing puzzles down. Formats are registered on a [`FormatRegistry`], which picks
+/// the one to use for each source.
+pub trait PuzzleFormat: Send + Sync {
+    /// Name used in `format:` headers and in errors.
+    fn name(&self) -> &'static str;
+
+    /// File extensions of this format, without the dot. Matched case-insensitively.
+    fn extensions(&self) -> &[&'static str] {
+        &[]
+    }
+
+    /// Whether `content` starts like a source of this format, for sources without a telling
+    /// extension.
+    fn sniff(&self, content: &[u8]) -> bool {
+        let _ = content;
+        false
+    }
+
+    /// Reads a source. `name` identifies the source (usually its path) in diagnostics.
+    fn parse(
+        &self,
+        content: &[u8],
+        name: Option<String>,
+    ) -> Result<(Metadata, Vec<RawPartMetadata>), ParseError>;
+
+    /// Reads a source into its raw `Field` tree, if the format has one. Diagnostics use it to
+    /// point at unknown keys; formats without a tree only get their errors reported.
+    fn parse_tree(
+        &self,
+        content: &[u8],
+        name: Option<String>,
+    ) -> Option<Result<Field, ParseError>> {
+        let _ = (content, name);
+        None
+    }
+}
+
+/// The key-path format read by [`parse_puzzle_format`](super::processors::parse_puzzle_format).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KeyPathFormat;
+
+impl PuzzleFormat for KeyPathFormat {
+    fn name(&self) -> &'static str {
+        "key-path"
+    }
+
+    fn extensions(&self) -> &[&'static str] {
+        &["sf", "txt"]
+    }
+
+    fn parse(
+        &self,
+        content: &[u8],
+        name: Option<String>,
+    ) -> Result<(Metadata, Vec<RawPartMetadata>), ParseError> {
+        extract_puzzle(&parse_puzzle_tree(&utf8(content)?, name)?)
+    }
+
+    fn parse_tree(
+        &self,
+        content: &[u8],
+        name: Option<String>,
+    ) -> Option<Result<Field, ParseError>> {
+        Some(utf8(content).and_then(|content| parse_puzzle_tree(&content, name)))
+    }
+}
+
+/// The JSON format read by [`parse_json_puzzle_format`](super::json::parse_json_puzzle_format).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonFormat;
+
+impl PuzzleFormat for JsonFormat {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn extensions(&self) -> &[&'static str] {
+        &["json"]
+    }
+
+    /// A key-path puzzle can never start with `{`.
+    fn sniff(&self, content: &[u8]) -> bool {
+        content.trim_ascii_start().starts_with(b"{")
+    }
+
+    fn parse(
+        &self,
+        content: &[u8],
+        name: Option<String>,
+    ) -> Result<(Metadata, Vec<RawPartMetadata>), ParseError> {
+        extract_puzzle(&parse_json_tree(&utf8(content)?, name)?)
+    }
+
+    fn parse_tree(
+        &self,
+        content: &[u8],
+        name: Option<String>,
+    ) -> Option<Result<Field, ParseError>> {
+        Some(utf8(content).and_then(|content| parse_json_tree(&content, name)))
+    }
+}
+
+fn utf8(content: &[u8]) -> Result<String, ParseError> {
+    Ok(String::from_utf8(content.to_vec())?)
+}
+
+/// The formats puzzles can be read from.
+///
+/// The format of a source is, in order of precedence: the one named by a `format: <name>` first
+/// line, the one owning the source's extension, the first one whose [`PuzzleFormat::sniff`]
+/// accepts the content, and finally the first registered format.
+pub struct FormatRegistry {
+    formats: Vec<Box<dyn PuzzleFormat>>,
+}
+
+impl FormatRegistry {
+    /// Creates a registry without any format.
+    pub fn new() -> Self {
+        Self {
+            formats: Vec::new(),
+        }
+    }
+
+    /// Adds a format. A format registered under an existing name replaces it.
+    pub fn register<F: PuzzleFormat + 'static>(&mut self, format: F) {
+        let format: Box<dyn PuzzleFormat> = Box::new(format);
+        match self.formats.iter_mut().find(|f| f.name() == format.name()) {
+            Some(existing) => *existing = format,
+            None => self.formats.push(format),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn PuzzleFormat> {
+        self.formats
+            .iter()
+            .find(|format| format.name() == name)
+            .map(Box::as_ref)
+    }
+
+    pub fn format_names(&self) -> Vec<String> {
+        self.formats.iter().map(|f| f.name().to_string()).collect()
+    }
+
+    /// Picks the format of a source. When the format comes from a `format:` header, the returned
+    /// content has that line blanked so that formats never see it, while line numbers stay the
+    /// same.
+    pub fn detect<'a>(
+        &self,
+        name: Option<&str>,
+        content: &'a [u8],
+    ) -> Result<(&dyn PuzzleFormat, std::borrow::Cow<'a, [u8]>), ParseError> {
+        if let Some((format_name, header_end)) = read_format_header(content) {
+            let format = self
+                .get(&format_name)
+                .ok_or_else(|| ParseError::UnknownFormat {
+                    format: format_name,
+                    supported_formats: self.format_names(),
+                })?;
+            let mut stripped = content.to_vec();
+            let header_start = content.len() - content.trim_ascii_start().len();
+            stripped.splice(header_start..header_end, std::iter::empty());
+            return Ok((format, stripped.into()));
+        }
+
+        let extension = name
+            .and_then(|name| Path::new(name).extension())
+            .and_then(|extension| extension.to_str());
+        let by_extension = extension.and_then(|extension| {
+            self.formats.iter().find(|format| {
+                format
+                    .extensions()
+                    .iter()
+                    .any(|known| known.eq_ignore_ascii_case(extension))
+            })
+        });
+        by_extension
+            .or_else(|| self.formats.iter().find(|format| format.sniff(content)))
+            .or_else(|| self.formats.first())
+            .map(|format| (format.as_ref(), content.into()))
+            .ok_or_else(|| ParseError::UnknownFormat {
+                format: extension.unwrap_or_default().to_string(),
+                supported_formats: Vec::new(),
+            })
+    }
+
+    /// Reads a source with the format it is written in.
+    pub fn parse(
+        &self,
+        content: &[u8],
+        name: Option<String>,
+    ) -> Result<(Metadata, Vec<RawPartMetadata>), ParseError> {
+        let (format, content) = self.detect(name.as_deref(), content)?;
+        format.parse(&content, name)
+    }
+}
+
+/// The default registry knows the key-path format, which is also the fallback, and JSON.
+impl Default for FormatRegistry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register(KeyPathFormat);
+        registry.register(JsonFormat);
+        registry
+    }
+}
+
+/// Reads a `format: <name>` first line, returning the name and where the line ends (before its
+/// line break).
+fn read_format_header(content: &[u8]) -> Option<(String, usize)> {
+    let trimmed = content.trim_ascii_start();
+    let line_len = trimmed
+        .iter()
+        .position(|b| *b == b'\n')
+        .unwrap_or(trimmed.len());
+    let line = std::str::from_utf8(&trimmed[..line_len]).ok()?;
+    let name = line.strip_prefix(FORMAT_HEADER)?.trim();
+    let header_end = content.len() - trimmed.len() + line_len;
+    Some((name.to_string(), header_end))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::input::processors::parse_puzzle_format;
+
+    /// A toy format: `title` on the first line, then one `id;name;step_type;input;steps` part per
+    /// line. Written as key-path behind the scenes to keep the test short.
+    struct CsvFormat;
+
+    impl PuzzleFormat for CsvFormat {
+        fn name(&self) -> &'static str {
+            "csv"
+        }
+
+        fn extensions(&self) -> &[&'static str] {
+            &["csv"]
+        }
+
+        fn sniff(&self, content: &[u8]) -> bool {
+            content.starts_with(b"#!csv")
+        }
+
+        fn parse(
+            &self,
+            content: &[u8],
+            _name: Option<String>,
+        ) -> Result<(Metadata, Vec<RawPartMetadata>), ParseError> {
+            let content = utf8(content)?;
+            let mut lines = content
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty() && !l.starts_with("#!"));
+            let mut key_path = format!("title: {}\n", lines.next().unwrap_or_default());
+            for line in lines {
+                let [id, name, step_type, input, steps] =
+                    line.splitn(5, ';').collect::<Vec<_>>()[..]
+                else {
+                    return Err(ParseError::InvalidFormat(line.to_string()));
+                };
+                key_path += &format!(
+                    "part.{id}.name: {name}\npart.{id}.step_type: {step_type}\n\
+                     part.{id}.input: {input}\npart.{id}.steps: {steps}\n"
+                );
+            }
+            parse_puzzle_format(&key_path)
+        }
+    }
+
+    const CSV: &str = "Test Puzzle\na;First;text;hello;0__a____ | 1__b____\n";
+
+    fn registry() -> FormatRegistry {
+        let mut registry = FormatRegistry::default();
+        registry.register(CsvFormat);
+        registry
+    }
+
+    #[test]
+    fn test_detects_format() {
+        let registry = registry();
+        let detect = |name: Option<&str>, content: &str| {
+            registry
+                .detect(name, content.as_bytes())
+                .map(|(format, _)| format.name())
+                .unwrap()
+        };
+        assert_eq!(detect(Some("day1.CSV"), CSV), "csv");
+        assert_eq!(detect(None, &format!("#!csv\n{CSV}")), "csv");
+        assert_eq!(detect(None, &format!("\n format: csv \n{CSV}")), "csv");
+        assert_eq!(detect(Some("day1.json"), "title: x"), "json");
+        assert_eq!(detect(None, " {\"title\": \"x\"}"), "json");
+        assert_eq!(detect(None, "title: x"), "key-path");
+
+        let (_, parts) = registry
+            .parse(format!("format: csv\n{CSV}").as_bytes(), None)
+            .unwrap();
+        assert_eq!(parts[0].display_name, "First");
+    }
+
+    #[test]
+    fn test_header_is_blanked() {
+        let content = "format: key-path\ntitle: x\npart.a.name: bad\n";
+        let registry = FormatRegistry::default();
+        let (format, stripped) = registry.detect(None, content.as_bytes()).unwrap();
+        assert_eq!(format.name(), "key-path");
+        assert_eq!(&*stripped, b"\ntitle: x\npart.a.name: bad\n");
+
+        let err = FormatRegistry::default()
+            .parse(b"format: yaml\ntitle: x", None)
+            .unwrap_err();
+        assert!(matches!(err, ParseError::UnknownFormat { format, .. } if format == "yaml"));
+    }
+}
@@ -0,0 +1,460 @@
+use std::{
+    fmt::Write as _,
+    io::{self, BufRead, Write},
+    sync::Arc,
+};
+
+use crate::{
+    algorithm::{AlgorithmInstance, PuzzleSource},
+    core::input::{
+        diagnostics::{Diagnostic, DiagnosticKind, suggest},
+        processors::{
+            BLOCK_END, BLOCK_START, COMMENT_SIGN, Field, INCLUDE_KEY, Leaf, PART_KEYS,
+            TOP_LEVEL_KEYS, check_input_sources, extract_config, extract_macros, extract_raw_steps,
+            extract_single_part_with_steps, parse_puzzle_tree,
+        },
+        references::resolve_references,
+    },
+    domains::DomainRegistry,
+    error::ParseError,
+};
+
+// ============================================================================
+// INTERACTIVE SOURCES
+// ============================================================================
+
+/// Starts the lines that are commands to the builder rather than puzzle content.
+pub const COMMAND_SIGN: char = ':';
+
+/// Builds a puzzle from key-path lines typed one at a time, as read by
+/// [`parse_puzzle_format`](super::processors::parse_puzzle_format).
+///
+/// Each line is checked as it arrives against the lines kept so far, and only kept if it does
+/// not break the puzzle: references and `input_from` can only name what is already written, and
+/// steps are parsed as soon as their part has a step type. Parts missing some of their fields
+/// are fine until the puzzle is built. A block value is checked once closed.
+///
+/// Besides content, lines can be commands:
+/// - `:parts` lists the parts written so far,
+/// - `:check` tells whether the puzzle loads as it stands,
+/// - `:done` builds the puzzle, which is the same as loading the kept lines from a file.
+pub struct InteractiveBuilder<'a> {
+    registry: &'a DomainRegistry,
+    /// The lines kept so far
+    content: String,
+    /// The lines of a block value being written, starting with its key line, and its closing tag
+    block: Option<(String, String)>,
+}
+
+/// What the builder makes of a line.
+#[derive(Debug)]
+pub enum Reply {
+    /// The line is kept, with warnings about keys nothing reads.
+    Accepted(Vec<Diagnostic>),
+    /// The line belongs to a block value, checked once the block is closed.
+    Pending,
+    /// The line is dropped.
+    Rejected(Diagnostic),
+    /// The answer to a command.
+    Info(String),
+    /// The puzzle is built.
+    Done(Box<AlgorithmInstance>),
+}
+
+impl<'a> InteractiveBuilder<'a> {
+    pub fn new(registry: &'a DomainRegistry) -> Self {
+        Self {
+            registry,
+            content: String::new(),
+            block: None,
+        }
+    }
+
+    /// The lines kept so far, a key-path puzzle loading the same as the one being built.
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// Reads lines from `input` until `:done` or the end of the input, writing what is made of
+    /// each of them to `output`. The puzzle is built at the end of the input if `:done` did not
+    /// come.
+    pub fn run(
+        &mut self,
+        input: impl BufRead,
+        mut output: impl Write,
+    ) -> Result<AlgorithmInstance, ParseError> {
+        for line in input.lines() {
+            let reply = self.feed(&line?);
+            match reply {
+                Reply::Done(instance) => return Ok(*instance),
+                Reply::Accepted(warnings) => {
+                    for warning in warnings {
+                        writeln!(output, "{warning}")?;
+                    }
+                }
+                Reply::Rejected(error) => writeln!(output, "{error}")?,
+                Reply::Info(info) => writeln!(output, "{info}")?,
+                Reply::Pending => {}
+            }
+        }
+        self.build()
+    }
+
+    /// Handles a single line.
+    pub fn feed(&mut self, line: &str) -> Reply {
+        if let Some((lines, tag)) = &mut self.block {
+            lines.push('\n');
+            lines.push_str(line);
+            if line.trim().strip_prefix(BLOCK_END) != Some(tag.as_str()) {
+                return Reply::Pending;
+            }
+            let (lines, _) = self.block.take().unwrap_or_default();
+            return self.add(&lines);
+        }
+
+        let trimmed = line.trim();
+        if let Some(command) = trimmed.strip_prefix(COMMAND_SIGN) {
+            return self.command(command.trim());
+        }
+        if trimmed.is_empty() || trimmed.starts_with(COMMENT_SIGN) {
+            return self.add(line);
+        }
+        let Some((_, value)) = trimmed.split_once(':') else {
+            let error =
+                ParseError::InvalidFormat(format!("expected `key.path: value`, got '{trimmed}'"));
+            return Reply::Rejected(Diagnostic::error(error));
+        };
+        match value.trim().strip_prefix(BLOCK_START) {
+            Some(tag) => {
+                self.block = Some((line.to_string(), tag.trim().to_string()));
+                Reply::Pending
+            }
+            None => self.add(line),
+        }
+    }
+
+    /// Builds the puzzle out of the lines kept so far, parsing every step.
+    pub fn build(&self) -> Result<AlgorithmInstance, ParseError> {
+        if let Some((lines, _)) = &self.block {
+            let key = lines
+                .split_once(':')
+                .map(|(key, _)| key.trim())
+                .unwrap_or_default();
+            return Err(ParseError::UnterminatedBlock {
+                key: key.to_string(),
+                line: self.content.lines().count() + 1,
+            });
+        }
+        AlgorithmInstance::from_source_strict(
+            PuzzleSource::String(self.content.clone()),
+            self.registry,
+        )
+    }
+
+    fn command(&mut self, command: &str) -> Reply {
+        match command {
+            "parts" => Reply::Info(self.parts()),
+            "check" => match self.build() {
+                Ok(_) => Reply::Info("ok".to_string()),
+                Err(error) => Reply::Rejected(Diagnostic::error(error)),
+            },
+            "done" => match self.build() {
+                Ok(instance) => Reply::Done(Box::new(instance)),
+                Err(error) => Reply::Rejected(Diagnostic::error(error)),
+            },
+            command => Reply::Rejected(Diagnostic::error(ParseError::InvalidFormat(format!(
+                "unknown command '{COMMAND_SIGN}{command}', expected :parts, :check or :done"
+            )))),
+        }
+    }
+
+    /// Keeps `lines` if the puzzle still checks with them.
+    fn add(&mut self, lines: &str) -> Reply {
+        let mut content = self.content.clone();
+        content.push_str(lines);
+        content.push('\n');
+        if let Err(error) = self.check(&content) {
+            let mut diagnostic = Diagnostic::error(error);
+            if let DiagnosticKind::Error(error) = &diagnostic.kind
+                && let ParseError::UnknownStepType {
+                    step_type,
+                    supported_step_types,
+                } = error.inner()
+            {
+                diagnostic.suggestion =
+                    suggest(step_type, supported_step_types.iter().map(String::as_str));
+            }
+            return Reply::Rejected(diagnostic);
+        }
+        self.content = content;
+        Reply::Accepted(unknown_keys(lines))
+    }
+
+    /// Checks a puzzle that may still be missing some part fields.
+    fn check(&self, content: &str) -> Result<(), ParseError> {
+        let root = parse_puzzle_tree(content, None)?;
+        let (root, errors) = resolve_references(&root);
+        if let Some(error) = errors.into_iter().next() {
+            return Err(error);
+        }
+        let root_config = Arc::new(extract_config(&root, None).with_macros(extract_macros(&root)?));
+        let Some(Field::Node(part_map)) = root.get_path(&["part"]) else {
+            return Ok(());
+        };
+
+        let mut sources: Vec<(&str, Option<&Leaf>)> = Vec::new();
+        for (part_id, part_field) in part_map {
+            let raw_steps = match extract_single_part_with_steps(part_id, part_field, &root_config)
+            {
+                Ok(part) => Some(part.raw_steps),
+                Err(error) if matches!(error.inner(), ParseError::MissingPartField(..)) => {
+                    part_field
+                        .get_path(&["steps"])
+                        .map(|steps| extract_raw_steps(part_id, steps))
+                        .transpose()?
+                }
+                Err(error) => return Err(error),
+            };
+            let input_from = match part_field.get_path(&["input_from"]) {
+                Some(Field::Leaf(leaf)) => Some(leaf),
+                _ => None,
+            };
+            sources.push((part_id, input_from));
+
+            let Some(Field::Leaf(step_type)) = part_field.get_path(&["step_type"]) else {
+                continue;
+            };
+            let step_type_id = self
+                .registry
+                .step_type_to_id(&step_type.value)
+                .map_err(|error| error.at(step_type.span.clone()))?;
+            if let Some(raw_steps) = raw_steps {
+                let configuration = extract_config(part_field, Some(root_config.clone()));
+                let errors = self
+                    .registry
+                    .check_steps(step_type_id, &raw_steps, &configuration);
+                if let Some(error) = errors.into_iter().next() {
+                    return Err(error);
+                }
+            }
+        }
+        check_input_sources(&sources)
+    }
+
+    /// One line per part written so far, with its step type and number of steps once complete.
+    fn parts(&self) -> String {
+        let Ok(root) = parse_puzzle_tree(&self.content, None) else {
+            return String::new();
+        };
+        let (root, _) = resolve_references(&root);
+        let macros = extract_macros(&root).unwrap_or_default();
+        let root_config = Arc::new(extract_config(&root, None).with_macros(macros));
+        let mut out = String::new();
+        let Some(Field::Node(part_map)) = root.get_path(&["part"]) else {
+            return "no parts".to_string();
+        };
+        for (part_id, part_field) in part_map {
+            match extract_single_part_with_steps(part_id, part_field, &root_config) {
+                Ok(part) => {
+                    let steps = part
+                        .raw_steps
+                        .count(&part.configuration)
+                        .unwrap_or_default();
+                    let plural = if steps == 1 { "" } else { "s" };
+                    let _ = writeln!(
+                        out,
+                        "{part_id}: {} ({}, {steps} step{plural})",
+                        part.display_name, part.raw_step_type_id
+                    );
+                }
+                Err(error) => match error.inner() {
+                    ParseError::MissingPartField(_, field) => {
+                        let _ = writeln!(out, "{part_id}: incomplete, missing {field}");
+                    }
+                    error => {
+                        let _ = writeln!(out, "{part_id}: {error}");
+                    }
+                },
+            }
+        }
+        out.trim_end().to_string()
+    }
+}
+
+/// Warnings for the keys of `lines` that nothing reads.
+fn unknown_keys(lines: &str) -> Vec<Diagnostic> {
+    if lines.trim_start().starts_with(COMMENT_SIGN) {
+        return Vec::new();
+    }
+    let Some((key, _)) = lines.split_once(':') else {
+        return Vec::new();
+    };
+    let path: Vec<&str> = key.split('.').map(str::trim).collect();
+    match path.as_slice() {
+        [key] if *key == INCLUDE_KEY => Vec::new(),
+        ["part", part, field, ..] if !PART_KEYS.contains(field) => {
+            let kind = DiagnosticKind::UnknownPartField {
+                part: part.to_string(),
+                field: field.to_string(),
+            };
+            vec![Diagnostic::warning(
+                kind,
+                &Default::default(),
+                PART_KEYS,
+                field,
+            )]
+        }
+        [key, ..] if !TOP_LEVEL_KEYS.contains(key) => {
+            let kind = DiagnosticKind::UnknownKey(key.to_string());
+            vec![Diagnostic::warning(
+                kind,
+                &Default::default(),
+                TOP_LEVEL_KEYS,
+                key,
+            )]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Reads the lines of an interactive source up to `:done` without checking them, the commands
+/// being dropped. [`AlgorithmInstance::from_source`] checks each line as it comes instead.
+pub(crate) fn read_lines(input: impl BufRead) -> io::Result<String> {
+    let mut content = String::new();
+    let mut block: Option<String> = None;
+    for line in input.lines() {
+        let line = line?;
+        let trimmed = line.trim();
+        match &block {
+            Some(tag) if trimmed.strip_prefix(BLOCK_END) == Some(tag.as_str()) => block = None,
+            Some(_) => {}
+            None if trimmed.strip_prefix(COMMAND_SIGN).map(str::trim) == Some("done") => break,
+            None if trimmed.starts_with(COMMAND_SIGN) => continue,
+            None => {
+                block = trimmed
+                    .split_once(':')
+                    .and_then(|(_, value)| value.trim().strip_prefix(BLOCK_START))
+                    .map(|tag| tag.trim().to_string());
+            }
+        }
+        content.push_str(&line);
+        content.push('\n');
+    }
+    Ok(content)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::domains::create_registry;
+
+    fn rejected(reply: Reply) -> String {
+        match reply {
+            Reply::Rejected(diagnostic) => diagnostic.to_string(),
+            reply => panic!("expected the line to be rejected, got {reply:?}"),
+        }
+    }
+
+    #[test]
+    fn test_interactive_builder_checks_each_line() {
+        let registry = create_registry();
+        let mut builder = InteractiveBuilder::new(registry.domain_registry());
+        assert!(matches!(builder.feed("title: Live"), Reply::Accepted(w) if w.is_empty()));
+        assert!(rejected(builder.feed("no colon")).contains("expected `key.path: value`"));
+        assert!(matches!(
+            builder.feed("athor: me"),
+            Reply::Accepted(w) if w[0].suggestion.as_deref() == Some("author")
+        ));
+
+        builder.feed("part.a.name: A");
+        let error = rejected(builder.feed("part.a.step_type: txt"));
+        assert!(error.contains("did you mean 'text'?"), "{error}");
+        assert!(matches!(
+            builder.feed("part.a.step_type: text"),
+            Reply::Accepted(_)
+        ));
+        // Steps are parsed as soon as the step type is known, even with fields still missing
+        assert!(rejected(builder.feed("part.a.steps: nope")).contains("Step 0 is invalid"));
+        assert!(matches!(
+            builder.feed("part.a.steps: 0__x____"),
+            Reply::Accepted(_)
+        ));
+        assert!(rejected(builder.feed("part.b.input_from: c")).contains("unknown part 'c'"));
+        assert!(rejected(builder.feed("part.b.input: ${part.c.input}")).contains("unknown key"));
+
+        match builder.feed(":parts") {
+            Reply::Info(parts) => assert_eq!(parts, "a: incomplete, missing input"),
+            reply => panic!("unexpected {reply:?}"),
+        }
+        assert!(rejected(builder.feed(":check")).contains("missing the key : input"));
+        assert!(rejected(builder.feed(":done")).contains("missing the key : input"));
+        assert!(rejected(builder.feed(":undo")).contains("unknown command ':undo'"));
+
+        assert!(matches!(builder.feed("part.a.input: <<<"), Reply::Pending));
+        assert!(matches!(builder.feed("two words"), Reply::Pending));
+        assert!(matches!(builder.feed(":done"), Reply::Pending));
+        assert!(matches!(builder.feed(">>>"), Reply::Accepted(_)));
+        assert!(matches!(builder.feed(":check"), Reply::Info(ok) if ok == "ok"));
+        match builder.feed(":done") {
+            Reply::Done(instance) => {
+                assert_eq!(
+                    instance.get_part("a").unwrap().input_data,
+                    "two words\n:done"
+                );
+            }
+            reply => panic!("unexpected {reply:?}"),
+        }
+    }
+
+    #[test]
+    fn test_interactive_builder_matches_file() {
+        let content = "\
+title: Typed live
+config.rows: 2
+macro.twice: 0__a____ | 1__b____
+# A comment: not a key
+
+part.first.name: First
+part.first.step_type: text
+part.first.steps: twice! | [2__c____ | 3__d____] @\"grouped\"
+part.first.input: <<<
+hello
+>>>
+part.first.bookmarks.middle: 1
+part.second.name: Second
+part.second.step_type: text
+part.second.input_from: first
+part.second.steps: 0__z____
+";
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("puzzle.txt");
+        std::fs::write(&path, content).unwrap();
+        let registry = create_registry();
+        let from_file =
+            AlgorithmInstance::from_source(PuzzleSource::File(path), registry.domain_registry())
+                .unwrap();
+
+        let typed = format!("{content}part.second.steps: broken\n:parts\n:done\ntitle: ignored\n");
+        let mut output = Vec::new();
+        let mut builder = InteractiveBuilder::new(registry.domain_registry());
+        let built = builder.run(Cursor::new(typed), &mut output).unwrap();
+        assert_eq!(built, from_file);
+        assert_eq!(builder.content(), content);
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines[0].starts_with("error: Step 0 is invalid"), "{output}");
+        assert!(output.ends_with("first: First (text, 3 steps)\nsecond: Second (text, 1 step)\n"));
+
+        // Without `:done`, the end of the input builds the puzzle
+        let mut builder = InteractiveBuilder::new(registry.domain_registry());
+        let built = builder.run(Cursor::new(content), io::sink()).unwrap();
+        assert_eq!(built, from_file);
+        assert_eq!(
+            read_lines(Cursor::new(format!(":parts\n{content}: done\nx: y"))).unwrap(),
+            content
+        );
+    }
+}
@@ -0,0 +1,641 @@
+use std::{fmt::Write, sync::Arc};
+
+use indexmap::IndexMap;
+
+use crate::{
+    algorithm::{Metadata, PartInfo, RawPartMetadata, RawSteps},
+    core::{
+        configuration::Configuration,
+        input::{
+            processors::{Field, Leaf, extract_puzzle},
+            references::escape_references,
+            span::{SourceText, Span},
+            writer::part_bookmarks,
+        },
+    },
+    error::ParseError,
+};
+
+// ============================================================================
+// JSON PUZZLE FORMAT
+// ============================================================================
+
+/// Reads a puzzle written as JSON. The document mirrors the key-path format: every dotted key
+/// becomes a nested object, so `part.a.steps` is `{"part": {"a": {"steps": …}}}`.
+///
+/// Numbers and booleans are read as their literal text and `null` entries are ignored. `steps`
+/// may be a single `|`-separated string or an array with one entry per step; entries are strings
+/// handed to the step parser, or objects for step types that accept structured steps.
+pub fn parse_json_puzzle_format(
+    content: &str,
+    name: Option<String>,
+) -> Result<(Metadata, Vec<RawPartMetadata>), ParseError> {
+    let root = parse_json_tree(content, name)?;
+    extract_puzzle(&root)
+}
+
+/// Builds the raw `Field` tree of a JSON puzzle, without interpreting any key.
+pub fn parse_json_tree(content: &str, name: Option<String>) -> Result<Field, ParseError> {
+    let source = SourceText::new(name, content);
+    let mut parser = JsonParser {
+        source: &source,
+        text: source.text.as_str(),
+        position: 0,
+        depth: 0,
+    };
+    parser.skip_whitespace();
+    let root = match parser.peek() {
+        Some('{') => parser.parse_value(Span::default())?,
+        _ => return Err(parser.error("a puzzle must be a JSON object")),
+    };
+    parser.skip_whitespace();
+    if parser.position < parser.text.len() {
+        return Err(parser.error("unexpected content after the puzzle object"));
+    }
+    Ok(root.unwrap_or_else(|| Field::Node(IndexMap::new())))
+}
+
+/// Objects and arrays nested deeper than this are rejected, rather than overflowing the stack.
+pub const MAX_DEPTH: usize = 128;
+
+struct JsonParser<'a> {
+    source: &'a Arc<SourceText>,
+    text: &'a str,
+    position: usize,
+    /// Objects and arrays the parser is within
+    depth: usize,
+}
+
+impl JsonParser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+
+    fn span(&self, start: usize) -> Span {
+        Span::new(self.source, start..self.position)
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        let end = self.position + self.peek().map_or(0, char::len_utf8);
+        ParseError::InvalidFormat(format!("JSON: {message}"))
+            .at(Span::new(self.source, self.position..end))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        if self.peek() == Some(expected) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{expected}'")))
+        }
+    }
+
+    /// Parses any value. `key_span` is the span of the key the value is stored under, kept on
+    /// leaves for diagnostics. Returns `None` for `null`.
+    fn parse_value(&mut self, key_span: Span) -> Result<Option<Field>, ParseError> {
+        self.skip_whitespace();
+        let start = self.position;
+        let field = match self.peek() {
+            Some(open @ ('{' | '[')) => {
+                if self.depth == MAX_DEPTH {
+                    return Err(self.error(&format!("nested deeper than {MAX_DEPTH} levels")));
+                }
+                self.depth += 1;
+                let field = match open {
+                    '{' => Field::Node(self.parse_object()?),
+                    _ => Field::List(self.parse_array()?),
+                };
+                self.depth -= 1;
+                field
+            }
+            Some('"') => {
+                let value = self.parse_string()?;
+                // The value span covers the text between the quotes
+                let span = Span::new(self.source, start + 1..self.position - 1);
+                Field::Leaf(Leaf {
+                    value,
+                    span,
+                    key_span,
+                })
+            }
+            Some(c) if c == '-' || c.is_ascii_alphanumeric() => {
+                while self
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+                {
+                    self.position += 1;
+                }
+                let literal = &self.text[start..self.position];
+                match literal {
+                    "null" => return Ok(None),
+                    "true" | "false" => {}
+                    number if is_number(number) => {}
+                    _ => {
+                        self.position = start;
+                        return Err(self.error("expected a value"));
+                    }
+                }
+                Field::Leaf(Leaf {
+                    value: literal.to_string(),
+                    span: self.span(start),
+                    key_span,
+                })
+            }
+            _ => return Err(self.error("expected a value")),
+        };
+        Ok(Some(field))
+    }
+
+    fn parse_object(&mut self) -> Result<IndexMap<String, Field>, ParseError> {
+        self.expect('{')?;
+        let mut map = IndexMap::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.position += 1;
+            return Ok(map);
+        }
+        loop {
+            self.skip_whitespace();
+            let key_start = self.position;
+            if self.peek() != Some('"') {
+                return Err(self.error("expected a string key"));
+            }
+            let key = self.parse_string()?;
+            let key_span = self.span(key_start);
+            self.expect(':')?;
+            if let Some(value) = self.parse_value(key_span)? {
+                map.insert(key, value);
+            }
+            self.skip_whitespace();
+            match self.bump() {
+                Some(',') => continue,
+                Some('}') => return Ok(map),
+                _ => {
+                    self.position -= self.text[..self.position]
+                        .chars()
+                        .next_back()
+                        .map_or(0, char::len_utf8);
+                    return Err(self.error("expected ',' or '}'"));
+                }
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Vec<Field>, ParseError> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.position += 1;
+            return Ok(items);
+        }
+        loop {
+            self.skip_whitespace();
+            // Array items have no key, their own span stands in for it
+            let item_start = self.position;
+            if let Some(mut item) = self.parse_value(Span::default())? {
+                if let Field::Leaf(leaf) = &mut item {
+                    leaf.key_span = self.span(item_start);
+                }
+                items.push(item);
+            }
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.position += 1,
+                Some(']') => {
+                    self.position += 1;
+                    return Ok(items);
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, ParseError> {
+        self.expect('"')?;
+        let mut value = String::new();
+        loop {
+            let escape_start = self.position;
+            match self.bump() {
+                None => return Err(self.error("unterminated string")),
+                Some('"') => return Ok(value),
+                Some('\\') => match self.bump() {
+                    Some('"') => value.push('"'),
+                    Some('\\') => value.push('\\'),
+                    Some('/') => value.push('/'),
+                    Some('b') => value.push('\u{8}'),
+                    Some('f') => value.push('\u{c}'),
+                    Some('n') => value.push('\n'),
+                    Some('r') => value.push('\r'),
+                    Some('t') => value.push('\t'),
+                    Some('u') => value.push(self.parse_unicode_escape(escape_start)?),
+                    _ => {
+                        self.position = escape_start;
+                        return Err(self.error("invalid escape sequence"));
+                    }
+                },
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    /// Reads the digits of a `\u` escape, combining surrogate pairs.
+    fn parse_unicode_escape(&mut self, escape_start: usize) -> Result<char, ParseError> {
+        let high = self.parse_hex4(escape_start)?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if !self.text[self.position..].starts_with("\\u") {
+                self.position = escape_start;
+                return Err(self.error("unpaired surrogate in escape sequence"));
+            }
+            self.position += 2;
+            let low = self.parse_hex4(escape_start)?;
+            if !(0xDC00..=0xDFFF).contains(&low) {
+                self.position = escape_start;
+                return Err(self.error("unpaired surrogate in escape sequence"));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| {
+            self.position = escape_start;
+            self.error("invalid unicode escape")
+        })
+    }
+
+    fn parse_hex4(&mut self, escape_start: usize) -> Result<u32, ParseError> {
+        let digits = self.text.get(self.position..self.position + 4);
+        match digits.and_then(|d| u32::from_str_radix(d, 16).ok()) {
+            Some(code) => {
+                self.position += 4;
+                Ok(code)
+            }
+            None => {
+                self.position = escape_start;
+                Err(self.error("invalid unicode escape"))
+            }
+        }
+    }
+}
+
+/// Whether `literal` follows the JSON number grammar: an optional minus, an integer part without
+/// leading zeros, then optional fraction and exponent parts. `NaN` and `Infinity` are no numbers.
+fn is_number(literal: &str) -> bool {
+    let digits =
+        |text: &str| text.len() - text.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let rest = literal.strip_prefix('-').unwrap_or(literal);
+    let integer = digits(rest);
+    if integer == 0 || (integer > 1 && rest.starts_with('0')) {
+        return false;
+    }
+    let mut rest = &rest[integer..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let count = digits(fraction);
+        if count == 0 {
+            return false;
+        }
+        rest = &fraction[count..];
+    }
+    if let Some(exponent) = rest.strip_prefix(['e', 'E']) {
+        let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+        let count = digits(exponent);
+        if count == 0 {
+            return false;
+        }
+        rest = &exponent[count..];
+    }
+    rest.is_empty()
+}
+
+// ============================================================================
+// JSON WRITER
+// ============================================================================
+
+/// Encodes a puzzle as JSON, in the shape read by [`parse_json_puzzle_format`].
+///
+/// Steps are written as an array of strings, one per step, and every value as a string. An
+/// `input_file` or `steps_file` is written back as the path found in the source.
+pub fn write_json_puzzle_format(metadata: &Metadata, parts: &[PartInfo]) -> String {
+    let mut root = IndexMap::new();
+    root.insert("title".to_string(), Field::leaf(&metadata.title));
+    if let Some(author) = &metadata.author {
+        root.insert("author".to_string(), Field::leaf(author));
+    }
+    if let Some(difficulty) = metadata.difficulty {
+        root.insert(
+            "difficulty".to_string(),
+            Field::leaf(difficulty.to_string()),
+        );
+    }
+    if let Some(description) = &metadata.description {
+        root.insert("description".to_string(), Field::leaf(description));
+    }
+    if !metadata.meta.is_empty() {
+        root.insert("meta".to_string(), Field::Node(metadata.meta.clone()));
+    }
+    let configuration = &metadata.configuration;
+    if configuration.entries().next().is_some() {
+        root.insert("config".to_string(), configuration_node(configuration));
+    }
+    let macros: IndexMap<String, Field> = configuration
+        .macros()
+        .map(|(name, body)| (name.clone(), Field::Leaf(body.clone())))
+        .collect();
+    if !macros.is_empty() {
+        root.insert("macro".to_string(), Field::Node(macros));
+    }
+
+    let mut part_map = IndexMap::new();
+    for part in parts {
+        let mut fields = IndexMap::new();
+        fields.insert("name".to_string(), Field::leaf(&part.display_name));
+        if let Some(description) = &part.description {
+            fields.insert("description".to_string(), Field::leaf(description));
+        }
+        fields.insert("step_type".to_string(), Field::leaf(part.step_type_id));
+        match (&part.input_from, &part.input_file) {
+            (Some(source), _) => fields.insert("input_from".to_string(), Field::leaf(source)),
+            (None, Some(path)) => fields.insert("input_file".to_string(), Field::leaf(path)),
+            (None, None) => fields.insert("input".to_string(), Field::leaf(&part.input_data)),
+        };
+        match &part.steps_file {
+            Some(path) => fields.insert("steps_file".to_string(), Field::leaf(path)),
+            None => fields.insert("steps".to_string(), written_steps(part)),
+        };
+        let bookmarks: IndexMap<String, Field> = part_bookmarks(part)
+            .into_iter()
+            .map(|bookmark| (bookmark.name, Field::leaf(bookmark.step.to_string())))
+            .collect();
+        if !bookmarks.is_empty() {
+            fields.insert("bookmarks".to_string(), Field::Node(bookmarks));
+        }
+        if part.configuration.entries().next().is_some() {
+            fields.insert(
+                "config".to_string(),
+                configuration_node(&part.configuration),
+            );
+        }
+        part_map.insert(part.id.clone(), Field::Node(fields));
+    }
+    if !part_map.is_empty() {
+        root.insert("part".to_string(), Field::Node(part_map));
+    }
+
+    let mut out = String::new();
+    write_field(&mut out, &Field::Node(root), 0);
+    out.push('\n');
+    out
+}
+
+/// The steps of a part, a step group being written as a nested list. Steps that do not parse are
+/// written back as found.
+fn written_steps(part: &PartInfo) -> Field {
+    match part
+        .steps()
+        .and_then(|steps| steps.written_frames(&part.configuration))
+    {
+        Ok(frames) => Field::List(
+            frames
+                .into_iter()
+                .map(|mut steps| match steps.len() {
+                    1 => Field::leaf(steps.remove(0)),
+                    _ => Field::List(steps.into_iter().map(Field::leaf).collect()),
+                })
+                .collect(),
+        ),
+        Err(_) => match &part.raw_steps {
+            RawSteps::Delimited(leaf) => Field::Leaf(leaf.clone()),
+            RawSteps::List(items) => Field::List(items.clone()),
+            raw_steps => Field::leaf(raw_steps.to_string()),
+        },
+    }
+}
+
+/// The entries set at this level of the configuration, without the inherited ones.
+fn configuration_node(configuration: &Configuration) -> Field {
+    Field::Node(
+        configuration
+            .entries()
+            .map(|(key, field)| (key.clone(), field.clone()))
+            .collect(),
+    )
+}
+
+fn write_field(out: &mut String, field: &Field, indent: usize) {
+    let padding = "  ".repeat(indent + 1);
+    let closing = "  ".repeat(indent);
+    match field {
+        Field::Leaf(leaf) => write_string(out, &escape_references(&leaf.value)),
+        Field::Node(map) if map.is_empty() => out.push_str("{}"),
+        Field::List(items) if items.is_empty() => out.push_str("[]"),
+        Field::Node(map) => {
+            out.push_str("{\n");
+            for (idx, (key, child)) in map.iter().enumerate() {
+                out.push_str(&padding);
+                write_string(out, key);
+                out.push_str(": ");
+                write_field(out, child, indent + 1);
+                out.push_str(if idx + 1 < map.len() { ",\n" } else { "\n" });
+            }
+            let _ = write!(out, "{closing}}}");
+        }
+        Field::List(items) => {
+            out.push_str("[\n");
+            for (idx, item) in items.iter().enumerate() {
+                out.push_str(&padding);
+                write_field(out, item, indent + 1);
+                out.push_str(if idx + 1 < items.len() { ",\n" } else { "\n" });
+            }
+            let _ = write!(out, "{closing}]");
+        }
+    }
+}
+
+fn write_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if u32::from(c) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", u32::from(c));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        algorithm::{AlgorithmInstance, PuzzleSource},
+        core::input::processors::parse_puzzle_format,
+        domains::create_registry,
+    };
+
+    const KEY_PATH: &str = r#"
+        title: Test Puzzle
+        author: Someone
+        difficulty: 3
+        config.columns: 2
+        part.a.name: First
+        part.a.step_type: text_step
+        part.a.input: hello
+        part.a.steps: 0__a____ | 1__b__red__
+        part.a.config.rows: 4
+        part.b.name: Second
+        part.b.step_type: text
+        part.b.input: world
+        part.b.steps: 2__c____
+    "#;
+
+    const JSON: &str = r#"{
+        "title": "Test Puzzle",
+        "author": "Someone",
+        "difficulty": 3,
+        "config": { "columns": 2 },
+        "part": {
+            "a": {
+                "name": "First",
+                "step_type": "text_step",
+                "input": "hello",
+                "steps": ["0__a____", { "position": 1, "content": "b", "background": "red" }],
+                "config": { "rows": "4", "unused": null }
+            },
+            "b": {
+                "name": "Second",
+                "step_type": "text",
+                "input": "world",
+                "steps": "2__c____"
+            }
+        }
+    }"#;
+
+    fn load(source: PuzzleSource) -> AlgorithmInstance {
+        AlgorithmInstance::from_source(source, create_registry().domain_registry())
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    #[test]
+    fn test_json_matches_key_path() {
+        let (json_metadata, json_parts) = parse_json_puzzle_format(JSON, None).unwrap();
+        let (metadata, parts) = parse_puzzle_format(KEY_PATH).unwrap();
+        assert_eq!(json_metadata, metadata);
+        assert_eq!(json_parts[0].configuration, parts[0].configuration);
+
+        let json = load(PuzzleSource::String(JSON.to_string()));
+        let key_path = load(PuzzleSource::String(KEY_PATH.to_string()));
+        assert_eq!(json, key_path);
+    }
+
+    #[test]
+    fn test_json_round_trips() {
+        let instance = load(PuzzleSource::String(KEY_PATH.to_string()));
+        let json = instance.to_json_format();
+        assert_eq!(load(PuzzleSource::String(json)), instance);
+    }
+
+    #[test]
+    fn test_json_tags_survive_key_path() {
+        let json = JSON.replace(
+            "\"difficulty\": 3,",
+            "\"difficulty\": 3,\n        \"meta\": { \"tags\": [\"grid\", \"search\"] },",
+        );
+        let instance = load(PuzzleSource::String(json));
+        assert_eq!(instance.metadata.tags(), ["grid", "search"]);
+
+        let key_path = instance.to_puzzle_format();
+        assert!(key_path.contains("meta.tags: grid, search\n"), "{key_path}");
+        let from_key_path = load(PuzzleSource::String(key_path));
+        assert_eq!(from_key_path.metadata.tags(), ["grid", "search"]);
+        assert_eq!(from_key_path.parts, instance.parts);
+
+        let back = load(PuzzleSource::String(from_key_path.to_json_format()));
+        assert_eq!(back.metadata.tags(), ["grid", "search"]);
+        assert_eq!(back, from_key_path);
+    }
+
+    #[test]
+    fn test_json_errors_have_locations() {
+        let err =
+            parse_json_tree("{\n  \"title\": \"x\",\n  \"part\": [1 2]\n}", None).unwrap_err();
+        let span = err.span().unwrap();
+        assert_eq!((span.line, span.column), (3, 14));
+
+        let bad_step = JSON.replace("\"position\": 1", "\"position\": \"x\"");
+        let err = AlgorithmInstance::from_source_strict(
+            PuzzleSource::String(bad_step),
+            create_registry().domain_registry(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ParseError::InvalidStep { index: 1, .. }));
+        assert_eq!(err.span().unwrap().line, 11);
+    }
+
+    #[test]
+    fn test_json_unicode_escapes() {
+        let tree = parse_json_tree(r#"{"a": "\u00e9\ud83d\ude00"}"#, None).unwrap();
+        assert_eq!(tree.get_path(&["a"]).and_then(Field::as_leaf), Some("é😀"));
+        for invalid in [
+            r"\ud83d",
+            r"\ud83dx",
+            r"\ud83d\u0041",
+            r"\ud83d\ud83d",
+            r"\ude00",
+        ] {
+            let err = parse_json_tree(&format!(r#"{{"a": "{invalid}"}}"#), None).unwrap_err();
+            let span = err.span().unwrap();
+            assert_eq!(span.column, 8, "{invalid}: {err}");
+        }
+    }
+
+    #[test]
+    fn test_json_nesting_depth() {
+        let nested = |depth| {
+            format!(
+                "{{\"a\": {}1{}}}",
+                "[".repeat(depth - 1),
+                "]".repeat(depth - 1)
+            )
+        };
+        assert!(parse_json_tree(&nested(MAX_DEPTH), None).is_ok());
+        let err = parse_json_tree(&nested(MAX_DEPTH + 1), None).unwrap_err();
+        assert!(err.to_string().contains("nested deeper"), "{err}");
+        assert!(parse_json_tree(&nested(100_000), None).is_err());
+    }
+
+    #[test]
+    fn test_json_numbers() {
+        for number in ["0", "-0", "12", "-1.5", "0.25e3", "1E+10", "2e-3"] {
+            let tree = parse_json_tree(&format!("{{\"a\": {number}}}"), None).unwrap();
+            assert_eq!(tree.get_path(&["a"]).and_then(Field::as_leaf), Some(number));
+        }
+        for not_number in [
+            "NaN", "inf", "-inf", "Infinity", "01", "+1", "1.", ".5", "1e", "1e+", "0x10", "1.5.2",
+        ] {
+            let err = parse_json_tree(&format!("{{\"a\": {not_number}}}"), None).unwrap_err();
+            assert!(
+                err.to_string().contains("expected a value"),
+                "{not_number}: {err}"
+            );
+        }
+    }
+}
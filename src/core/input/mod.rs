@@ -1,7 +1,23 @@
-use crate::algorithm::PuzzleSource;
+use std::sync::Arc;
+
+use crate::algorithm::{Metadata, PuzzleSource, RawPartMetadata, RawSteps, meta_tags};
+use crate::core::configuration::Configuration;
 use crate::error::ParseError;
+use format::FormatRegistry;
+use indexmap::IndexMap;
+use processors::Field;
 
+pub mod collection;
+pub mod diagnostics;
+pub mod executable;
+pub mod format;
+pub mod interactive;
+pub mod json;
+pub mod network;
 pub mod processors;
+pub mod references;
+pub mod span;
+pub mod writer;
 // ============================================================================
 // INPUT PROCESSING
 // ============================================================================
@@ -11,9 +27,20 @@ pub struct PuzzleMetadata {
     pub description: Option<String>,
     pub author: Option<String>,
     pub difficulty: Option<u8>,
+    /// Free-form `meta.<key>` entries, see [`Metadata::meta`]
+    pub meta: IndexMap<String, Field>,
+    /// The puzzle-wide `config`
+    pub configuration: Arc<Configuration>,
     pub parts_info: Vec<PartMetadata>, // Just the metadata, no steps/state
 }
 
+impl PuzzleMetadata {
+    /// The `meta.tags` entry, see [`Metadata::tags`].
+    pub fn tags(&self) -> Vec<String> {
+        meta_tags(&self.meta)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PartMetadata {
     pub id: String,
@@ -21,51 +48,80 @@ pub struct PartMetadata {
     pub description: Option<String>,
     pub step_type_id: String,
     pub input: String,
-    pub raw_steps: String,
+    /// The part whose final state this part starts from, `input` being empty then
+    pub input_from: Option<String>,
+    pub raw_steps: RawSteps,
+    /// Number of steps, counted without parsing them
+    pub step_count: usize,
 }
+
 pub fn read_source_content(source: PuzzleSource) -> Result<String, ParseError> {
-    let str = match source {
-        PuzzleSource::String(str) => str,
-        PuzzleSource::File(path) => std::fs::read_to_string(path)?,
-        PuzzleSource::Executable(path, args) => {
-            // Execute and capture output
-            let output = std::process::Command::new(path).args(args).output()?;
-            String::from_utf8(output.stdout)?
-        }
-        PuzzleSource::InlineCode(content) => content,
-        PuzzleSource::Network(_url) => {
-            // HTTP request implementation
-            todo!("Network source")
-        }
-        PuzzleSource::Interactive => {
-            // Interactive input
-            todo!("Interactive source")
+    Ok(String::from_utf8(read_source_bytes(source)?)?)
+}
+
+/// Reads the raw content of a source, leaving its decoding to the puzzle format.
+pub fn read_source_bytes(source: PuzzleSource) -> Result<Vec<u8>, ParseError> {
+    let bytes = match source {
+        PuzzleSource::String(str) => str.into_bytes(),
+        PuzzleSource::File(path) => std::fs::read(path)?,
+        PuzzleSource::Executable(executable) => executable.run()?,
+        PuzzleSource::InlineCode(content) => content.into_bytes(),
+        PuzzleSource::Network(network) => network.fetch()?,
+        PuzzleSource::Directory(path) => {
+            return Err(ParseError::InvalidFormat(format!(
+                "'{}' is a directory of puzzles, read it as a PuzzleCollection",
+                path.display()
+            )));
         }
+        PuzzleSource::Interactive => interactive::read_lines(std::io::stdin().lock())?.into_bytes(),
     };
-    Ok(str)
+    Ok(bytes)
+}
+
+/// Reads the metadata and raw parts of a source, in whichever of `formats` it is written.
+pub fn parse_source(
+    source: PuzzleSource,
+    formats: &FormatRegistry,
+) -> Result<(Metadata, Vec<RawPartMetadata>), ParseError> {
+    let name = source.name();
+    formats.parse(&read_source_bytes(source)?, name)
 }
+
 // Lightweight parsing - no step parsing, validation, or state creation
 pub fn get_metadata_from_source(source: PuzzleSource) -> Result<PuzzleMetadata, ParseError> {
-    let raw_content = read_source_content(source)?;
-    let (base_metadata, parts_with_steps) = processors::parse_puzzle_format(&raw_content)?;
+    get_metadata_from_source_with_formats(source, &FormatRegistry::default())
+}
+
+/// Same as [`get_metadata_from_source`], reading formats beyond the built-in ones.
+pub fn get_metadata_from_source_with_formats(
+    source: PuzzleSource,
+    formats: &FormatRegistry,
+) -> Result<PuzzleMetadata, ParseError> {
+    let (base_metadata, parts_with_steps) = parse_source(source, formats)?;
 
     let parts_info = parts_with_steps
         .into_iter()
-        .map(|part_data| PartMetadata {
-            id: part_data.id,
-            display_name: part_data.display_name,
-            description: part_data.description,
-            step_type_id: part_data.raw_step_type_id.to_string(),
-            input: part_data.input_data,
-            raw_steps: part_data.raw_steps_string,
+        .map(|part_data| {
+            Ok(PartMetadata {
+                step_count: part_data.raw_steps.count(&part_data.configuration)?,
+                id: part_data.id,
+                display_name: part_data.display_name,
+                description: part_data.description,
+                step_type_id: part_data.raw_step_type_id.to_string(),
+                input: part_data.input_data,
+                input_from: part_data.input_from.map(|leaf| leaf.value),
+                raw_steps: part_data.raw_steps,
+            })
         })
-        .collect();
+        .collect::<Result<_, ParseError>>()?;
 
     Ok(PuzzleMetadata {
         title: base_metadata.title,
         description: base_metadata.description,
         author: base_metadata.author,
         difficulty: base_metadata.difficulty,
+        meta: base_metadata.meta,
+        configuration: base_metadata.configuration,
         parts_info,
     })
 }
@@ -0,0 +1,443 @@
+use std::{
+    fmt::Write as _,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::error::{NetworkError, NetworkErrorKind, ParseError};
+
+// ============================================================================
+// NETWORK SOURCES
+// ============================================================================
+
+/// Directory of the cache holding downloaded contents, named by their SHA-256.
+const OBJECTS: &str = "objects";
+/// Directory of the cache holding what is known of each URL, named by the SHA-256 of the URL.
+const URLS: &str = "urls";
+
+/// A puzzle downloaded over HTTP(S).
+///
+/// With a cache directory, downloads are kept and revalidated with `If-None-Match` and
+/// `If-Modified-Since`, a server answering `304 Not Modified` getting the cached copy served.
+/// The cached copy is also served when the server cannot be reached or fails with a `5xx` status,
+/// and is the only one read in offline mode. A cache that cannot be read counts as empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkSource {
+    pub url: String,
+    pub cache_dir: Option<PathBuf>,
+    /// Serves from the cache without any request
+    pub offline: bool,
+    /// For the whole request, from connecting to reading the last byte
+    pub timeout: Duration,
+    /// Bytes of content after which the download is abandoned
+    pub max_size: u64,
+}
+
+/// What the cache knows of a URL.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct CacheEntry {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    /// SHA-256 of the content, in hexadecimal
+    content: String,
+}
+
+impl From<String> for NetworkSource {
+    fn from(url: String) -> Self {
+        Self::new(url)
+    }
+}
+
+impl From<&str> for NetworkSource {
+    fn from(url: &str) -> Self {
+        Self::new(url)
+    }
+}
+
+impl NetworkSource {
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+    pub const DEFAULT_MAX_SIZE: u64 = 16 * 1024 * 1024;
+
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            cache_dir: None,
+            offline: false,
+            timeout: Self::DEFAULT_TIMEOUT,
+            max_size: Self::DEFAULT_MAX_SIZE,
+        }
+    }
+
+    pub fn cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(dir.into());
+        self
+    }
+
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = bytes;
+        self
+    }
+
+    /// Returns the content at the URL, from the cache when it is still current.
+    pub fn fetch(&self) -> Result<Vec<u8>, ParseError> {
+        let cached = self.cached();
+        if self.offline {
+            return cached
+                .map(|(_, content)| content)
+                .ok_or_else(|| self.error(NetworkErrorKind::NotCached));
+        }
+
+        let agent: ureq::Agent = ureq::Agent::config_builder()
+            .timeout_global(Some(self.timeout))
+            .http_status_as_error(false)
+            .build()
+            .into();
+        let mut request = agent.get(&self.url);
+        if let Some((entry, _)) = &cached {
+            if let Some(etag) = &entry.etag {
+                request = request.header("If-None-Match", etag);
+            }
+            if let Some(date) = &entry.last_modified {
+                request = request.header("If-Modified-Since", date);
+            }
+        }
+        let mut response = match request.call() {
+            Ok(response) => response,
+            Err(err) => {
+                return match cached {
+                    // The server being out of reach is what the cache is kept for
+                    Some((_, content)) => Ok(content),
+                    None => Err(self.error(NetworkErrorKind::Request(err.to_string()))),
+                };
+            }
+        };
+
+        match (response.status().as_u16(), cached) {
+            (304, Some((_, content))) => Ok(content),
+            (200, cached) => {
+                let read = response
+                    .body_mut()
+                    .with_config()
+                    .limit(self.max_size)
+                    .read_to_vec();
+                let content = match (read, cached) {
+                    (Ok(content), _) => content,
+                    (Err(ureq::Error::BodyExceedsLimit(limit)), _) => {
+                        return Err(self.error(NetworkErrorKind::TooLarge { limit }));
+                    }
+                    // The connection dropping midway is the server being out of reach too
+                    (Err(_), Some((_, content))) => return Ok(content),
+                    (Err(err), None) => {
+                        return Err(self.error(NetworkErrorKind::Request(err.to_string())));
+                    }
+                };
+                let header = |name| {
+                    let value = response.headers().get(name)?.to_str().ok()?;
+                    Some(value.to_string())
+                };
+                let entry = CacheEntry {
+                    etag: header("etag"),
+                    last_modified: header("last-modified"),
+                    content: sha256(&content),
+                };
+                self.store(&entry, &content)?;
+                Ok(content)
+            }
+            (500..=599, Some((_, content))) => Ok(content),
+            (status, _) => Err(self.error(NetworkErrorKind::Status(status))),
+        }
+    }
+
+    /// The cache entry of the URL and its content, if both can be read and the content is intact.
+    fn cached(&self) -> Option<(CacheEntry, Vec<u8>)> {
+        let dir = self.cache_dir.as_ref()?;
+        let entry = fs::read(dir.join(URLS).join(sha256(self.url.as_bytes()))).ok()?;
+        let entry = CacheEntry::parse(&String::from_utf8_lossy(&entry));
+        if entry.content.is_empty() {
+            return None;
+        }
+        let content = fs::read(dir.join(OBJECTS).join(&entry.content)).ok()?;
+        (sha256(&content) == entry.content).then_some((entry, content))
+    }
+
+    fn store(&self, entry: &CacheEntry, content: &[u8]) -> Result<(), ParseError> {
+        let Some(dir) = &self.cache_dir else {
+            return Ok(());
+        };
+        write_atomic(&dir.join(OBJECTS).join(&entry.content), content)?;
+        let key = sha256(self.url.as_bytes());
+        write_atomic(&dir.join(URLS).join(key), entry.write(&self.url).as_bytes())?;
+        Ok(())
+    }
+
+    fn error(&self, kind: NetworkErrorKind) -> ParseError {
+        ParseError::Network(NetworkError {
+            url: self.url.clone(),
+            kind,
+        })
+    }
+}
+
+impl CacheEntry {
+    /// Entries are `key: value` lines, the URL being kept for whoever looks into the cache.
+    fn write(&self, url: &str) -> String {
+        let mut out = format!("url: {url}\ncontent: {}\n", self.content);
+        if let Some(etag) = &self.etag {
+            let _ = writeln!(out, "etag: {etag}");
+        }
+        if let Some(date) = &self.last_modified {
+            let _ = writeln!(out, "last_modified: {date}");
+        }
+        out
+    }
+
+    fn parse(text: &str) -> Self {
+        let mut entry = CacheEntry::default();
+        for (key, value) in text.lines().filter_map(|line| line.split_once(": ")) {
+            match key {
+                "content" => entry.content = value.to_string(),
+                "etag" => entry.etag = Some(value.to_string()),
+                "last_modified" => entry.last_modified = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        entry
+    }
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+/// Writes through a temporary file, so that a reader never sees half a file.
+fn write_atomic(path: &Path, content: &[u8]) -> io::Result<()> {
+    // Tells apart the temporary files of threads writing the same path
+    static WRITES: AtomicUsize = AtomicUsize::new(0);
+    let dir = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(dir)?;
+    let mut temporary = path.as_os_str().to_owned();
+    let write = WRITES.fetch_add(1, Ordering::Relaxed);
+    temporary.push(format!(".{}.{write}.tmp", std::process::id()));
+    fs::write(&temporary, content)?;
+    fs::rename(&temporary, path)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use super::*;
+
+    /// A request as seen by the test server: its path and lowercased headers.
+    type Request = (String, Vec<(String, String)>);
+
+    /// Serves every connection with `respond`, recording the requests. Returns the server's base
+    /// URL.
+    fn serve(respond: fn(&Request, usize) -> String) -> (String, Arc<Mutex<Vec<Request>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let seen = seen.clone();
+                thread::spawn(move || {
+                    let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+                    let line = lines.next().unwrap().unwrap();
+                    let path = line.split(' ').nth(1).unwrap().to_string();
+                    let headers = lines
+                        .map(Result::unwrap)
+                        .take_while(|line| !line.is_empty())
+                        .filter_map(|line| {
+                            let (key, value) = line.split_once(':')?;
+                            Some((key.to_lowercase(), value.trim().to_string()))
+                        })
+                        .collect();
+                    let request = (path, headers);
+                    let count = {
+                        let mut seen = seen.lock().unwrap();
+                        seen.push(request.clone());
+                        seen.iter().filter(|(path, _)| *path == request.0).count()
+                    };
+                    let _ = stream.write_all(respond(&request, count).as_bytes());
+                });
+            }
+        });
+        (url, requests)
+    }
+
+    fn response(status: &str, headers: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n{headers}\r\n{body}",
+            body.len()
+        )
+    }
+
+    fn header<'a>((_, headers): &'a Request, name: &str) -> Option<&'a str> {
+        headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn respond(request: &Request, count: usize) -> String {
+        match request.0.as_str() {
+            "/tagged" if header(request, "if-none-match") == Some("\"v1\"") => {
+                response("304 Not Modified", "", "")
+            }
+            "/tagged" => response("200 OK", "ETag: \"v1\"\r\n", "title: Tagged\n"),
+            "/dated" if header(request, "if-modified-since").is_some() => {
+                response("304 Not Modified", "", "")
+            }
+            "/dated" => response(
+                "200 OK",
+                "Last-Modified: Wed, 21 Oct 2015 07:28:00 GMT\r\n",
+                "title: Dated\n",
+            ),
+            "/flaky" if count > 1 => {
+                thread::sleep(Duration::from_secs(2));
+                response("200 OK", "", "title: Late\n")
+            }
+            "/flaky" => response("200 OK", "", "title: Flaky\n"),
+            "/large" => response("200 OK", "", &"x".repeat(4096)),
+            // Closes the connection before the announced length
+            "/cut" if count > 1 => {
+                "HTTP/1.1 200 OK\r\nContent-Length: 100\r\nConnection: close\r\n\r\ntitle"
+                    .to_string()
+            }
+            "/cut" => response("200 OK", "", "title: Cut\n"),
+            "/down" if count > 1 => response("503 Service Unavailable", "", ""),
+            "/down" => response("200 OK", "", "title: Down\n"),
+            _ => response("404 Not Found", "", ""),
+        }
+    }
+
+    fn kind(result: Result<Vec<u8>, ParseError>) -> NetworkErrorKind {
+        match result {
+            Err(ParseError::Network(error)) => error.kind,
+            other => panic!("expected a network error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_network_source_revalidates_cache() {
+        let (url, requests) = serve(respond);
+        let cache = tempfile::tempdir().unwrap();
+        let source =
+            |path: &str| NetworkSource::new(format!("{url}{path}")).cache_dir(cache.path());
+
+        for _ in 0..2 {
+            assert_eq!(source("/tagged").fetch().unwrap(), b"title: Tagged\n");
+            assert_eq!(source("/dated").fetch().unwrap(), b"title: Dated\n");
+        }
+        let requests = requests.lock().unwrap().clone();
+        assert_eq!(requests.len(), 4);
+        assert_eq!(header(&requests[0], "if-none-match"), None);
+        assert_eq!(header(&requests[2], "if-none-match"), Some("\"v1\""));
+        assert_eq!(
+            header(&requests[3], "if-modified-since"),
+            Some("Wed, 21 Oct 2015 07:28:00 GMT")
+        );
+
+        // Contents are stored once, under their hash
+        let objects = fs::read_dir(cache.path().join(OBJECTS)).unwrap().count();
+        assert_eq!(objects, 2);
+        let object = cache.path().join(OBJECTS).join(sha256(b"title: Tagged\n"));
+        assert!(object.exists());
+
+        // A corrupted copy is not served
+        fs::write(&object, "tampered").unwrap();
+        assert!(matches!(
+            kind(source("/tagged").offline(true).fetch()),
+            NetworkErrorKind::NotCached
+        ));
+        assert_eq!(source("/tagged").fetch().unwrap(), b"title: Tagged\n");
+    }
+
+    #[test]
+    fn test_network_source_offline_and_failures() {
+        let (url, requests) = serve(respond);
+        let cache = tempfile::tempdir().unwrap();
+        let source =
+            |path: &str| NetworkSource::new(format!("{url}{path}")).cache_dir(cache.path());
+
+        assert_eq!(source("/flaky").fetch().unwrap(), b"title: Flaky\n");
+        let offline = source("/flaky").offline(true).fetch().unwrap();
+        assert_eq!(offline, b"title: Flaky\n");
+        assert_eq!(requests.lock().unwrap().len(), 1);
+        assert!(matches!(
+            kind(source("/tagged").offline(true).fetch()),
+            NetworkErrorKind::NotCached
+        ));
+
+        // The server now answers too late, the cached copy is served instead
+        let short = Duration::from_millis(200);
+        let late = source("/flaky").timeout(short).fetch().unwrap();
+        assert_eq!(late, b"title: Flaky\n");
+        let uncached = NetworkSource::from(format!("{url}/flaky")).timeout(short);
+        assert!(matches!(
+            kind(uncached.fetch()),
+            NetworkErrorKind::Request(_)
+        ));
+
+        assert_eq!(source("/cut").fetch().unwrap(), b"title: Cut\n");
+        assert_eq!(source("/cut").fetch().unwrap(), b"title: Cut\n");
+        assert!(matches!(
+            kind(NetworkSource::new(format!("{url}/cut")).fetch()),
+            NetworkErrorKind::Request(_)
+        ));
+
+        assert_eq!(source("/down").fetch().unwrap(), b"title: Down\n");
+        assert_eq!(source("/down").fetch().unwrap(), b"title: Down\n");
+        assert!(matches!(
+            kind(NetworkSource::new(format!("{url}/down")).fetch()),
+            NetworkErrorKind::Status(503)
+        ));
+
+        // An entry that cannot be read is no different from a missing one
+        let entry = cache
+            .path()
+            .join(URLS)
+            .join(sha256(format!("{url}/down").as_bytes()));
+        fs::remove_file(&entry).unwrap();
+        fs::create_dir(&entry).unwrap();
+        assert!(matches!(
+            kind(source("/down").offline(true).fetch()),
+            NetworkErrorKind::NotCached
+        ));
+
+        assert!(matches!(
+            kind(source("/large").max_size(1024).fetch()),
+            NetworkErrorKind::TooLarge { limit: 1024 }
+        ));
+        assert!(matches!(
+            kind(source("/missing").fetch()),
+            NetworkErrorKind::Status(404)
+        ));
+    }
+}
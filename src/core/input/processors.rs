@@ -1,27 +1,75 @@
 // use std::str::pattern::Pattern;
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use indexmap::IndexMap;
 
 use crate::{
-    algorithm::{Metadata, RawPartMetadata},
+    algorithm::{Bookmark, Metadata, RawPartMetadata, RawSteps},
     // domains::step_type_to_id,
-    core::{configuration::Configuration, step::StepAction},
+    core::{
+        configuration::Configuration,
+        input::{
+            references::resolve_references,
+            span::{SourceText, Span},
+        },
+        step::{
+            StepAction,
+            binary::{StepContainer, is_step_container},
+        },
+    },
     error::ParseError,
 };
 
-pub type StepParserFn = fn(&str) -> Result<Box<dyn StepAction>, ParseError>;
+pub type StepParserFn = fn(&str, &Configuration) -> Result<Box<dyn StepAction>, ParseError>;
+pub type StructuredStepParserFn = fn(&Field) -> Result<Box<dyn StepAction>, ParseError>;
+pub type StepDecoderFn = fn(&[u8]) -> Result<Box<dyn StepAction>, ParseError>;
 #[derive(Debug, Clone, PartialEq)]
 pub enum Field {
-    Leaf(String),                 // Terminal value
-    Node(HashMap<String, Field>), // Nested
+    Leaf(Leaf),                    // Terminal value
+    Node(IndexMap<String, Field>), // Nested, in source order
+    List(Vec<Field>),              // Sequence, only produced by formats that have one (JSON)
+}
+
+/// A terminal value, along with where its key and value sit in the source.
+/// Spans are only diagnostic information and take no part in comparisons.
+#[derive(Debug, Clone, Default)]
+pub struct Leaf {
+    pub value: String,
+    pub span: Span,
+    pub key_span: Span,
+}
+
+impl Leaf {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self {
+            value: value.into(),
+            ..Default::default()
+        }
+    }
+}
+
+impl PartialEq for Leaf {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
 }
 
 impl Field {
+    /// Creates a leaf with detached spans.
+    pub fn leaf(value: impl Into<String>) -> Self {
+        Field::Leaf(Leaf::new(value))
+    }
+
     /// Parse a path like part.tokenize.name under the form "[part, tokenize, name]"
     pub fn get_path(&self, path: &[&str]) -> Option<&Field> {
         match (self, path) {
-            (Field::Leaf(_), []) => Some(self),
-            (Field::Leaf(_), _) => None, // Can't traverse into leaf
+            (Field::Leaf(_) | Field::List(_), []) => Some(self),
+            (Field::Leaf(_) | Field::List(_), _) => None, // Can't traverse into leaf
             (Field::Node(_), []) => Some(self),
             (Field::Node(map), [head, tail @ ..]) => map.get(*head)?.get_path(tail),
         }
@@ -30,41 +78,143 @@ impl Field {
     /// Get leaf value if this is a leaf
     pub fn as_leaf(&self) -> Option<&str> {
         match self {
-            Field::Leaf(value) => Some(value),
-            Field::Node(_) => None,
+            Field::Leaf(leaf) => Some(&leaf.value),
+            Field::Node(_) | Field::List(_) => None,
+        }
+    }
+
+    /// Span of the value, if this is a leaf
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            Field::Leaf(leaf) => Some(&leaf.span),
+            Field::Node(_) | Field::List(_) => None,
+        }
+    }
+
+    /// Key span of the leaf appearing first in the source, used to point at a whole node.
+    pub fn first_key_span(&self) -> Option<&Span> {
+        match self {
+            Field::Leaf(leaf) => Some(&leaf.key_span),
+            Field::Node(map) => map
+                .values()
+                .filter_map(|field| field.first_key_span())
+                .min_by_key(|span| span.start),
+            Field::List(items) => items.first().and_then(|field| field.first_key_span()),
         }
     }
 
     /// Set a value at a dotted path
-    pub fn set_path(&mut self, path: &[&str], value: String) {
+    pub fn set_path(&mut self, path: &[&str], value: Leaf) {
         match (self, path) {
             (_, []) => (), // Invalid: empty path
-            (field @ Field::Leaf(_), [_single]) => {
+            (field @ (Field::Leaf(_) | Field::List(_)), [_single]) => {
                 *field = Field::Leaf(value);
             }
             (Field::Node(map), [single]) => {
                 map.insert(single.to_string(), Field::Leaf(value));
             }
-            (field @ Field::Leaf(_), path) => {
+            (field @ (Field::Leaf(_) | Field::List(_)), path) => {
                 // Convert leaf to node to accommodate nested path
-                *field = Field::Node(HashMap::new());
+                *field = Field::Node(IndexMap::new());
                 field.set_path(path, value);
             }
             (Field::Node(map), [head, tail @ ..]) => {
                 let entry = map
                     .entry(head.to_string())
-                    .or_insert_with(|| Field::Node(HashMap::new()));
+                    .or_insert_with(|| Field::Node(IndexMap::new()));
                 entry.set_path(tail, value);
             }
         }
     }
 }
-const COMMENT_SIGN: char = '#';
+pub(crate) const COMMENT_SIGN: char = '#';
+/// Keys understood at the root of a puzzle.
+pub(crate) const TOP_LEVEL_KEYS: &[&str] = &[
+    "title",
+    "author",
+    "difficulty",
+    "description",
+    "meta",
+    "config",
+    "macro",
+    "part",
+];
+/// Keys understood under `part.<id>`.
+pub(crate) const PART_KEYS: &[&str] = &[
+    "name",
+    "step_type",
+    "input",
+    "steps",
+    "input_file",
+    "input_from",
+    "steps_file",
+    "description",
+    "bookmarks",
+    "config",
+    "order",
+];
+/// Key of the directive pulling another file into a key-path puzzle.
+pub(crate) const INCLUDE_KEY: &str = "include";
+/// Opens a multi-line block value. An optional tag may follow (`<<<END`), in which case the block
+/// is closed by `>>>END` instead of `>>>`.
+pub(crate) const BLOCK_START: &str = "<<<";
+pub(crate) const BLOCK_END: &str = ">>>";
 /// Entry point for the parser, once transformed into string.
+///
+/// Every non-empty, non-comment line is a `key.path: value` pair. A value of `<<<` opens a block:
+/// every following line is kept verbatim (whitespace included) until a line containing only
+/// `>>>`, and the lines are joined with `\n`.
+///
+/// ```text
+/// part.day1.input: <<<
+/// 467..114..
+/// ...*......
+/// >>>
+/// ```
+///
+/// An `include: path` line reads another key-path file as if its lines were written in place of
+/// the directive: a key set both in the included file and in the including one takes the value
+/// written last. Relative paths are resolved against the directory of the including file, so
+/// sources that are not files, the output of an executable among them, can only include absolute
+/// paths.
+///
+/// Once every line is read, values may use others with `${part.a.input}` or `${config.rows}`;
+/// `$${` stands for a literal `${`.
 pub fn parse_puzzle_format(content: &str) -> Result<(Metadata, Vec<RawPartMetadata>), ParseError> {
-    let mut root = Field::Node(HashMap::new());
+    parse_named_puzzle_format(content, None)
+}
+
+/// Same as [`parse_puzzle_format`], naming the source (usually its file path) in diagnostics.
+pub fn parse_named_puzzle_format(
+    content: &str,
+    name: Option<String>,
+) -> Result<(Metadata, Vec<RawPartMetadata>), ParseError> {
+    let root = parse_puzzle_tree(content, name)?;
+    extract_puzzle(&root)
+}
+
+/// Builds the raw `Field` tree of a key-path puzzle, without interpreting any key.
+pub fn parse_puzzle_tree(content: &str, name: Option<String>) -> Result<Field, ParseError> {
+    let mut root = Field::Node(IndexMap::new());
+    let mut including = Vec::new();
+    if let Some(path) = name.as_deref().and_then(|name| fs::canonicalize(name).ok()) {
+        including.push(path);
+    }
+    parse_puzzle_lines(&mut root, SourceText::new(name, content), &mut including)?;
+    Ok(root)
+}
 
-    for line in content.lines() {
+/// Sets every key of `source` on `root`. `including` holds the canonical paths of the files
+/// currently being read, to catch include cycles.
+fn parse_puzzle_lines(
+    root: &mut Field,
+    source: Arc<SourceText>,
+    including: &mut Vec<PathBuf>,
+) -> Result<(), ParseError> {
+    let content = source.text.as_str();
+
+    let mut lines = content.lines().enumerate();
+    while let Some((index, line)) = lines.next() {
         let line = line.trim();
         if line.is_empty() || line.starts_with(COMMENT_SIGN) {
             continue;
@@ -72,18 +222,191 @@ pub fn parse_puzzle_format(content: &str) -> Result<(Metadata, Vec<RawPartMetada
 
         if let Some((key_path, value)) = line.split_once(':') {
             let path: Vec<&str> = key_path.split('.').map(|s| s.trim()).collect();
-            root.set_path(&path, value.trim().to_string());
+            let key_span = Span::of_slice(&source, key_path.trim());
+            let value = value.trim();
+            if path == [INCLUDE_KEY] {
+                let span = Span::of_slice(&source, value);
+                include_file(root, &source, value, including).map_err(|error| error.at(span))?;
+                continue;
+            }
+            let (value, span) = match value.strip_prefix(BLOCK_START) {
+                Some(tag) => read_block(&mut lines, tag.trim(), &source).ok_or_else(|| {
+                    ParseError::UnterminatedBlock {
+                        key: key_path.trim().to_string(),
+                        line: index + 1,
+                    }
+                    .at(key_span.clone())
+                })?,
+                None => (value.to_string(), Span::of_slice(&source, value)),
+            };
+            root.set_path(
+                &path,
+                Leaf {
+                    value,
+                    span,
+                    key_span,
+                },
+            );
         }
     }
+    Ok(())
+}
+
+fn include_file(
+    root: &mut Field,
+    source: &SourceText,
+    include: &str,
+    including: &mut Vec<PathBuf>,
+) -> Result<(), ParseError> {
+    let path = resolve_relative(Some(source), include)?;
+    let read_error = |error| ParseError::Include {
+        path: path.display().to_string(),
+        error,
+    };
+    let canonical = fs::canonicalize(&path).map_err(read_error)?;
+    if let Some(first) = including.iter().position(|p| *p == canonical) {
+        let mut chain: Vec<String> = including[first..]
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect();
+        chain.push(canonical.display().to_string());
+        return Err(ParseError::IncludeCycle(chain));
+    }
+    let content = fs::read_to_string(&path).map_err(read_error)?;
 
+    including.push(canonical);
+    let included = SourceText::new(Some(path.display().to_string()), content);
+    let result = parse_puzzle_lines(root, included, including);
+    including.pop();
+    result
+}
+
+/// Resolves a path written in `source` relative to the file `source` was read from. Relative
+/// paths need the source to be a file.
+pub(crate) fn resolve_relative(
+    source: Option<&SourceText>,
+    path: &str,
+) -> Result<PathBuf, ParseError> {
+    let source_file = source
+        .and_then(|source| source.name.as_deref())
+        .map(Path::new)
+        .filter(|source| source.is_file());
+    match source_file {
+        Some(file) => Ok(file.parent().unwrap_or(Path::new("")).join(path)),
+        None if Path::new(path).is_relative() => Err(ParseError::Include {
+            path: path.to_string(),
+            error: std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "relative paths need the puzzle to be read from a file",
+            ),
+        }),
+        None => Ok(PathBuf::from(path)),
+    }
+}
+
+/// Reads a file referenced by a puzzle, such as a `steps_file`.
+pub(crate) fn read_external_file(path: &Path) -> Result<Arc<SourceText>, ParseError> {
+    let content = fs::read_to_string(path).map_err(|error| ParseError::Include {
+        path: path.display().to_string(),
+        error,
+    })?;
+    Ok(SourceText::new(Some(path.display().to_string()), content))
+}
+
+/// The content of a `steps_file`.
+pub(crate) enum StepsFile {
+    /// One step per line
+    Text(Arc<SourceText>),
+    Binary(StepContainer),
+}
+
+/// Reads a `steps_file`, telling binary step containers from text by their header.
+pub(crate) fn read_steps_file(path: &Path) -> Result<StepsFile, ParseError> {
+    let include_error = |error| ParseError::Include {
+        path: path.display().to_string(),
+        error,
+    };
+    let bytes = fs::read(path).map_err(include_error)?;
+    if is_step_container(&bytes) {
+        return Ok(StepsFile::Binary(StepContainer::new(bytes)?));
+    }
+    let content = String::from_utf8(bytes)
+        .map_err(|error| include_error(io::Error::new(io::ErrorKind::InvalidData, error)))?;
+    Ok(StepsFile::Text(SourceText::new(
+        Some(path.display().to_string()),
+        content,
+    )))
+}
+
+/// Extracts the metadata and parts out of a puzzle tree.
+pub(crate) fn extract_puzzle(root: &Field) -> Result<(Metadata, Vec<RawPartMetadata>), ParseError> {
+    let (root, errors) = resolve_references(root);
+    if let Some(error) = errors.into_iter().next() {
+        return Err(error);
+    }
     // Extract structured data
-    let metadata = extract_metadata(&root)?;
-    let configuration = Arc::new(extract_config(&root, None));
-    let parts = extract_parts_with_steps(&root, &configuration)?;
-    // TODO: store the config, maybe on the metadata, or add it as return
+    let mut metadata = extract_metadata(&root)?;
+    metadata.configuration =
+        Arc::new(extract_config(&root, None).with_macros(extract_macros(&root)?));
+    let parts = extract_parts_with_steps(&root, &metadata.configuration)?;
+    let sources: Vec<(&str, Option<&Leaf>)> = parts
+        .iter()
+        .map(|part| (part.id.as_str(), part.input_from.as_ref()))
+        .collect();
+    check_input_sources(&sources)?;
     Ok((metadata, parts))
 }
 
+/// Checks that the parts named by `input_from` exist and do not take their input from each
+/// other, which could never be played. `parts` holds the id and `input_from` of every part.
+pub(crate) fn check_input_sources(parts: &[(&str, Option<&Leaf>)]) -> Result<(), ParseError> {
+    let source_of = |id: &str| parts.iter().find(|(part, _)| *part == id);
+    for (id, source) in parts {
+        let mut chain = vec![*id];
+        let mut source = *source;
+        while let Some(leaf) = source {
+            let value = leaf.value.as_str();
+            if let Some(start) = chain.iter().position(|id| *id == value) {
+                let mut cycle: Vec<String> =
+                    chain[start..].iter().map(|id| id.to_string()).collect();
+                cycle.push(value.to_string());
+                return Err(ParseError::InputCycle(cycle).at(leaf.span.clone()));
+            }
+            let (next, next_source) = source_of(value).ok_or_else(|| {
+                ParseError::UnknownInputPart(value.to_string()).at(leaf.span.clone())
+            })?;
+            chain.push(next);
+            source = *next_source;
+        }
+    }
+    Ok(())
+}
+
+/// Collects the lines of a block value up to its closing marker, along with the span they cover.
+/// Returns `None` if the content ends before the block is closed.
+fn read_block<'a>(
+    lines: &mut impl Iterator<Item = (usize, &'a str)>,
+    tag: &str,
+    source: &Arc<SourceText>,
+) -> Option<(String, Span)> {
+    let mut block: Vec<&str> = Vec::new();
+    for (_, line) in lines {
+        if line.trim().strip_prefix(BLOCK_END) == Some(tag) {
+            let span = match (block.first(), block.last()) {
+                (Some(first), Some(last)) => {
+                    let start = Span::of_slice(source, first);
+                    let end = Span::of_slice(source, last);
+                    Span::new(source, start.start..end.end)
+                }
+                _ => Span::of_slice(source, line),
+            };
+            return Some((block.join("\n"), span));
+        }
+        block.push(line);
+    }
+    None
+}
+
 fn extract_metadata(root: &Field) -> Result<Metadata, ParseError> {
     let mut metadata = Metadata::default();
 
@@ -99,8 +422,18 @@ fn extract_metadata(root: &Field) -> Result<Metadata, ParseError> {
         metadata.difficulty = difficulty_str.parse().ok();
     }
 
+    if let Some(description) = root.get_path(&["description"]).and_then(|f| f.as_leaf()) {
+        metadata.description = Some(description.to_string());
+    }
+
+    if let Some(Field::Node(meta)) = root.get_path(&["meta"]) {
+        metadata.meta = meta.clone();
+    }
+
     Ok(metadata)
 }
+/// Parts come out in the order they first appear in the source, unless `part.<id>.order` says
+/// otherwise: parts with an `order` come first, sorted by it, followed by the others.
 fn extract_parts_with_steps(
     root: &Field,
     parent_config: &Arc<Configuration>,
@@ -109,67 +442,480 @@ fn extract_parts_with_steps(
 
     if let Some(Field::Node(part_map)) = root.get_path(&["part"]) {
         for (part_id, part_field) in part_map {
-            let part_metadata =
-                extract_single_part_with_steps(part_id, part_field, &parent_config)?;
+            let part_metadata = extract_single_part_with_steps(part_id, part_field, parent_config)?;
 
-            parts.push(part_metadata);
+            parts.push((extract_part_order(part_field)?, part_metadata));
         }
     }
+    // Stable, so that ties and unordered parts keep their source order
+    parts.sort_by_key(|(order, _)| (order.is_none(), *order));
+
+    Ok(parts.into_iter().map(|(_, part)| part).collect())
+}
 
-    Ok(parts)
+pub(crate) fn extract_part_order(part_field: &Field) -> Result<Option<i64>, ParseError> {
+    let Some(order) = part_field.get_path(&["order"]) else {
+        return Ok(None);
+    };
+    let value = order.as_leaf().unwrap_or_default();
+    value.trim().parse().map(Some).map_err(|_| {
+        ParseError::InvalidFormat(format!("part order must be an integer, got '{value}'")).at(order
+            .span()
+            .or(order.first_key_span())
+            .cloned()
+            .unwrap_or_default())
+    })
 }
 
-fn extract_config(root: &Field, parent: Option<Arc<Configuration>>) -> Configuration {
+/// The `bookmarks` of a part, mapping names to step indices.
+fn extract_bookmarks(part_field: &Field) -> Result<Vec<(Bookmark, Span)>, ParseError> {
+    let Some(bookmarks) = part_field.get_path(&["bookmarks"]) else {
+        return Ok(Vec::new());
+    };
+    let Field::Node(bookmarks) = bookmarks else {
+        let span = bookmarks.first_key_span().cloned().unwrap_or_default();
+        return Err(ParseError::InvalidFormat(
+            "bookmarks should map names to step indices".to_string(),
+        )
+        .at(span));
+    };
+    bookmarks
+        .iter()
+        .map(|(name, field)| {
+            let span = field
+                .span()
+                .or(field.first_key_span())
+                .cloned()
+                .unwrap_or_default();
+            let value = field.as_leaf().unwrap_or_default();
+            let step = value.trim().parse().map_err(|_| {
+                ParseError::InvalidFormat(format!(
+                    "bookmark '{name}' must be a step index, got '{value}'"
+                ))
+                .at(span.clone())
+            })?;
+            let bookmark = Bookmark {
+                name: name.clone(),
+                step,
+            };
+            Ok((bookmark, span))
+        })
+        .collect()
+}
+
+/// Steps are either a single `|`-separated leaf, or a list with one entry per step.
+pub(crate) fn extract_raw_steps(part_id: &str, steps: &Field) -> Result<RawSteps, ParseError> {
+    match steps {
+        Field::Leaf(leaf) => Ok(RawSteps::Delimited(leaf.clone())),
+        Field::List(items) => Ok(RawSteps::List(items.clone())),
+        Field::Node(_) => {
+            let span = steps.first_key_span().cloned().unwrap_or_default();
+            Err(ParseError::MissingPartField(part_id.to_string(), "steps").at(span))
+        }
+    }
+}
+
+/// A part value written in the puzzle itself, or kept in a file of its own.
+enum PartValue<'a> {
+    Inline(&'a Field),
+    File(&'a Field),
+}
+
+/// Resolves the path held by a `*_file` key, relative to the file the key is written in.
+fn referenced_path(field: &Field) -> Result<PathBuf, ParseError> {
+    let Field::Leaf(leaf) = field else {
+        let span = field.first_key_span().cloned().unwrap_or_default();
+        return Err(ParseError::InvalidFormat("expected a file path".to_string()).at(span));
+    };
+    resolve_relative(leaf.span.source.as_deref(), leaf.value.trim())
+        .map_err(|error| error.at(leaf.span.clone()))
+}
+
+/// The `macro.<name>` step lists of a puzzle.
+pub(crate) fn extract_macros(root: &Field) -> Result<IndexMap<String, Leaf>, ParseError> {
+    let Some(Field::Node(macros)) = root.get_path(&["macro"]) else {
+        return Ok(IndexMap::new());
+    };
+    macros
+        .iter()
+        .map(|(name, field)| match field {
+            Field::Leaf(leaf) => Ok((name.clone(), leaf.clone())),
+            field => {
+                let span = field.first_key_span().cloned().unwrap_or_default();
+                Err(
+                    ParseError::InvalidFormat(format!("macro '{name}' should be a step list"))
+                        .at(span),
+                )
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn extract_config(root: &Field, parent: Option<Arc<Configuration>>) -> Configuration {
     if let Some(Field::Node(configuration)) = root.get_path(&["config"]) {
         return Configuration::with_parent(configuration.clone(), parent);
     }
-    Configuration::with_parent(HashMap::new(), parent)
+    Configuration::with_parent(IndexMap::new(), parent)
 }
 
-fn extract_single_part_with_steps(
+pub(crate) fn extract_single_part_with_steps(
     part_id: &str,
     part_field: &Field,
     parent_config: &Arc<Configuration>,
 ) -> Result<RawPartMetadata, ParseError> {
     let Field::Node(fields) = part_field else {
-        return Err(ParseError::InvalidPartStructure(part_id.to_string()));
+        let span = part_field.first_key_span().cloned().unwrap_or_default();
+        return Err(ParseError::InvalidPartStructure(part_id.to_string()).at(span));
+    };
+    let required = |key: &'static str| {
+        fields
+            .get(key)
+            .filter(|f| f.as_leaf().is_some())
+            .ok_or_else(|| {
+                let span = part_field.first_key_span().cloned().unwrap_or_default();
+                ParseError::MissingPartField(part_id.to_string(), key).at(span)
+            })
     };
 
-    let name = fields
-        .get("name")
-        .and_then(|f| f.as_leaf())
-        .ok_or_else(|| ParseError::MissingPartField(part_id.to_string(), "name"))?;
-
-    let step_type = fields
-        .get("step_type")
-        .and_then(|f| f.as_leaf())
-        .ok_or_else(|| ParseError::MissingPartField(part_id.to_string(), "step_type"))?;
-
-    let input_data = fields
-        .get("input")
-        .and_then(|f| f.as_leaf())
-        .ok_or_else(|| ParseError::MissingPartField(part_id.to_string(), "input"))?
-        .to_string();
-
-    let steps_str = fields
-        .get("steps")
-        .and_then(|f| f.as_leaf())
-        .ok_or_else(|| ParseError::MissingPartField(part_id.to_string(), "steps"))?;
+    let name = required("name")?;
+    let step_type = required("step_type")?;
+    // `input` and `steps` can also be kept in files of their own, but not both ways at once
+    let inline_or_file = |key: &'static str, file_key: &'static str| {
+        let span = || part_field.first_key_span().cloned().unwrap_or_default();
+        match (fields.get(key), fields.get(file_key)) {
+            (Some(_), Some(file)) => Err(ParseError::InvalidFormat(format!(
+                "part {part_id} sets both {key} and {file_key}"
+            ))
+            .at(file.first_key_span().cloned().unwrap_or_else(span))),
+            (Some(inline), None) => Ok(PartValue::Inline(inline)),
+            (None, Some(file)) => Ok(PartValue::File(file)),
+            (None, None) => Err(ParseError::MissingPartField(part_id.to_string(), key).at(span())),
+        }
+    };
+    // A part can also start from the final state of another part instead
+    let input_from = match fields.get("input_from") {
+        Some(field) => {
+            let span = field
+                .first_key_span()
+                .cloned()
+                .unwrap_or_else(|| part_field.first_key_span().cloned().unwrap_or_default());
+            if let Some(key) = ["input", "input_file"]
+                .into_iter()
+                .find(|k| fields.contains_key(*k))
+            {
+                return Err(ParseError::InvalidFormat(format!(
+                    "part {part_id} sets both {key} and input_from"
+                ))
+                .at(span));
+            }
+            let Field::Leaf(leaf) = field else {
+                return Err(ParseError::InvalidFormat("expected a part id".to_string()).at(span));
+            };
+            Some(Leaf {
+                value: leaf.value.trim().to_string(),
+                ..leaf.clone()
+            })
+        }
+        None => None,
+    };
+    let written_path = |field: &Field| field.as_leaf().map(|path| path.trim().to_string());
+    let mut input_file = None;
+    let input_data = match input_from {
+        Some(_) => String::new(),
+        None => match inline_or_file("input", "input_file")? {
+            PartValue::Inline(_) => required("input")?.as_leaf().unwrap_or_default().to_string(),
+            PartValue::File(file) => {
+                input_file = written_path(file);
+                let span = file.span().cloned().unwrap_or_default();
+                read_external_file(&referenced_path(file)?)
+                    .map_err(|error| error.at(span))?
+                    .text
+                    .clone()
+            }
+        },
+    };
+    let mut steps_file = None;
+    let steps = match inline_or_file("steps", "steps_file")? {
+        PartValue::Inline(steps) => extract_raw_steps(part_id, steps)?,
+        PartValue::File(file) => {
+            steps_file = written_path(file);
+            RawSteps::File {
+                path: referenced_path(file)?,
+                span: file.span().cloned().unwrap_or_default(),
+            }
+        }
+    };
     let configuration = extract_config(part_field, Some(parent_config.clone()));
 
     // Create PartInfo without steps (they'll be parsed later with proper input context)
     let part_metadata = RawPartMetadata {
         id: part_id.to_string(),
-        display_name: name.to_string(),
+        display_name: name.as_leaf().unwrap_or_default().to_string(),
         configuration,
         description: fields
             .get("description")
             .and_then(|f| f.as_leaf())
             .map(String::from),
-        input_data,                              // Store in PartInfo too for easy access
-        raw_steps_string: steps_str.to_string(), // Will be populated later
-        raw_step_type_id: step_type.to_string(),
+        // Store in PartInfo too for easy access
+        input_data,
+        // Will be parsed later
+        raw_steps: steps,
+        raw_step_type_id: step_type.as_leaf().unwrap_or_default().to_string(),
+        step_type_span: step_type.span().cloned().unwrap_or_default(),
+        bookmarks: extract_bookmarks(part_field)?,
+        input_from,
+        input_file,
+        steps_file,
     };
 
     Ok(part_metadata)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        algorithm::{AlgorithmInstance, PuzzleSource},
+        core::input::get_metadata_from_source,
+        domains::create_registry,
+    };
+
+    #[test]
+    fn test_block_values_keep_whitespace() {
+        let content = "title: Blocks\n\
+            part.grid.name: Grid\n\
+            part.grid.step_type: text_step\n\
+            part.grid.input: <<<\n\
+            467..114..\n\
+            \x20 ...*......\n\
+            \n\
+            >>>\n\
+            part.grid.steps: <<<END\n\
+            0__a____ |\n\
+            1__b____\n\
+            >>>END\n\
+            part.grid.config.legend: <<<\n\
+            \t. is empty\n\
+            >>>\n";
+        let (_, parts) = parse_puzzle_format(content).unwrap();
+        let part = &parts[0];
+        assert_eq!(part.input_data, "467..114..\n  ...*......\n");
+        assert_eq!(part.raw_steps.to_string(), "0__a____ |\n1__b____");
+        assert_eq!(
+            part.configuration.get("legend").and_then(|f| f.as_leaf()),
+            Some("\t. is empty")
+        );
+    }
+
+    #[test]
+    fn test_unterminated_block() {
+        let content = "title: Blocks\n\npart.grid.input: <<<\nabc\n";
+        let result = parse_puzzle_format(content);
+        assert!(matches!(
+            result.as_ref().map_err(ParseError::inner),
+            Err(ParseError::UnterminatedBlock { line: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn test_parts_keep_source_order() {
+        let part = |id: &str| {
+            format!(
+                "part.{id}.name: {id}\npart.{id}.step_type: text\n\
+                 part.{id}.input: a\npart.{id}.steps: 0__b____\n"
+            )
+        };
+        let ids = ["zeta", "alpha", "mu", "beta", "omega"];
+        let content: String = ids.iter().map(|id| part(id)).collect();
+        for _ in 0..10 {
+            let (_, parts) = parse_puzzle_format(&content).unwrap();
+            let parsed: Vec<&str> = parts.iter().map(|p| p.id.as_str()).collect();
+            assert_eq!(parsed, ids);
+        }
+
+        let content = format!("{content}part.mu.order: 2\npart.omega.order: -1\n");
+        let (_, parts) = parse_puzzle_format(&content).unwrap();
+        let parsed: Vec<&str> = parts.iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(parsed, ["omega", "mu", "zeta", "alpha", "beta"]);
+
+        let content = format!("{content}part.beta.order: first\n");
+        assert!(parse_puzzle_format(&content).is_err());
+    }
+
+    #[test]
+    fn test_leaves_keep_spans() {
+        let content = "title: Spans\n\
+            part.a.steps: <<<\n  0__x____\n1__y____\n>>>\n\
+            part.a.name: A\npart.a.step_type: text\npart.a.input: xy\n";
+        let (_, parts) = parse_puzzle_format(content).unwrap();
+        let RawSteps::Delimited(steps) = &parts[0].raw_steps else {
+            panic!("expected a delimited step list");
+        };
+        let span = &steps.span;
+        assert_eq!((span.line, span.column), (3, 1));
+        assert_eq!(&content[span.start..span.end], "  0__x____\n1__y____");
+
+        let step = span.sub_span(11..19);
+        assert_eq!((step.line, step.column), (4, 1));
+    }
+
+    fn write_files(files: &[(&str, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, content) in files {
+            let path = dir.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    fn parse_file(path: &Path) -> Result<Field, ParseError> {
+        let content = fs::read_to_string(path).unwrap();
+        parse_puzzle_tree(&content, Some(path.display().to_string()))
+    }
+
+    #[test]
+    fn test_include_merges_in_place() {
+        let dir = write_files(&[
+            (
+                "main.sf",
+                "title: Main\nconfig.rows: 1\ninclude: shared/common.sf\nconfig.columns: 9\n",
+            ),
+            (
+                "shared/common.sf",
+                "config.rows: 5\nconfig.columns: 5\ninclude: input.sf\n",
+            ),
+            ("shared/input.sf", "part.a.input: <<<\nab\ncd\n>>>\n"),
+        ]);
+        let root = parse_file(&dir.path().join("main.sf")).unwrap();
+
+        let value = |path: &[&str]| root.get_path(path).and_then(Field::as_leaf).unwrap();
+        // Included keys override earlier lines and are overridden by later ones
+        assert_eq!(value(&["config", "rows"]), "5");
+        assert_eq!(value(&["config", "columns"]), "9");
+        assert_eq!(value(&["part", "a", "input"]), "ab\ncd");
+        let span = root
+            .get_path(&["part", "a", "input"])
+            .unwrap()
+            .span()
+            .unwrap();
+        assert!(span.source_name().ends_with("input.sf"));
+    }
+
+    #[test]
+    fn test_include_errors() {
+        let dir = write_files(&[
+            ("a.sf", "title: A\ninclude: b.sf\n"),
+            ("b.sf", "include: a.sf\n"),
+            ("bad.sf", "title: Bad\ninclude: part.sf\n"),
+            (
+                "part.sf",
+                "\npart.a.name: A\npart.a.input: <<<\nnever closed\n",
+            ),
+        ]);
+        let err = parse_file(&dir.path().join("a.sf")).unwrap_err();
+        let ParseError::IncludeCycle(chain) = err.inner() else {
+            panic!("expected an include cycle, got {err}");
+        };
+        assert_eq!(chain.len(), 3);
+        assert!(err.span().unwrap().source_name().ends_with("b.sf"));
+
+        let err = parse_file(&dir.path().join("bad.sf")).unwrap_err();
+        let span = err.span().unwrap();
+        assert!(span.source_name().ends_with("part.sf"));
+        assert_eq!(span.line, 3);
+
+        let err = parse_puzzle_tree("include: missing.sf", None).unwrap_err();
+        assert!(matches!(err.inner(), ParseError::Include { .. }));
+    }
+
+    #[test]
+    fn test_input_and_steps_files() {
+        let puzzle = "title: Files\n\
+            part.a.name: A\n\
+            part.a.step_type: text\n\
+            part.a.input_file: data/input.txt\n\
+            part.a.steps_file: data/steps.txt\n";
+        let dir = write_files(&[
+            ("day1/puzzle.sf", puzzle),
+            ("day1/data/input.txt", "hello world\n"),
+            ("day1/data/steps.txt", "0__a____\n\n1__b____\nnot a step\n"),
+            ("day1/both.sf", &format!("{puzzle}part.a.steps: 0__a____\n")),
+        ]);
+        let source = |name: &str| PuzzleSource::File(dir.path().join("day1").join(name));
+
+        // Counting does not parse the steps, so the broken one is counted too
+        let metadata = get_metadata_from_source(source("puzzle.sf")).unwrap();
+        assert_eq!(metadata.parts_info[0].input, "hello world\n");
+        assert_eq!(metadata.parts_info[0].step_count, 3);
+
+        // The steps file is only read once the steps are needed
+        let registry = create_registry();
+        assert!(
+            AlgorithmInstance::from_source(source("puzzle.sf"), registry.domain_registry()).is_ok()
+        );
+        let err =
+            AlgorithmInstance::from_source_strict(source("puzzle.sf"), registry.domain_registry())
+                .unwrap_err();
+        assert!(matches!(err, ParseError::InvalidStep { index: 2, .. }));
+        let span = err.span().unwrap();
+        assert!(span.source_name().ends_with("steps.txt"));
+        assert_eq!(span.line, 4);
+
+        let err = get_metadata_from_source(source("both.sf")).unwrap_err();
+        assert!(matches!(err.inner(), ParseError::InvalidFormat(_)));
+    }
+
+    #[test]
+    fn test_input_from_other_parts() {
+        let puzzle = |b_input: &str, c_input: &str| {
+            format!(
+                "title: Chained\n\
+                part.a.name: A\n\
+                part.a.step_type: text\n\
+                part.a.input: a_b\n\
+                part.a.steps: 0__x____\n\
+                part.b.name: B\n\
+                part.b.step_type: text\n\
+                part.b.{b_input}\n\
+                part.b.steps: 1__y____\n\
+                part.c.name: C\n\
+                part.c.step_type: text\n\
+                part.c.{c_input}\n\
+                part.c.steps: 1__y____\n"
+            )
+        };
+        let load = |content: String| get_metadata_from_source(PuzzleSource::String(content));
+
+        let metadata = load(puzzle("input_from: a", "input_from: b")).unwrap();
+        assert_eq!(metadata.parts_info[1].input_from.as_deref(), Some("a"));
+        assert_eq!(metadata.parts_info[1].input, "");
+
+        let err = load(puzzle("input_from: c", "input_from: b")).unwrap_err();
+        let ParseError::InputCycle(cycle) = err.inner() else {
+            panic!("unexpected error {err:?}");
+        };
+        assert_eq!(cycle, &["b", "c", "b"]);
+        assert_eq!(err.span().unwrap().line, 12);
+        let err = load(puzzle("input_from: d", "input: c")).unwrap_err();
+        assert!(matches!(err.inner(), ParseError::UnknownInputPart(part) if part == "d"));
+        let err = load(puzzle("input_from: a\npart.b.input: b", "input: c")).unwrap_err();
+        assert!(matches!(err.inner(), ParseError::InvalidFormat(_)));
+
+        // The source is kept when writing the puzzle back
+        let instance = AlgorithmInstance::from_source(
+            PuzzleSource::String(puzzle("input_from: a", "input: c")),
+            create_registry().domain_registry(),
+        )
+        .unwrap();
+        for written in [instance.to_puzzle_format(), instance.to_json_format()] {
+            assert!(written.contains("input_from"));
+            let reloaded = AlgorithmInstance::from_source(
+                PuzzleSource::String(written),
+                create_registry().domain_registry(),
+            )
+            .unwrap();
+            assert!(reloaded == instance);
+        }
+    }
+}
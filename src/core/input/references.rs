@@ -0,0 +1,250 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use indexmap::IndexMap;
+
+use crate::{
+    core::input::processors::{Field, Leaf},
+    error::ParseError,
+};
+
+// ============================================================================
+// REFERENCES
+// ============================================================================
+
+const REFERENCE_START: &str = "${";
+const REFERENCE_END: char = '}';
+/// Written in a value to get a literal `${`.
+const ESCAPED_REFERENCE_START: &str = "$${";
+
+/// Replaces every `${path}` in the values of a puzzle tree by the value found at `path`.
+///
+/// Paths are absolute (`${part.a.input}`), except for `${config.<key>}` inside a part, which
+/// reads the part's configuration first and falls back to the puzzle one, like the part does at
+/// runtime. Referenced values are themselves resolved first; `$${` stands for a literal `${`.
+///
+/// Every broken value is reported, where it is written; values that reference a broken one are
+/// left out of the report and keep their raw text, like the broken value itself.
+pub(crate) fn resolve_references(root: &Field) -> (Field, Vec<ParseError>) {
+    let mut resolver = Resolver {
+        root,
+        resolved: HashMap::new(),
+        resolving: Vec::new(),
+        errors: Vec::new(),
+    };
+    let resolved = resolver.resolve_field(root, &mut Vec::new());
+    (resolved, resolver.errors)
+}
+
+/// Escapes a value so that resolving it gives it back unchanged, for writers.
+pub(crate) fn escape_references(value: &str) -> Cow<'_, str> {
+    if value.contains(REFERENCE_START) {
+        Cow::Owned(value.replace(REFERENCE_START, ESCAPED_REFERENCE_START))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+struct Resolver<'a> {
+    root: &'a Field,
+    /// Values already resolved, by path; `None` for values that could not be.
+    resolved: HashMap<String, Option<String>>,
+    /// Paths of the values being resolved, to catch cycles.
+    resolving: Vec<String>,
+    errors: Vec<ParseError>,
+}
+
+impl<'a> Resolver<'a> {
+    fn resolve_field(&mut self, field: &Field, path: &mut Vec<String>) -> Field {
+        match field {
+            Field::Leaf(leaf) => Field::Leaf(Leaf {
+                value: self
+                    .resolve_leaf(leaf, path)
+                    .unwrap_or_else(|| leaf.value.clone()),
+                ..leaf.clone()
+            }),
+            Field::Node(map) => {
+                let mut resolved = IndexMap::with_capacity(map.len());
+                for (key, child) in map {
+                    path.push(key.clone());
+                    resolved.insert(key.clone(), self.resolve_field(child, path));
+                    path.pop();
+                }
+                Field::Node(resolved)
+            }
+            Field::List(items) => {
+                let mut resolved = Vec::with_capacity(items.len());
+                for (index, item) in items.iter().enumerate() {
+                    path.push(index.to_string());
+                    resolved.push(self.resolve_field(item, path));
+                    path.pop();
+                }
+                Field::List(resolved)
+            }
+        }
+    }
+
+    /// Returns the resolved value of a leaf, or `None` if it (or a value it references) is
+    /// broken.
+    fn resolve_leaf(&mut self, leaf: &Leaf, path: &[String]) -> Option<String> {
+        if !leaf.value.contains(REFERENCE_START) {
+            return Some(leaf.value.clone());
+        }
+        let key = path.join(".");
+        if let Some(resolved) = self.resolved.get(&key) {
+            return resolved.clone();
+        }
+        if let Some(first) = self.resolving.iter().position(|p| *p == key) {
+            let mut chain = self.resolving[first..].to_vec();
+            chain.push(key.clone());
+            self.errors
+                .push(ParseError::ReferenceCycle(chain).at(leaf.span.clone()));
+            return None;
+        }
+
+        self.resolving.push(key.clone());
+        let resolved = self.interpolate(leaf, path);
+        self.resolving.pop();
+        self.resolved.insert(key, resolved.clone());
+        resolved
+    }
+
+    fn interpolate(&mut self, leaf: &Leaf, path: &[String]) -> Option<String> {
+        let value = leaf.value.as_str();
+        let mut out = String::with_capacity(value.len());
+        let mut rest = value;
+        while let Some(idx) = rest.find('$') {
+            out.push_str(&rest[..idx]);
+            let after = &rest[idx..];
+            if let Some(after) = after.strip_prefix(ESCAPED_REFERENCE_START) {
+                out.push_str(REFERENCE_START);
+                rest = after;
+            } else if let Some(after) = after.strip_prefix(REFERENCE_START) {
+                let start = value.len() - rest.len() + idx;
+                let Some(end) = after.find(REFERENCE_END) else {
+                    let span = leaf.span.sub_span(start..value.len());
+                    self.errors.push(
+                        ParseError::InvalidFormat(format!(
+                            "reference '{}' is never closed",
+                            &value[start..]
+                        ))
+                        .at(span),
+                    );
+                    return None;
+                };
+                let reference = after[..end].trim();
+                let reference_span = leaf
+                    .span
+                    .sub_span(start..start + REFERENCE_START.len() + end + 1);
+                let Some(target) = self.lookup(path, reference) else {
+                    self.errors.push(
+                        ParseError::UnknownReference(reference.to_string()).at(reference_span),
+                    );
+                    return None;
+                };
+                match target {
+                    (Field::Leaf(target), target_path) => {
+                        out.push_str(&self.resolve_leaf(target, &target_path)?)
+                    }
+                    _ => {
+                        self.errors.push(
+                            ParseError::InvalidFormat(format!(
+                                "reference '{reference}' does not point to a single value"
+                            ))
+                            .at(reference_span),
+                        );
+                        return None;
+                    }
+                }
+                rest = &after[end + 1..];
+            } else {
+                out.push('$');
+                rest = &after[1..];
+            }
+        }
+        out.push_str(rest);
+        Some(out)
+    }
+
+    /// Finds the field a reference written at `path` points to, along with its own path.
+    fn lookup(&self, path: &[String], reference: &str) -> Option<(&'a Field, Vec<String>)> {
+        let segments: Vec<String> = reference.split('.').map(|s| s.trim().to_string()).collect();
+        let mut candidates = Vec::with_capacity(2);
+        if let (["part", part_id, ..], Some("config")) = (
+            path.iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .as_slice(),
+            segments.first().map(String::as_str),
+        ) {
+            let mut part_config = vec!["part".to_string(), part_id.to_string()];
+            part_config.extend(segments.iter().cloned());
+            candidates.push(part_config);
+        }
+        candidates.push(segments);
+
+        candidates.into_iter().find_map(|candidate| {
+            let keys: Vec<&str> = candidate.iter().map(String::as_str).collect();
+            self.root.get_path(&keys).map(|field| (field, candidate))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::input::processors::parse_puzzle_tree;
+
+    fn resolve(content: &str) -> (Field, Vec<ParseError>) {
+        resolve_references(&parse_puzzle_tree(content, None).unwrap())
+    }
+
+    fn value<'a>(root: &'a Field, path: &str) -> &'a str {
+        let keys: Vec<&str> = path.split('.').collect();
+        root.get_path(&keys).and_then(Field::as_leaf).unwrap()
+    }
+
+    #[test]
+    fn test_resolves_references() {
+        let (root, errors) = resolve(
+            "title: Grid of ${config.rows}x${config.columns}
+            config.rows: 4
+            config.columns: ${config.rows}
+            part.a.config.rows: 2
+            part.a.name: ${part.a.config.rows} rows
+            part.a.input: ab $${literal} $5
+            part.a.steps: 0_0__${config.rows}__
+            part.b.input: ${part.a.input}
+            part.b.steps: 0_0__${config.rows}__",
+        );
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(value(&root, "title"), "Grid of 4x4");
+        assert_eq!(value(&root, "part.a.name"), "2 rows");
+        assert_eq!(value(&root, "part.a.input"), "ab ${literal} $5");
+        assert_eq!(value(&root, "part.b.input"), "ab ${literal} $5");
+        // Inside a part, `config` is the part configuration with the puzzle one as fallback
+        assert_eq!(value(&root, "part.a.steps"), "0_0__2__");
+        assert_eq!(value(&root, "part.b.steps"), "0_0__4__");
+    }
+
+    #[test]
+    fn test_reports_broken_references() {
+        let (root, errors) = resolve(
+            "config.a: ${config.b}
+            config.b: x${config.a}
+            part.a.input: ${part.a.missing}
+            part.a.name: ${part.a.input} and ${config.a}
+            part.a.steps: ${part
+            part.b.input: ${part.a}",
+        );
+        let errors: Vec<&ParseError> = errors.iter().map(ParseError::inner).collect();
+        assert!(matches!(errors[0], ParseError::ReferenceCycle(chain) if chain.len() == 3));
+        assert!(matches!(errors[1], ParseError::UnknownReference(r) if r == "part.a.missing"));
+        assert!(matches!(errors[2], ParseError::InvalidFormat(_)));
+        assert!(matches!(errors[3], ParseError::InvalidFormat(_)));
+        assert_eq!(errors.len(), 4);
+        assert_eq!(
+            value(&root, "part.a.name"),
+            "${part.a.input} and ${config.a}"
+        );
+    }
+}
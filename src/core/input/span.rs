@@ -0,0 +1,170 @@
+use std::{fmt, ops::Range, sync::Arc};
+
+// ============================================================================
+// SOURCE SPANS
+// ============================================================================
+
+/// Name used in diagnostics for sources that do not come from a file.
+const UNNAMED_SOURCE: &str = "<string>";
+const TAB_WIDTH: usize = 4;
+
+/// The full text of a puzzle source, shared by every [`Span`] pointing into it.
+#[derive(Debug, Default)]
+pub struct SourceText {
+    pub name: Option<String>,
+    pub text: String,
+}
+
+impl SourceText {
+    pub fn new(name: Option<String>, text: impl Into<String>) -> Arc<Self> {
+        Arc::new(Self {
+            name,
+            text: text.into(),
+        })
+    }
+
+    pub fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(UNNAMED_SOURCE)
+    }
+
+    /// Returns the 1-based line and column of a byte offset.
+    pub fn line_column(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.text.len());
+        let before = &self.text[..offset];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map(|idx| idx + 1).unwrap_or(0);
+        let column = before[line_start..].chars().count() + 1;
+        (line, column)
+    }
+
+    /// Returns the text of a 1-based line, without its line ending.
+    pub fn line(&self, line: usize) -> Option<&str> {
+        self.text
+            .lines()
+            .nth(line.checked_sub(1)?)
+            .map(|l| l.trim_end_matches('\r'))
+    }
+}
+
+/// Location of a piece of text within a puzzle source. Lines and columns are 1-based, `start`
+/// and `end` are byte offsets into the source text.
+///
+/// Spans created by hand (for example when building a [`Configuration`] in code) are detached:
+/// they have no source and render without a snippet.
+///
+/// [`Configuration`]: crate::core::configuration::Configuration
+#[derive(Debug, Clone, Default)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub start: usize,
+    pub end: usize,
+    pub source: Option<Arc<SourceText>>,
+}
+
+impl Span {
+    pub fn new(source: &Arc<SourceText>, range: Range<usize>) -> Self {
+        let (line, column) = source.line_column(range.start);
+        Self {
+            line,
+            column,
+            start: range.start,
+            end: range.end,
+            source: Some(source.clone()),
+        }
+    }
+
+    /// Creates a span for a sub-slice of `text`, which must itself be borrowed from the source.
+    pub(crate) fn of_slice(source: &Arc<SourceText>, text: &str) -> Self {
+        let start = text.as_ptr() as usize - source.text.as_ptr() as usize;
+        Self::new(source, start..start + text.len())
+    }
+
+    /// Narrows the span to a byte range relative to its own start, as when pointing at a single
+    /// step inside a `steps` value.
+    pub fn sub_span(&self, range: Range<usize>) -> Self {
+        let start = (self.start + range.start).min(self.end.max(self.start));
+        let end = (self.start + range.end).min(self.end.max(start));
+        match &self.source {
+            Some(source) => Self::new(source, start..end),
+            None => Self {
+                line: self.line,
+                column: self.column + range.start,
+                start,
+                end,
+                source: None,
+            },
+        }
+    }
+
+    pub fn is_detached(&self) -> bool {
+        self.source.is_none()
+    }
+
+    pub fn source_name(&self) -> &str {
+        self.source
+            .as_ref()
+            .map(|s| s.display_name())
+            .unwrap_or(UNNAMED_SOURCE)
+    }
+
+    /// Renders a rustc-style snippet of the span, with a caret under the offending text.
+    pub fn snippet(&self) -> Option<String> {
+        let source = self.source.as_ref()?;
+        let line_text = source.line(self.line)?;
+        let gutter = " ".repeat(self.line.to_string().len());
+
+        let mut printed = String::new();
+        let mut caret_offset = 0;
+        let mut caret_len = 0;
+        let end_column = self.column + source.text[self.start..self.end].chars().count();
+        for (idx, c) in line_text.chars().enumerate() {
+            let column = idx + 1;
+            let width = if c == '\t' { TAB_WIDTH } else { 1 };
+            if c == '\t' {
+                printed.push_str(&" ".repeat(TAB_WIDTH));
+            } else {
+                printed.push(c);
+            }
+            if column < self.column {
+                caret_offset += width;
+            } else if column < end_column {
+                caret_len += width;
+            }
+        }
+
+        Some(format!(
+            "{gutter}--> {}:{}:{}\n{gutter} |\n{} | {}\n{gutter} | {}{}",
+            source.display_name(),
+            self.line,
+            self.column,
+            self.line,
+            printed,
+            " ".repeat(caret_offset),
+            "^".repeat(caret_len.max(1)),
+        ))
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.source_name(), self.line, self.column)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_snippet_points_at_text() {
+        let source = SourceText::new(Some("day1.sf".to_string()), "title: x\n\tpart.a.name: Bad");
+        let text = &source.text;
+        let span = Span::of_slice(&source, &text[text.find("Bad").unwrap()..]);
+        assert_eq!((span.line, span.column), (2, 15));
+        assert_eq!(
+            span.snippet().unwrap(),
+            " --> day1.sf:2:15\n  |\n2 |     part.a.name: Bad\n  |                  ^^^"
+        );
+    }
+}
@@ -0,0 +1,374 @@
+use std::fmt::Write;
+
+use crate::{
+    algorithm::{Bookmark, Metadata, PartInfo},
+    core::{
+        configuration::Configuration,
+        input::{processors::Field, references::escape_references},
+        split::Separators,
+        step::expand::{GROUP_END, GROUP_START},
+    },
+};
+
+// ============================================================================
+// PUZZLE FORMAT WRITER
+// ============================================================================
+
+/// Encodes a puzzle back into the key-path format read by
+/// [`parse_puzzle_format`](super::processors::parse_puzzle_format).
+///
+/// The output is canonical: metadata first, then the puzzle configuration and macros, then every
+/// part in order with its own (non-inherited) configuration. Steps are written expanded, and an
+/// `input_file` or `steps_file` is written back as the path found in the source. Values that would
+/// not survive a single line (newlines, surrounding whitespace) are written as `<<<` blocks.
+/// Carriage returns cannot be represented and are dropped by the reader.
+pub fn write_puzzle_format(metadata: &Metadata, parts: &[PartInfo]) -> String {
+    let mut out = String::new();
+    write_entry(&mut out, "title", &metadata.title);
+    if let Some(author) = &metadata.author {
+        write_entry(&mut out, "author", author);
+    }
+    if let Some(difficulty) = metadata.difficulty {
+        write_entry(&mut out, "difficulty", &difficulty.to_string());
+    }
+    if let Some(description) = &metadata.description {
+        write_entry(&mut out, "description", description);
+    }
+    for (key, field) in &metadata.meta {
+        write_field(&mut out, &format!("meta.{key}"), field);
+    }
+    write_configuration(&mut out, "config", &metadata.configuration);
+    // Steps are written expanded, macros are kept for steps that do not parse
+    for (name, body) in metadata.configuration.macros() {
+        write_entry(&mut out, &format!("macro.{name}"), &body.value);
+    }
+
+    for part in parts {
+        let prefix = format!("part.{}", part.id);
+        out.push('\n');
+        write_entry(&mut out, &format!("{prefix}.name"), &part.display_name);
+        if let Some(description) = &part.description {
+            write_entry(&mut out, &format!("{prefix}.description"), description);
+        }
+        write_entry(&mut out, &format!("{prefix}.step_type"), part.step_type_id);
+        match (&part.input_from, &part.input_file) {
+            (Some(source), _) => write_entry(&mut out, &format!("{prefix}.input_from"), source),
+            (None, Some(path)) => write_entry(&mut out, &format!("{prefix}.input_file"), path),
+            (None, None) => write_entry(&mut out, &format!("{prefix}.input"), &part.input_data),
+        }
+        match &part.steps_file {
+            Some(path) => write_entry(&mut out, &format!("{prefix}.steps_file"), path),
+            None => write_entry(&mut out, &format!("{prefix}.steps"), &written_steps(part)),
+        }
+        for bookmark in part_bookmarks(part) {
+            write_entry(
+                &mut out,
+                &format!("{prefix}.bookmarks.{}", bookmark.name),
+                &bookmark.step.to_string(),
+            );
+        }
+        write_configuration(&mut out, &format!("{prefix}.config"), &part.configuration);
+    }
+    out
+}
+
+/// The steps of a part as a single list. Steps that do not parse are written back as found.
+fn written_steps(part: &PartInfo) -> String {
+    let frames = match part
+        .steps()
+        .and_then(|steps| steps.written_frames(&part.configuration))
+    {
+        Ok(frames) => frames,
+        Err(_) => return part.raw_steps.to_string(),
+    };
+    let separator = format!(
+        " {} ",
+        Separators::from_configuration(&part.configuration).step
+    );
+    let frames: Vec<String> = frames
+        .iter()
+        .map(|steps| match steps.as_slice() {
+            [step] => step.clone(),
+            steps => format!("{GROUP_START}{}{GROUP_END}", steps.join(&separator)),
+        })
+        .collect();
+    frames.join(&separator)
+}
+
+/// Bookmarks written among the steps are lost as steps are written expanded, every bookmark is
+/// written under the `bookmarks` key instead. Only the ones of that key are known if the steps do
+/// not parse, and only those are written when the steps stay in their `steps_file`.
+pub(crate) fn part_bookmarks(part: &PartInfo) -> Vec<Bookmark> {
+    match part.bookmarks() {
+        Ok(bookmarks) if part.steps_file.is_none() => bookmarks.to_vec(),
+        _ => part
+            .configured_bookmarks
+            .iter()
+            .map(|(bookmark, _)| bookmark.clone())
+            .collect(),
+    }
+}
+
+fn write_configuration(out: &mut String, prefix: &str, configuration: &Configuration) {
+    for (key, field) in configuration.entries() {
+        write_field(out, &format!("{prefix}.{key}"), field);
+    }
+}
+
+fn write_field(out: &mut String, path: &str, field: &Field) {
+    match field {
+        Field::Leaf(leaf) => write_entry(out, path, &leaf.value),
+        Field::Node(map) => {
+            for (key, child) in map {
+                write_field(out, &format!("{path}.{key}"), child);
+            }
+        }
+        // The key-path format has no lists. Plain values are joined by commas, as `meta.tags` is
+        // read, and other items are keyed by their index instead
+        Field::List(items) if let Some(values) = comma_separated(items) => {
+            write_entry(out, path, &values)
+        }
+        Field::List(items) => {
+            for (idx, item) in items.iter().enumerate() {
+                write_field(out, &format!("{path}.{idx}"), item);
+            }
+        }
+    }
+}
+
+/// The items of a list joined by commas, if they are all values holding none.
+fn comma_separated(items: &[Field]) -> Option<String> {
+    let values: Vec<&str> = items.iter().map(Field::as_leaf).collect::<Option<_>>()?;
+    let joinable = values
+        .iter()
+        .all(|value| !value.contains(',') && !value.trim().is_empty());
+    joinable.then(|| values.join(", "))
+}
+
+fn write_entry(out: &mut String, path: &str, value: &str) {
+    let value = &*escape_references(value);
+    let needs_block = value.contains('\n') || value.trim() != value || value.starts_with("<<<");
+    if !needs_block {
+        let _ = writeln!(out, "{path}: {value}");
+        return;
+    }
+    let tag = block_tag(value);
+    let _ = writeln!(out, "{path}: <<<{tag}");
+    for line in value.split('\n') {
+        let _ = writeln!(out, "{line}");
+    }
+    let _ = writeln!(out, ">>>{tag}");
+}
+
+/// Picks a tag for a block so that no line of the value can be mistaken for its closing marker.
+fn block_tag(value: &str) -> String {
+    let closes = |tag: &str| {
+        value
+            .split('\n')
+            .any(|line| line.trim().strip_prefix(">>>") == Some(tag))
+    };
+    if !closes("") {
+        return String::new();
+    }
+    (0..)
+        .map(|n| format!("END{n}"))
+        .find(|tag| !closes(tag))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, OnceLock};
+
+    use indexmap::IndexMap;
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{
+        StepAction,
+        algorithm::{AlgorithmInstance, PuzzleSource, RawSteps},
+        domains::{create_registry, text::TextStep},
+    };
+
+    /// Any text the reader can hold in a value: everything but carriage returns.
+    fn value() -> impl Strategy<Value = String> {
+        "[^\r]{0,24}"
+    }
+
+    fn key() -> impl Strategy<Value = String> {
+        "[a-z][a-z0-9_]{0,8}"
+    }
+
+    fn configuration() -> impl Strategy<Value = IndexMap<String, Field>> {
+        prop::collection::vec((key(), value()), 0..4).prop_map(|entries| {
+            entries
+                .into_iter()
+                .map(|(key, value)| (key, Field::leaf(value)))
+                .collect()
+        })
+    }
+
+    fn step() -> impl Strategy<Value = String> {
+        let word = r"([a-zA-Z0-9#_|\\]([a-zA-Z0-9 #_|\\]{0,6}[a-zA-Z0-9#_|\\])?)?";
+        let separators = Separators::default();
+        (0..64usize, word, word, word).prop_map(move |(position, content, bg, fg)| {
+            let [content, bg, fg] = [content, bg, fg].map(|field| separators.escape(&field));
+            format!("{position}__{content}__{bg}__{fg}")
+        })
+    }
+
+    #[derive(Debug, Clone)]
+    struct PartModel {
+        name: String,
+        description: Option<String>,
+        input: String,
+        steps: Vec<String>,
+        configuration: IndexMap<String, Field>,
+    }
+
+    fn part() -> impl Strategy<Value = PartModel> {
+        (
+            value(),
+            prop::option::of(value()),
+            value(),
+            prop::collection::vec(step(), 1..6),
+            configuration(),
+        )
+            .prop_map(
+                |(name, description, input, steps, configuration)| PartModel {
+                    name,
+                    description,
+                    input,
+                    steps,
+                    configuration,
+                },
+            )
+    }
+
+    fn instance(
+        mut metadata: Metadata,
+        root: IndexMap<String, Field>,
+        parts: IndexMap<String, PartModel>,
+    ) -> (Metadata, Vec<PartInfo>) {
+        let root = Arc::new(Configuration::new(root));
+        metadata.configuration = root.clone();
+        let registry = create_registry();
+        let parts = parts
+            .into_iter()
+            .map(|(id, part)| PartInfo {
+                id,
+                display_name: part.name,
+                description: part.description,
+                configuration: Configuration::with_parent(part.configuration, Some(root.clone())),
+                raw_steps: RawSteps::List(part.steps.iter().map(Field::leaf).collect()),
+                step_parsers: registry
+                    .domain_registry()
+                    .step_parsers(TextStep::type_id())
+                    .unwrap(),
+                steps: OnceLock::new(),
+                input_data: part.input,
+                step_type_id: TextStep::type_id(),
+                configured_bookmarks: Vec::new(),
+                input_from: None,
+                input_file: None,
+                steps_file: None,
+                final_state: OnceLock::new(),
+            })
+            .collect();
+        (metadata, parts)
+    }
+
+    fn reparse(text: &str) -> AlgorithmInstance {
+        AlgorithmInstance::from_source(
+            PuzzleSource::String(text.to_string()),
+            create_registry().domain_registry(),
+        )
+        .unwrap_or_else(|err| panic!("{err}\n{text}"))
+    }
+
+    proptest! {
+        #[test]
+        fn test_write_then_parse_round_trips(
+            title in value(),
+            author in prop::option::of(value()),
+            description in prop::option::of(value()),
+            difficulty in prop::option::of(any::<u8>()),
+            meta in configuration(),
+            root in configuration(),
+            parts in prop::collection::vec((key(), part()), 1..4),
+        ) {
+            let metadata = Metadata {
+                title,
+                author,
+                description,
+                difficulty,
+                meta,
+                ..Metadata::default()
+            };
+            let (metadata, parts) = instance(metadata, root, parts.into_iter().collect());
+
+            let text = write_puzzle_format(&metadata, &parts);
+            let parsed = reparse(&text);
+            prop_assert_eq!(&parsed.metadata, &metadata);
+            prop_assert_eq!(&parsed.parts, &parts);
+
+            let rewritten = parsed.to_puzzle_format();
+            prop_assert_eq!(&rewritten, &text);
+            prop_assert_eq!(reparse(&rewritten), parsed);
+        }
+    }
+
+    #[test]
+    fn test_files_are_written_back_as_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("data")).unwrap();
+        std::fs::write(dir.path().join("data/input.txt"), "a_b_c").unwrap();
+        std::fs::write(dir.path().join("data/steps.txt"), "0__x____\n1__y____\n").unwrap();
+        let puzzle = dir.path().join("puzzle.sf");
+        std::fs::write(
+            &puzzle,
+            "title: Files\n\
+             part.a.name: A\n\
+             part.a.step_type: text\n\
+             part.a.input_file: data/input.txt\n\
+             part.a.steps_file: data/steps.txt\n\
+             part.a.bookmarks.second: 1\n",
+        )
+        .unwrap();
+        let load = |path| {
+            AlgorithmInstance::from_source(
+                PuzzleSource::File(path),
+                create_registry().domain_registry(),
+            )
+            .unwrap()
+        };
+        let instance = load(puzzle);
+
+        let text = instance.to_puzzle_format();
+        assert!(
+            text.contains("part.a.input_file: data/input.txt\n"),
+            "{text}"
+        );
+        assert!(
+            text.contains("part.a.steps_file: data/steps.txt\n"),
+            "{text}"
+        );
+        assert!(!text.contains("part.a.input:") && !text.contains("part.a.steps:"));
+        let copy = dir.path().join("copy.sf");
+        std::fs::write(&copy, &text).unwrap();
+        let reloaded = load(copy);
+        assert_eq!(reloaded, instance);
+        assert_eq!(reloaded.to_puzzle_format(), text);
+
+        let json = dir.path().join("copy.json");
+        std::fs::write(&json, instance.to_json_format()).unwrap();
+        assert_eq!(load(json), instance);
+    }
+
+    #[test]
+    fn test_block_tag_avoids_content() {
+        assert_eq!(block_tag("a\nb"), "");
+        assert_eq!(block_tag("a\n>>>\nb"), "END0");
+        assert_eq!(block_tag(" >>>END0\n>>>"), "END1");
+    }
+}
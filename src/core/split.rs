@@ -1,6 +1,21 @@
+use std::borrow::Cow;
+
+use crate::{core::configuration::Configuration, error::ParseError};
+
+/// Character making the next one literal in step and input syntax: `\|` is a `|` that does not
+/// separate steps, `\\` a backslash.
+pub const ESCAPE: char = '\\';
+
 pub trait SplitArray<'a> {
     fn split_array_exact<const N: usize>(self, sep: impl AsRef<str>) -> Option<[&'a str; N]>;
     fn split_array<const N: usize>(self, sep: impl AsRef<str>) -> Option<[&'a str; N]>;
+    /// Splits on `sep`, except where it is escaped. Pieces keep their escapes, so that they can
+    /// be split further before being [`unescape`]d.
+    fn split_escaped(self, sep: impl AsRef<str>) -> Vec<&'a str>;
+    fn split_array_escaped_exact<const N: usize>(
+        self,
+        sep: impl AsRef<str>,
+    ) -> Option<[&'a str; N]>;
 }
 
 impl<'a> SplitArray<'a> for &'a str {
@@ -23,4 +38,177 @@ impl<'a> SplitArray<'a> for &'a str {
         }
         Some(out)
     }
+    fn split_escaped(self, sep: impl AsRef<str>) -> Vec<&'a str> {
+        let sep = sep.as_ref();
+        if sep.is_empty() {
+            return vec![self];
+        }
+        let mut pieces = Vec::new();
+        let mut piece_start = 0;
+        let mut chars = self.char_indices();
+        while let Some((idx, c)) = chars.next() {
+            if c == ESCAPE {
+                chars.next();
+            } else if self[idx..].starts_with(sep) {
+                pieces.push(&self[piece_start..idx]);
+                piece_start = idx + sep.len();
+                // Skip the rest of the separator
+                for _ in sep.chars().skip(1) {
+                    chars.next();
+                }
+            }
+        }
+        pieces.push(&self[piece_start..]);
+        pieces
+    }
+    fn split_array_escaped_exact<const N: usize>(
+        self,
+        sep: impl AsRef<str>,
+    ) -> Option<[&'a str; N]> {
+        self.split_escaped(sep).try_into().ok()
+    }
+}
+
+/// Removes the escapes of a value, once it has been split into its smallest pieces.
+pub fn unescape(value: &str) -> Cow<'_, str> {
+    if !value.contains(ESCAPE) {
+        return Cow::Borrowed(value);
+    }
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ESCAPE => out.extend(chars.next()),
+            c => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Removes the escapes of a field and the whitespace around it, but not escaped whitespace: `a\ `
+/// is `a `. A lone escape ending the field has nothing to make literal and is refused.
+pub fn unescape_trimmed(value: &str) -> Result<Cow<'_, str>, ParseError> {
+    let trimmed = value.trim_start();
+    if !trimmed.contains(ESCAPE) {
+        return Ok(Cow::Borrowed(trimmed.trim_end()));
+    }
+    let mut out = String::with_capacity(trimmed.len());
+    // Length of `out` up to its last character that is not unescaped whitespace
+    let mut kept = 0;
+    let mut chars = trimmed.chars();
+    while let Some(c) = chars.next() {
+        if c == ESCAPE {
+            let escaped = chars.next().ok_or_else(|| {
+                ParseError::InvalidFormat(format!("'{value}' ends with a lone '{ESCAPE}'"))
+            })?;
+            out.push(escaped);
+        } else {
+            out.push(c);
+            if c.is_whitespace() {
+                continue;
+            }
+        }
+        kept = out.len();
+    }
+    out.truncate(kept);
+    Ok(Cow::Owned(out))
+}
+
+/// The separators of step and input syntax. Parts override them with `config.step_separator`,
+/// `config.field_separator` and `config.token_separator`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Separators {
+    /// Between the steps of a list, `|` by default.
+    pub step: String,
+    /// Between the fields of a step, `__` by default.
+    pub field: String,
+    /// Between the tokens of a field or of an input, `_` by default.
+    pub token: String,
+}
+
+impl Separators {
+    pub const STEP_KEY: &'static str = "step_separator";
+    pub const FIELD_KEY: &'static str = "field_separator";
+    pub const TOKEN_KEY: &'static str = "token_separator";
+
+    /// The default separators, overridden by the configuration.
+    pub fn from_configuration(configuration: &Configuration) -> Self {
+        Self::default().overridden_by(configuration)
+    }
+
+    /// Keeps these separators where the configuration does not set one.
+    pub fn overridden_by(self, configuration: &Configuration) -> Self {
+        let get = |key: &str, default: String| {
+            configuration
+                .get_str(key)
+                .filter(|sep| !sep.is_empty())
+                .map(str::to_string)
+                .unwrap_or(default)
+        };
+        Self {
+            step: get(Self::STEP_KEY, self.step),
+            field: get(Self::FIELD_KEY, self.field),
+            token: get(Self::TOKEN_KEY, self.token),
+        }
+    }
+
+    /// Escapes a value so that none of the separators can split it, the inverse of [`unescape`].
+    /// Whitespace at either end is escaped too, for [`unescape_trimmed`] to keep it.
+    pub fn escape(&self, value: &str) -> String {
+        let special = |c: char| {
+            c == ESCAPE || self.step.contains(c) || self.field.contains(c) || self.token.contains(c)
+        };
+        let start = value.len() - value.trim_start().len();
+        let end = value.trim_end().len();
+        let mut out = String::with_capacity(value.len());
+        for (idx, c) in value.char_indices() {
+            if special(c) || !(start..end).contains(&idx) {
+                out.push(ESCAPE);
+            }
+            out.push(c);
+        }
+        out
+    }
+}
+
+impl Default for Separators {
+    fn default() -> Self {
+        Self {
+            step: "|".to_string(),
+            field: "__".to_string(),
+            token: "_".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_split_escaped() {
+        assert_eq!(r"a|b\|c|".split_escaped("|"), vec!["a", r"b\|c", ""]);
+        assert_eq!(
+            r"0__a\_b\___c".split_escaped("__"),
+            vec!["0", r"a\_b\_", "c"]
+        );
+        assert_eq!(r"a\\|b".split_escaped("|"), vec![r"a\\", "b"]);
+        assert_eq!(unescape(r"a\_b\\c\|"), r"a_b\c|");
+        assert_eq!(unescape_trimmed("  a b  ").unwrap(), "a b");
+        assert_eq!(unescape_trimmed(r" a\  ").unwrap(), "a ");
+        assert_eq!(unescape_trimmed(r"\ a\_ \\ ").unwrap(), r" a_ \");
+        assert!(matches!(
+            unescape_trimmed(r"a\"),
+            Err(ParseError::InvalidFormat(_))
+        ));
+        assert!(unescape_trimmed(r"a\\").is_ok());
+
+        let separators = Separators::default();
+        for value in ["a_b", "|", r"\", "__x__", "plain", " a b ", "  "] {
+            let escaped = separators.escape(value);
+            assert_eq!(escaped.split_escaped("__").len(), 1);
+            assert_eq!(unescape(&escaped), value);
+            assert_eq!(unescape_trimmed(&escaped).unwrap(), value);
+        }
+    }
 }
@@ -2,9 +2,11 @@ use crate::{
     core::{configuration::Configuration, step::StepAction},
     error::{ParseError, StepError},
 };
+use schema::{ConfigField, ConfigProblem};
 use snapshot::StateSnapshot;
-use std::fmt::Debug;
+use std::{any::Any, fmt::Debug};
 
+pub mod schema;
 pub mod snapshot;
 // ============================================================================
 // VISUALIZATION STATE MANAGEMENT
@@ -20,13 +22,28 @@ where
     /// Apply a single step to modify the state
     fn apply_step(&mut self, step: &Self::Step) -> Result<(), StepError>;
 
-    /// Jump directly to a specific step index (may reset + replay)
+    /// Jump directly to a specific step index (may reset + replay). `all_steps` holds every step
+    /// of the part, every action of a step group counting as a step. Steps of a binary steps file
+    /// are not held at once: the state is sought to index 0 with none, then stepped forward.
     fn seek_to_step(
         &mut self,
         step_index: usize,
         all_steps: &[&Self::Step],
     ) -> Result<(), StepError>;
 
+    /// Makes the current state the one seeks replay from. Called on the state a part starts from
+    /// when it is the final state of another part, see `input_from`.
+    fn mark_initial(&mut self) {}
+
+    /// A copy of the state, which lets the final state of a part other parts start from be played
+    /// once. Without one, that part is played again whenever they are reset.
+    fn duplicate(&self) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        None
+    }
+
     /// Create a snapshot of current state for rendering
     fn create_snapshot(&self) -> Box<Self::Snapshot>;
 
@@ -44,6 +61,14 @@ where
     fn parse(input: &str, configuration: &Configuration) -> Result<Self, ParseError>
     where
         Self: Sized;
+
+    /// The configuration entries this state reads, checked when a puzzle is loaded
+    fn config_schema() -> &'static [ConfigField]
+    where
+        Self: Sized,
+    {
+        &[]
+    }
 }
 
 pub trait StateProxy: Send + Sync + 'static
@@ -53,15 +78,25 @@ where
     /// Apply a single step to modify the state
     fn apply_step_erased(&mut self, step: &dyn StepAction) -> Result<(), StepError>;
 
-    /// Jump directly to a specific step index (may reset + replay)
+    /// Jump directly to a specific step index (may reset + replay). `all_steps` holds every step
+    /// of the part, every action of a step group counting as a step.
     fn seek_to_step_erased(
         &mut self,
         step_index: usize,
         all_steps: &[Box<dyn StepAction>],
     ) -> Result<(), StepError>;
 
+    /// Makes the current state the one seeks replay from
+    fn mark_initial_erased(&mut self);
+
+    /// A copy of the state, if it has one
+    fn duplicate_erased(&self) -> Option<Box<dyn StateProxy>>;
+
     /// Create a snapshot of current state for rendering
     fn create_snapshot_erased(&self) -> Box<dyn StateSnapshot>;
+
+    /// For downcasting to the concrete state, in state conversions
+    fn as_any(&self) -> &dyn Any;
 }
 
 impl<S: VisualizationState + Sync> StateProxy for S {
@@ -92,9 +127,49 @@ impl<S: VisualizationState + Sync> StateProxy for S {
         self.seek_to_step(step_index, &downcasted_steps?)
     }
 
+    fn mark_initial_erased(&mut self) {
+        self.mark_initial()
+    }
+
+    fn duplicate_erased(&self) -> Option<Box<dyn StateProxy>> {
+        self.duplicate()
+            .map(|state| Box::new(state) as Box<dyn StateProxy>)
+    }
+
     fn create_snapshot_erased(&self) -> Box<dyn StateSnapshot> {
         self.create_snapshot()
     }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Builds a state from the final state of another part, for a part whose `input_from` names a
+/// part played on a state of type `S`. Parts sharing a state type need no conversion.
+///
+/// Conversions are declared with
+/// [`register_conversion`](crate::engine::registry::StateRegistry::register_conversion).
+pub trait FromState<S: VisualizationState>: VisualizationState + Sized {
+    fn from_state(state: &S, configuration: &Configuration) -> Result<Self, ParseError>;
+}
+
+pub type StateConversionFn =
+    fn(&dyn StateProxy, &Configuration) -> Result<Box<dyn StateProxy>, ParseError>;
+
+/// The erased form of [`FromState::from_state`].
+pub(crate) fn convert_state<S, T>(
+    state: &dyn StateProxy,
+    configuration: &Configuration,
+) -> Result<Box<dyn StateProxy>, ParseError>
+where
+    S: VisualizationState,
+    T: FromState<S> + Sync,
+{
+    let state = state.as_any().downcast_ref::<S>().ok_or_else(|| {
+        ParseError::InvalidFormat(format!("state to convert is not a {}", S::state_type_id()))
+    })?;
+    Ok(Box::new(T::from_state(state, configuration)?))
 }
 
 pub type StateFactoryFn = fn(&str, &Configuration) -> Result<Box<dyn StateProxy>, ParseError>;
@@ -105,5 +180,27 @@ pub struct StateInfo {
     pub snapshot_type_id: &'static str,
     pub factory: StateFactoryFn,
     pub required_config_fields: &'static [&'static str],
+    pub config_schema: &'static [ConfigField],
     pub is_default: bool,
 }
+
+impl StateInfo {
+    /// Checks a configuration against the state's schema. Required fields the schema does not
+    /// describe only need to be present.
+    pub fn validate(&self, configuration: &Configuration) -> Vec<ConfigProblem> {
+        let mut problems: Vec<ConfigProblem> = self
+            .config_schema
+            .iter()
+            .filter_map(|field| field.check(configuration))
+            .collect();
+        for key in self.required_config_fields {
+            let described = self.config_schema.iter().any(|field| field.name == *key);
+            if !described && configuration.get(key).is_none() {
+                problems.push(ConfigProblem::Missing {
+                    key: key.to_string(),
+                });
+            }
+        }
+        problems
+    }
+}
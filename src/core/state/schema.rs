@@ -0,0 +1,329 @@
+use std::fmt;
+
+use crate::core::{
+    configuration::{Color, Configuration, parse_bool, parse_duration, parse_enum},
+    input::{processors::Field, span::Span},
+};
+
+// ============================================================================
+// CONFIGURATION SCHEMA
+// ============================================================================
+
+/// The kind of value a configuration entry holds, each one read by a getter of
+/// [`Configuration`] that accepts the same values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigType {
+    String,
+    Integer,
+    Float,
+    /// See [`Configuration::get_bool`]
+    Boolean,
+    /// See [`Configuration::get_color`]
+    Color,
+    /// See [`Configuration::get_duration`]
+    Duration,
+    /// One of the given variants, see [`Configuration::get_enum`]
+    Enum(&'static [&'static str]),
+}
+
+impl ConfigType {
+    fn accepts(self, value: &str) -> bool {
+        let value = value.trim();
+        match self {
+            ConfigType::String => true,
+            ConfigType::Integer => value.parse::<i64>().is_ok(),
+            ConfigType::Float => value.parse::<f64>().is_ok(),
+            ConfigType::Boolean => parse_bool(value).is_some(),
+            ConfigType::Color => value.parse::<Color>().is_ok(),
+            ConfigType::Duration => parse_duration(value).is_some(),
+            ConfigType::Enum(variants) => parse_enum(value, variants).is_some(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigType::String => write!(f, "a string"),
+            ConfigType::Integer => write!(f, "an integer"),
+            ConfigType::Float => write!(f, "a number"),
+            ConfigType::Boolean => write!(f, "true or false"),
+            ConfigType::Color => write!(f, "a color"),
+            ConfigType::Duration => write!(f, "a duration"),
+            ConfigType::Enum(variants) => write!(f, "one of {}", variants.join(", ")),
+        }
+    }
+}
+
+/// One entry of the configuration a state reads. States declare theirs through
+/// [`VisualizationState::config_schema`](super::VisualizationState::config_schema).
+///
+/// ```
+/// use storyframe::core::state::schema::{ConfigField, ConfigType};
+///
+/// const COLUMNS: ConfigField = ConfigField::new("columns", ConfigType::Integer)
+///     .with_range(Some(1.0), None)
+///     .with_description("Width of the grid");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConfigField {
+    pub name: &'static str,
+    pub kind: ConfigType,
+    /// Value used when the entry is not set. Entries without a default are required.
+    pub default: Option<&'static str>,
+    /// Inclusive bounds, for numeric entries.
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub description: &'static str,
+}
+
+impl ConfigField {
+    pub const fn new(name: &'static str, kind: ConfigType) -> Self {
+        Self {
+            name,
+            kind,
+            default: None,
+            min: None,
+            max: None,
+            description: "",
+        }
+    }
+
+    pub const fn with_default(mut self, default: &'static str) -> Self {
+        self.default = Some(default);
+        self
+    }
+
+    pub const fn with_range(mut self, min: Option<f64>, max: Option<f64>) -> Self {
+        self.min = min;
+        self.max = max;
+        self
+    }
+
+    pub const fn with_description(mut self, description: &'static str) -> Self {
+        self.description = description;
+        self
+    }
+
+    pub fn is_required(&self) -> bool {
+        self.default.is_none()
+    }
+
+    /// The value of the entry in `configuration`, or its default.
+    pub fn value<'a>(&self, configuration: &'a Configuration) -> Option<&'a str> {
+        configuration.get_str(self.name).or(self.default)
+    }
+
+    /// Checks the entry against a configuration, inherited entries included.
+    pub fn check(&self, configuration: &Configuration) -> Option<ConfigProblem> {
+        let Some(field) = configuration.get(self.name) else {
+            return self.is_required().then(|| ConfigProblem::Missing {
+                key: self.name.to_string(),
+            });
+        };
+        let span = field.span().or(field.first_key_span()).cloned();
+        let value = match field {
+            Field::Leaf(leaf) => leaf.value.trim(),
+            _ => "",
+        };
+        if field.as_leaf().is_none() || !self.kind.accepts(value) {
+            return Some(ConfigProblem::WrongType {
+                key: self.name.to_string(),
+                expected: self.kind,
+                value: value.to_string(),
+                span,
+            });
+        }
+        if let Ok(number) = value.parse::<f64>() {
+            let below = self.min.is_some_and(|min| number < min);
+            let above = self.max.is_some_and(|max| number > max);
+            if below || above {
+                return Some(ConfigProblem::OutOfRange {
+                    key: self.name.to_string(),
+                    value: value.to_string(),
+                    min: self.min,
+                    max: self.max,
+                    span,
+                });
+            }
+        }
+        None
+    }
+}
+
+/// Why a configuration does not fit a state.
+#[derive(Debug, Clone)]
+pub enum ConfigProblem {
+    Missing {
+        key: String,
+    },
+    WrongType {
+        key: String,
+        expected: ConfigType,
+        value: String,
+        span: Option<Span>,
+    },
+    OutOfRange {
+        key: String,
+        value: String,
+        min: Option<f64>,
+        max: Option<f64>,
+        span: Option<Span>,
+    },
+}
+
+impl ConfigProblem {
+    pub fn key(&self) -> &str {
+        match self {
+            ConfigProblem::Missing { key }
+            | ConfigProblem::WrongType { key, .. }
+            | ConfigProblem::OutOfRange { key, .. } => key,
+        }
+    }
+
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            ConfigProblem::Missing { .. } => None,
+            ConfigProblem::WrongType { span, .. } | ConfigProblem::OutOfRange { span, .. } => {
+                span.as_ref()
+            }
+        }
+    }
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigProblem::Missing { key } => write!(f, "'{key}' is missing"),
+            ConfigProblem::WrongType {
+                key,
+                expected,
+                value,
+                ..
+            } => write!(f, "'{key}' should be {expected}, got '{value}'"),
+            ConfigProblem::OutOfRange {
+                key,
+                value,
+                min,
+                max,
+                ..
+            } => match (min, max) {
+                (Some(min), Some(max)) => {
+                    write!(f, "'{key}' should be between {min} and {max}, got {value}")
+                }
+                (Some(min), None) => write!(f, "'{key}' should be at least {min}, got {value}"),
+                (None, Some(max)) => write!(f, "'{key}' should be at most {max}, got {value}"),
+                (None, None) => write!(f, "'{key}' is out of range, got {value}"),
+            },
+        }?;
+        match self.span().filter(|span| !span.is_detached()) {
+            Some(span) => write!(f, " ({span})"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Every problem of a part's configuration with regard to one state.
+#[derive(Debug, Clone)]
+pub struct PartConfigProblems {
+    pub part: String,
+    pub state: &'static str,
+    pub problems: Vec<ConfigProblem>,
+}
+
+impl fmt::Display for PartConfigProblems {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "configuration of part {} does not fit state {}:",
+            self.part, self.state
+        )?;
+        for problem in &self.problems {
+            write!(f, "\n  - {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use indexmap::IndexMap;
+
+    use super::*;
+
+    #[test]
+    fn test_check_fields() {
+        let configuration = Configuration::new(IndexMap::from([
+            ("rows".to_string(), Field::leaf("3")),
+            ("columns".to_string(), Field::leaf("three")),
+            ("scale".to_string(), Field::leaf("0")),
+        ]));
+        let rows = ConfigField::new("rows", ConfigType::Integer);
+        let columns = ConfigField::new("columns", ConfigType::Integer);
+        let scale = ConfigField::new("scale", ConfigType::Float).with_range(Some(0.5), None);
+        let title = ConfigField::new("title", ConfigType::String);
+        let wrap = ConfigField::new("wrap", ConfigType::Boolean).with_default("false");
+
+        assert!(rows.check(&configuration).is_none());
+        assert!(matches!(
+            columns.check(&configuration),
+            Some(ConfigProblem::WrongType { .. })
+        ));
+        assert!(matches!(
+            scale.check(&configuration),
+            Some(ConfigProblem::OutOfRange { .. })
+        ));
+        assert!(matches!(
+            title.check(&configuration),
+            Some(ConfigProblem::Missing { .. })
+        ));
+        assert!(wrap.check(&configuration).is_none());
+        assert_eq!(wrap.value(&configuration), Some("false"));
+    }
+
+    #[test]
+    fn test_types_accept_what_getters_read() {
+        let configuration = |value: &str| {
+            Configuration::new(IndexMap::from([("key".to_string(), Field::leaf(value))]))
+        };
+        let field = |kind| ConfigField::new("key", kind);
+        // Whether the getter of a type reads the value of `key`
+        type Read = fn(&Configuration) -> bool;
+        let cases: [(ConfigType, &[&str], Read); 4] = [
+            (
+                ConfigType::Boolean,
+                &["true", "No", "on", "1", "maybe"],
+                |c| c.get_bool("key").is_ok(),
+            ),
+            (
+                ConfigType::Color,
+                &["#f80", "red", "#12345", "purple"],
+                |c| c.get_color("key").is_ok(),
+            ),
+            (
+                ConfigType::Duration,
+                &["500ms", "1.5s", "2", "fast", "3d"],
+                |c| c.get_duration("key").is_ok(),
+            ),
+            (
+                ConfigType::Enum(&["slow", "fast"]),
+                &["slow", "FAST", "medium"],
+                |c| c.get_enum("key", &["slow", "fast"]).is_ok(),
+            ),
+        ];
+        for (kind, values, read) in cases {
+            for value in values {
+                let configuration = configuration(value);
+                let accepted = field(kind).check(&configuration).is_none();
+                assert_eq!(accepted, read(&configuration), "{kind} with '{value}'");
+            }
+        }
+        let problem = field(ConfigType::Enum(&["slow", "fast"]))
+            .check(&configuration("medium"))
+            .unwrap();
+        assert_eq!(
+            problem.to_string(),
+            "'key' should be one of slow, fast, got 'medium'"
+        );
+    }
+}
@@ -0,0 +1,404 @@
+use std::{io::Write, sync::Arc};
+
+use crate::{core::step::StepAction, error::ParseError};
+
+// ============================================================================
+// BINARY STEP CONTAINER
+// ============================================================================
+
+/// First bytes of every binary step file.
+pub const MAGIC: &[u8; 8] = b"SFSTEPS\0";
+const VERSION: u8 = 1;
+/// Records are spread over this many entries of the offset index, see [`StepContainer`].
+const INDEX_STRIDE: usize = 256;
+
+/// How the records of a container are laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordLayout {
+    /// Every record takes exactly this many bytes, so any of them is found without scanning.
+    Fixed(u32),
+    /// Every record is prefixed by its length, as a varint.
+    Varint,
+}
+
+/// A compact, binary list of steps of a single step type, for timelines too long to be written
+/// as text. Parts read one through a `steps_file` starting with [`MAGIC`].
+///
+/// The layout is a header, then the records one after another:
+///
+/// ```text
+/// MAGIC | version: u8 | layout: u8 (0 = varint, 1 = fixed) | record size: u32 LE (fixed only)
+///       | step type id length: varint | step type id: utf8 | records...
+/// ```
+///
+/// Records are whatever [`StepAction::encode`] writes. The container works on any byte buffer,
+/// such as a memory map of the file, and only keeps the offset of one record out of
+/// `INDEX_STRIDE` for varint records.
+#[derive(Clone)]
+pub struct StepContainer {
+    bytes: Arc<dyn AsRef<[u8]> + Send + Sync>,
+    step_type_id: String,
+    layout: RecordLayout,
+    records_start: usize,
+    len: usize,
+    /// Offsets of records `0`, `INDEX_STRIDE`, `2 * INDEX_STRIDE`..., for varint records
+    index: Vec<usize>,
+}
+
+impl std::fmt::Debug for StepContainer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StepContainer")
+            .field("step_type_id", &self.step_type_id)
+            .field("layout", &self.layout)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+/// Whether `bytes` start like a binary step container.
+pub fn is_step_container(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+impl StepContainer {
+    /// Reads the header of a container and indexes its records, without decoding any of them.
+    pub fn new(bytes: impl AsRef<[u8]> + Send + Sync + 'static) -> Result<Self, ParseError> {
+        let bytes: Arc<dyn AsRef<[u8]> + Send + Sync> = Arc::new(bytes);
+        let data = (*bytes).as_ref();
+        let invalid = |reason: &str| ParseError::InvalidFormat(format!("binary steps: {reason}"));
+        let mut reader = RecordReader::new(data);
+        if reader.bytes(MAGIC.len()) != Some(MAGIC) {
+            return Err(invalid("missing header"));
+        }
+        let version = reader.u8()?;
+        if version != VERSION {
+            return Err(invalid(&format!("unsupported version {version}")));
+        }
+        let layout = match reader.u8()? {
+            0 => RecordLayout::Varint,
+            1 => RecordLayout::Fixed(reader.u32()?),
+            other => return Err(invalid(&format!("unknown record layout {other}"))),
+        };
+        let step_type_id = reader.str()?.to_string();
+        let records_start = reader.position();
+
+        let records = &data[records_start..];
+        let mut index = Vec::new();
+        let len = match layout {
+            RecordLayout::Fixed(0) => return Err(invalid("records cannot be empty")),
+            RecordLayout::Fixed(size) => {
+                if records.len() % size as usize != 0 {
+                    return Err(invalid("truncated record"));
+                }
+                records.len() / size as usize
+            }
+            RecordLayout::Varint => {
+                let mut reader = RecordReader::new(records);
+                let mut len = 0;
+                while reader.remaining() > 0 {
+                    if len % INDEX_STRIDE == 0 {
+                        index.push(records_start + reader.position());
+                    }
+                    let size = reader.varint()? as usize;
+                    reader
+                        .bytes(size)
+                        .ok_or_else(|| invalid("truncated record"))?;
+                    len += 1;
+                }
+                len
+            }
+        };
+        Ok(Self {
+            bytes,
+            step_type_id,
+            layout,
+            records_start,
+            len,
+            index,
+        })
+    }
+
+    pub fn step_type_id(&self) -> &str {
+        &self.step_type_id
+    }
+
+    pub fn layout(&self) -> RecordLayout {
+        self.layout
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The bytes of a record, as written by [`StepAction::encode`].
+    pub fn record(&self, index: usize) -> Option<&[u8]> {
+        if index >= self.len {
+            return None;
+        }
+        let data = (*self.bytes).as_ref();
+        match self.layout {
+            RecordLayout::Fixed(size) => {
+                let start = self.records_start + index * size as usize;
+                data.get(start..start + size as usize)
+            }
+            RecordLayout::Varint => {
+                let mut reader = RecordReader::new(data);
+                reader.position = self.index[index / INDEX_STRIDE];
+                for _ in 0..index % INDEX_STRIDE {
+                    let size = reader.varint().ok()? as usize;
+                    reader.bytes(size)?;
+                }
+                let size = reader.varint().ok()? as usize;
+                reader.bytes(size)
+            }
+        }
+    }
+
+    /// Every record, in order.
+    pub fn records(&self) -> impl Iterator<Item = &[u8]> {
+        (0..self.len).filter_map(|index| self.record(index))
+    }
+}
+
+/// Writes a binary step container to any writer, one step at a time, so that solvers can stream
+/// their trace out without holding it.
+pub struct StepWriter<W: Write> {
+    writer: W,
+    layout: RecordLayout,
+    record: Vec<u8>,
+}
+
+impl<W: Write> StepWriter<W> {
+    /// Writes the header for steps of type `step_type_id`.
+    pub fn new(
+        mut writer: W,
+        step_type_id: &str,
+        layout: RecordLayout,
+    ) -> Result<Self, ParseError> {
+        let mut header = MAGIC.to_vec();
+        header.push(VERSION);
+        match layout {
+            RecordLayout::Varint => header.push(0),
+            RecordLayout::Fixed(size) => {
+                header.push(1);
+                header.extend(size.to_le_bytes());
+            }
+        }
+        put_str(&mut header, step_type_id);
+        writer.write_all(&header)?;
+        Ok(Self {
+            writer,
+            layout,
+            record: Vec::new(),
+        })
+    }
+
+    /// Appends a step, encoded by [`StepAction::encode`].
+    pub fn push(&mut self, step: &dyn StepAction) -> Result<(), ParseError> {
+        self.record.clear();
+        step.encode(&mut self.record)?;
+        match self.layout {
+            RecordLayout::Fixed(size) if self.record.len() != size as usize => {
+                return Err(ParseError::InvalidFormat(format!(
+                    "binary steps: record of {} bytes in a container of {size}-byte records",
+                    self.record.len()
+                )));
+            }
+            RecordLayout::Fixed(_) => {}
+            RecordLayout::Varint => {
+                let mut prefix = Vec::with_capacity(4);
+                put_varint(&mut prefix, self.record.len() as u64);
+                self.writer.write_all(&prefix)?;
+            }
+        }
+        self.writer.write_all(&self.record)?;
+        Ok(())
+    }
+
+    /// Flushes the container and hands the writer back.
+    pub fn finish(mut self) -> Result<W, ParseError> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+// ============================================================================
+// RECORD ENCODING HELPERS
+// ============================================================================
+
+/// Appends an unsigned LEB128 varint.
+pub fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Appends a string, prefixed by its length.
+pub fn put_str(out: &mut Vec<u8>, value: &str) {
+    put_varint(out, value.len() as u64);
+    out.extend_from_slice(value.as_bytes());
+}
+
+/// Appends an optional string, prefixed by whether it is set.
+pub fn put_opt_str(out: &mut Vec<u8>, value: Option<&str>) {
+    match value {
+        Some(value) => {
+            out.push(1);
+            put_str(out, value);
+        }
+        None => out.push(0),
+    }
+}
+
+/// Appends an `f32`, little-endian.
+pub fn put_f32(out: &mut Vec<u8>, value: f32) {
+    out.extend(value.to_le_bytes());
+}
+
+/// Reads the values written by the `put_*` helpers back, failing on truncated input.
+pub struct RecordReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> RecordReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.bytes.len() - self.position
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position.checked_add(len)?)?;
+        self.position += len;
+        Some(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, ParseError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    pub fn u32(&mut self) -> Result<u32, ParseError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn f32(&mut self) -> Result<f32, ParseError> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    pub fn varint(&mut self) -> Result<u64, ParseError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(ParseError::InvalidFormat(
+            "binary steps: varint too long".to_string(),
+        ))
+    }
+
+    pub fn str(&mut self) -> Result<&'a str, ParseError> {
+        let len = self.varint()? as usize;
+        let bytes = self.bytes(len).ok_or_else(truncated)?;
+        std::str::from_utf8(bytes)
+            .map_err(|_| ParseError::InvalidFormat("binary steps: invalid utf8".to_string()))
+    }
+
+    pub fn opt_str(&mut self) -> Result<Option<&'a str>, ParseError> {
+        match self.u8()? {
+            0 => Ok(None),
+            _ => self.str().map(Some),
+        }
+    }
+
+    /// Fails if the record holds more than what was read.
+    pub fn finish(self) -> Result<(), ParseError> {
+        match self.remaining() {
+            0 => Ok(()),
+            extra => Err(ParseError::InvalidFormat(format!(
+                "binary steps: {extra} unexpected bytes at the end of a record"
+            ))),
+        }
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], ParseError> {
+        let bytes = self.bytes(N).ok_or_else(truncated)?;
+        Ok(bytes.try_into().unwrap_or([0; N]))
+    }
+}
+
+fn truncated() -> ParseError {
+    ParseError::InvalidFormat("binary steps: truncated record".to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::domains::grids::simple_grid::SimpleF32GridStep;
+
+    fn steps(count: usize) -> Vec<SimpleF32GridStep> {
+        (0..count)
+            .map(|i| {
+                let color = if i % 3 == 0 { "red" } else { "" };
+                SimpleF32GridStep::from_str(&format!("{}_{}__{i}.5__{color}", i % 7, i % 5))
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_varint_records_round_trip() {
+        let steps = steps(1000);
+        let mut writer = StepWriter::new(
+            Vec::new(),
+            SimpleF32GridStep::type_id(),
+            RecordLayout::Varint,
+        )
+        .unwrap();
+        for step in &steps {
+            writer.push(step).unwrap();
+        }
+        let container = StepContainer::new(writer.finish().unwrap()).unwrap();
+        assert_eq!(container.step_type_id(), "simple_f32_grid_step");
+        assert_eq!(container.len(), 1000);
+        for index in [0, 1, 255, 256, 257, 999] {
+            let decoded = SimpleF32GridStep::decode(container.record(index).unwrap()).unwrap();
+            assert_eq!(decoded.to_str(), steps[index].to_str());
+        }
+        assert!(container.record(1000).is_none());
+    }
+
+    #[test]
+    fn test_fixed_records() {
+        let mut writer = StepWriter::new(Vec::new(), "grid", RecordLayout::Fixed(4)).unwrap();
+        let err = writer.push(&steps(1)[0]).unwrap_err();
+        assert!(matches!(err, ParseError::InvalidFormat(_)));
+
+        let mut bytes = writer.finish().unwrap();
+        bytes.extend([1, 2, 3, 4, 5, 6, 7, 8]);
+        let container = StepContainer::new(bytes.clone()).unwrap();
+        assert_eq!(container.len(), 2);
+        assert_eq!(container.record(1), Some(&[5, 6, 7, 8][..]));
+
+        bytes.push(9);
+        assert!(StepContainer::new(bytes).is_err());
+        assert!(StepContainer::new(b"title: x".to_vec()).is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use std::fmt::{self, Display, Write};
+
+use crate::core::split::ESCAPE;
+
+// ============================================================================
+// STEP CAPTIONS
+// ============================================================================
+
+/// Opens a caption written after a step, `0__x____ @"comparing 3 and 5"`.
+pub(crate) const CAPTION_START: &str = "@\"";
+const QUOTE: char = '"';
+
+/// Text explaining what a step does, written after the step as `@"caption"`, or
+/// `@"caption" @"note"` for a longer note on top of it.
+///
+/// Captions are kept by the part next to its steps, every step type gets them without knowing
+/// about them. Within the quotes, `"` and `\` are escaped with a backslash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepCaption {
+    pub caption: String,
+    pub note: Option<String>,
+}
+
+/// Splits the captions written at the end of `item` from the step. Text that does not read as
+/// captions is left to the step.
+pub(crate) fn split_caption(item: &str) -> (&str, Option<StepCaption>) {
+    let mut search = 0;
+    while let Some(found) = item[search..].find(CAPTION_START) {
+        let start = search + found;
+        let separated = item[..start]
+            .chars()
+            .next_back()
+            .is_none_or(char::is_whitespace);
+        if separated && let Some(caption) = parse_captions(&item[start..]) {
+            return (item[..start].trim_end(), Some(caption));
+        }
+        search = start + CAPTION_START.len();
+    }
+    (item, None)
+}
+
+/// `@"caption"` or `@"caption" @"note"`, and nothing else.
+fn parse_captions(text: &str) -> Option<StepCaption> {
+    let (caption, rest) = quoted(text)?;
+    let rest = rest.trim_start();
+    if rest.is_empty() {
+        return Some(StepCaption {
+            caption,
+            note: None,
+        });
+    }
+    let (note, rest) = quoted(rest)?;
+    rest.trim().is_empty().then_some(StepCaption {
+        caption,
+        note: Some(note),
+    })
+}
+
+/// The unescaped text of the `@"..."` `text` starts with, and what follows it.
+fn quoted(text: &str) -> Option<(String, &str)> {
+    let end = quoted_end(text, 0)?;
+    let mut value = String::with_capacity(end);
+    let mut chars = text[CAPTION_START.len()..end].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ESCAPE => value.extend(chars.next()),
+            c => value.push(c),
+        }
+    }
+    Some((value, &text[end + QUOTE.len_utf8()..]))
+}
+
+/// If a caption starts at `start`, the index of its closing quote.
+pub(crate) fn quoted_end(text: &str, start: usize) -> Option<usize> {
+    if !text[start..].starts_with(CAPTION_START) {
+        return None;
+    }
+    let mut idx = start + CAPTION_START.len();
+    while let Some(c) = text[idx..].chars().next() {
+        match c {
+            ESCAPE => {
+                idx += c.len_utf8();
+                idx += text[idx..].chars().next().map_or(0, char::len_utf8);
+            }
+            QUOTE => return Some(idx),
+            c => idx += c.len_utf8(),
+        }
+    }
+    None
+}
+
+/// Written as found after a step, `@"caption" @"note"`.
+impl Display for StepCaption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_quoted(f, &self.caption)?;
+        if let Some(note) = &self.note {
+            f.write_char(' ')?;
+            write_quoted(f, note)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_quoted(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    f.write_str(CAPTION_START)?;
+    for c in value.chars() {
+        if c == QUOTE || c == ESCAPE {
+            f.write_char(ESCAPE)?;
+        }
+        f.write_char(c)?;
+    }
+    f.write_char(QUOTE)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_split_caption() {
+        let (step, caption) = split_caption(r#"0__x____ @"comparing \"3\" and 5""#);
+        assert_eq!(step, "0__x____");
+        let caption = caption.unwrap();
+        assert_eq!(caption.caption, r#"comparing "3" and 5"#);
+        assert_eq!(caption.note, None);
+        assert_eq!(split_caption(&format!("a {caption}")), ("a", Some(caption)));
+
+        let (step, caption) = split_caption(r#"0__x____ @"swap" @"3 > 1, | kept""#);
+        assert_eq!(step, "0__x____");
+        assert_eq!(caption.unwrap().note.as_deref(), Some("3 > 1, | kept"));
+
+        // Not captions: unterminated, not at the end, or glued to the step
+        for item in [r#"a @"open"#, r#"a @"b" c"#, r#"a@"b""#] {
+            assert_eq!(split_caption(item), (item, None));
+        }
+    }
+}
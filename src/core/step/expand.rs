@@ -0,0 +1,709 @@
+use std::ops::Range;
+
+use crate::{
+    core::{
+        configuration::Configuration,
+        input::span::Span,
+        split::{ESCAPE, Separators, SplitArray},
+        step::caption::{StepCaption, quoted_end, split_caption},
+    },
+    error::ParseError,
+};
+
+// ============================================================================
+// STEP LIST EXPANSION
+// ============================================================================
+
+/// Written after a macro name to call it, `swap!` or `swap!(1, 2)`.
+const MACRO_CALL: char = '!';
+/// Around steps applied together as a single frame, `[a | b]`.
+pub(crate) const GROUP_START: char = '[';
+pub(crate) const GROUP_END: char = ']';
+/// Starts a bookmark written among the steps, `#loop 1 starts`.
+pub(crate) const BOOKMARK: char = '#';
+
+/// Bounds on what a step list expands to, so that a puzzle from an untrusted source cannot
+/// exhaust memory. Set for every puzzle a registry loads with
+/// [`DomainRegistry::set_expansion_limits`](crate::domains::DomainRegistry::set_expansion_limits).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExpansionLimits {
+    /// Most steps and bookmarks a list expands to. A round of a repetition or loop expanding to
+    /// nothing counts as one
+    pub max_steps: usize,
+    /// Most macro calls nested within each other
+    pub max_macro_depth: usize,
+}
+
+impl Default for ExpansionLimits {
+    fn default() -> Self {
+        Self {
+            max_steps: 1_000_000,
+            max_macro_depth: 64,
+        }
+    }
+}
+
+/// A step once expanded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpandedStep {
+    pub step: String,
+    /// Byte range of the text the step comes from
+    pub range: Range<usize>,
+    /// Whether the step is applied along with the one before it, as part of a `[a | b]` group
+    pub grouped: bool,
+    pub caption: Option<StepCaption>,
+}
+
+/// A bookmark written among the steps, `#name`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Marker {
+    pub name: String,
+    /// Index of the step it is written before, within [`Expansion::steps`]
+    pub before: usize,
+    /// Byte range of the text the marker comes from
+    pub range: Range<usize>,
+}
+
+/// A step list once expanded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Expansion {
+    pub steps: Vec<ExpandedStep>,
+    pub markers: Vec<Marker>,
+}
+
+/// Expands a step list into the plain steps it stands for.
+///
+/// On top of steps separated by the step separator, a list can hold:
+/// - repetitions: `3*(a | b)` is `a | b | a | b | a | b`
+/// - loops over a range of integers, `for i in 0..3: {i}__x____` being
+///   `0__x____ | 1__x____ | 2__x____`. `..=` includes the end, `{i+1}` and `{i-1}` are offset
+///   values, and a body holding several steps is written in parentheses
+/// - calls to the macros of the puzzle, defined as `macro.<name>: <steps>` and written
+///   `name!`, or `name!(a, b)` to replace `{0}` and `{1}` in the macro by `a` and `b`
+///
+/// Any entry can end with a [`StepCaption`], `a @"caption"`. The caption of a repetition, group
+/// or macro call goes to its first step, while captions within the body of a loop are expanded
+/// with it. An entry starting with `#` is a [`Marker`], naming the position it is written at.
+///
+/// Constructs nest. Steps come with their range in `steps`, steps coming from a loop or a macro
+/// with the range of the loop or the call. Errors are located through `span`, the one of `steps`.
+/// Lists going over the [`ExpansionLimits`] of `configuration` fail before they are expanded.
+pub fn expand_steps(
+    steps: &str,
+    span: &Span,
+    configuration: &Configuration,
+) -> Result<Expansion, ParseError> {
+    expand(steps, true, configuration).map_err(|located| {
+        let (error, range) = *located;
+        error.at(span.sub_span(range))
+    })
+}
+
+/// Same as [`expand_steps`] for a single entry of a list, that is not split on the step
+/// separator unless it is a construct.
+pub fn expand_step(
+    step: &str,
+    span: &Span,
+    configuration: &Configuration,
+) -> Result<Expansion, ParseError> {
+    expand(step, false, configuration).map_err(|located| {
+        let (error, range) = *located;
+        error.at(span.sub_span(range))
+    })
+}
+
+/// An expansion error, with the range it is located at.
+pub(crate) type Located<T> = Result<T, Box<(ParseError, Range<usize>)>>;
+
+/// Expansion errors come with the range they are located at, so that spans are only computed
+/// for failing lists.
+pub(crate) fn expand(
+    text: &str,
+    is_list: bool,
+    configuration: &Configuration,
+) -> Located<Expansion> {
+    let mut expander = Expander {
+        configuration,
+        separator: Separators::from_configuration(configuration).step,
+        calls: Vec::new(),
+        limits: configuration.expansion_limits(),
+        empty_rounds: 0,
+        expansion: Expansion::default(),
+    };
+    let range = 0..text.len();
+    if is_list {
+        expander.list(text, &range, true)?;
+    } else {
+        expander.item(text, &range, true)?;
+    }
+    Ok(expander.expansion)
+}
+
+struct Expander<'a> {
+    configuration: &'a Configuration,
+    separator: String,
+    /// Macros being expanded, to catch cycles.
+    calls: Vec<String>,
+    limits: ExpansionLimits,
+    /// Rounds of repetitions and loops that expanded to nothing, which count against the limit.
+    empty_rounds: usize,
+    expansion: Expansion,
+}
+
+impl Expander<'_> {
+    /// Steps, bookmarks and empty rounds expanded so far.
+    fn expanded(&self) -> usize {
+        self.expansion.steps.len() + self.expansion.markers.len() + self.empty_rounds
+    }
+
+    /// Fails unless `count` more entries fit within the limit.
+    fn reserve(&self, count: usize, range: &Range<usize>) -> Located<()> {
+        let limit = self.limits.max_steps;
+        if count > limit.saturating_sub(self.expanded()) {
+            return Err(Box::new((ParseError::TooManySteps(limit), range.clone())));
+        }
+        Ok(())
+    }
+
+    /// Expands `body` once for a round of a repetition or a loop.
+    fn round(&mut self, body: &str, range: &Range<usize>, exact: bool) -> Located<()> {
+        let before = self.expanded();
+        self.list(body, range, exact)?;
+        if self.expanded() == before {
+            self.empty_rounds += 1;
+        }
+        Ok(())
+    }
+
+    /// `exact` tells whether `text` is written as is at `range`, so that its pieces can be
+    /// located; expanded text is located at `range` as a whole.
+    fn list(&mut self, text: &str, range: &Range<usize>, exact: bool) -> Located<()> {
+        for item in split_steps(text, &self.separator) {
+            self.item(item, &locate(range, text, item, exact), exact)?;
+        }
+        Ok(())
+    }
+
+    fn item(&mut self, text: &str, range: &Range<usize>, exact: bool) -> Located<()> {
+        let trimmed = text.trim();
+        let range = &locate(range, text, trimmed, exact);
+        if trimmed.starts_with("for ") {
+            return self.construct(trimmed, range, exact);
+        }
+        let (item, caption) = split_caption(trimmed);
+        let first = self.expansion.steps.len();
+        self.construct(item, &locate(range, trimmed, item, exact), exact)?;
+        if let Some(step) = self.expansion.steps.get_mut(first) {
+            step.caption = caption.or(step.caption.take());
+        }
+        Ok(())
+    }
+
+    fn construct(&mut self, item: &str, range: &Range<usize>, exact: bool) -> Located<()> {
+        let located = |error: ParseError| Box::new((error, range.clone()));
+        if let Some((count, body)) = repetition(item, &self.separator) {
+            let body_range = locate(range, item, body, exact);
+            self.reserve(count, range)?;
+            for _ in 0..count {
+                self.round(body, &body_range, exact)?;
+            }
+        } else if let Some(body) = item.strip_prefix("for ") {
+            let (variable, values, body) = parse_loop(body).map_err(located)?;
+            let body = group(body, '(', &self.separator).unwrap_or(body);
+            let rounds = i128::from(values.end) - i128::from(values.start);
+            self.reserve(usize::try_from(rounds.max(0)).unwrap_or(usize::MAX), range)?;
+            for value in values {
+                let body = substitute(body, variable, value).map_err(located)?;
+                self.round(&body, range, false)?;
+            }
+        } else if let Some(body) = group(item, GROUP_START, &self.separator) {
+            if body.trim().is_empty() {
+                return Err(located(ParseError::InvalidFormat(
+                    "empty step group".to_string(),
+                )));
+            }
+            let first = self.expansion.steps.len();
+            self.list(body, &locate(range, item, body, exact), exact)?;
+            for step in &mut self.expansion.steps[first + 1..] {
+                step.grouped = true;
+            }
+        } else if let Some((name, arguments)) = macro_call(item, &self.separator) {
+            let definition = self
+                .configuration
+                .get_macro(name)
+                .ok_or_else(|| located(ParseError::UnknownMacro(name.to_string())))?;
+            if let Some(start) = self.calls.iter().position(|call| call == name) {
+                let mut cycle = self.calls[start..].to_vec();
+                cycle.push(name.to_string());
+                return Err(located(ParseError::MacroCycle(cycle)));
+            }
+            if self.calls.len() >= self.limits.max_macro_depth {
+                return Err(located(ParseError::MacroTooDeep(
+                    self.limits.max_macro_depth,
+                )));
+            }
+            let mut body = definition.value.clone();
+            for (index, argument) in arguments.iter().enumerate() {
+                body = body.replace(&format!("{{{index}}}"), argument.trim());
+            }
+            self.calls.push(name.to_string());
+            self.list(&body, range, false)?;
+            self.calls.pop();
+        } else if let Some(name) = item.strip_prefix(BOOKMARK) {
+            let name = name.trim();
+            if name.is_empty() {
+                return Err(located(ParseError::InvalidFormat(
+                    "empty bookmark name".to_string(),
+                )));
+            }
+            self.reserve(1, range)?;
+            self.expansion.markers.push(Marker {
+                name: name.to_string(),
+                before: self.expansion.steps.len(),
+                range: range.clone(),
+            });
+        } else {
+            self.reserve(1, range)?;
+            self.expansion.steps.push(ExpandedStep {
+                step: item.to_string(),
+                range: range.clone(),
+                grouped: false,
+                caption: None,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Splits a list on the step separator, except inside the parentheses of a construct, the
+/// brackets of a group or the quotes of a caption.
+fn split_steps<'s>(text: &'s str, separator: &str) -> Vec<&'s str> {
+    if separator.is_empty() {
+        return vec![text];
+    }
+    let mut pieces = Vec::new();
+    let mut piece_start = 0;
+    let mut idx = 0;
+    while let Some(c) = text[idx..].chars().next() {
+        if c == ESCAPE {
+            idx += c.len_utf8();
+            idx += text[idx..].chars().next().map_or(0, char::len_utf8);
+            continue;
+        }
+        if let Some(close) = closing(text, idx, piece_start, separator) {
+            idx = close + 1;
+        } else if text[idx..].starts_with(separator) {
+            pieces.push(&text[piece_start..idx]);
+            idx += separator.len();
+            piece_start = idx;
+        } else {
+            idx += c.len_utf8();
+        }
+    }
+    pieces.push(&text[piece_start..]);
+    pieces
+}
+
+/// If `text[open]` opens the group of a construct within the item starting at `item_start`,
+/// the index of the character closing it. Groups that are never closed are left to the step.
+fn closing(text: &str, open: usize, item_start: usize, separator: &str) -> Option<usize> {
+    match text[open..].chars().next()? {
+        '(' if opens_group(&text[item_start..open]) => matching(text, open, ')', separator),
+        GROUP_START if opens_frame(&text[item_start..open]) => {
+            matching(text, open, GROUP_END, separator)
+        }
+        '@' => quoted_end(text, open),
+        _ => None,
+    }
+}
+
+/// Index of the `close` character ending the group opened at `open`.
+fn matching(text: &str, open: usize, close: char, separator: &str) -> Option<usize> {
+    let mut item_start = open + 1;
+    let mut idx = item_start;
+    while let Some(c) = text[idx..].chars().next() {
+        if c == ESCAPE {
+            idx += c.len_utf8();
+            idx += text[idx..].chars().next().map_or(0, char::len_utf8);
+        } else if c == close {
+            return Some(idx);
+        } else if let Some(inner) = closing(text, idx, item_start, separator) {
+            idx = inner + 1;
+        } else if !separator.is_empty() && text[idx..].starts_with(separator) {
+            idx += separator.len();
+            item_start = idx;
+        } else {
+            idx += c.len_utf8();
+        }
+    }
+    None
+}
+
+/// Whether a parenthesis following `before` opens the group of a construct, rather than being
+/// part of a step.
+fn opens_group(before: &str) -> bool {
+    let before = before.trim();
+    if let Some(count) = before.strip_suffix('*') {
+        return count.trim().parse::<usize>().is_ok();
+    }
+    if let Some(name) = before.strip_suffix(MACRO_CALL) {
+        return is_identifier(name);
+    }
+    is_loop_header(before)
+}
+
+/// Whether a bracket following `before` opens a step group: at the start of an entry, or as the
+/// body of a loop.
+fn opens_frame(before: &str) -> bool {
+    let before = before.trim();
+    before.is_empty() || is_loop_header(before)
+}
+
+fn is_loop_header(before: &str) -> bool {
+    before.starts_with("for ") && before.ends_with(':')
+}
+
+/// The inside of the group `text` is made of, opened by `open`.
+fn group<'s>(text: &'s str, open: char, separator: &str) -> Option<&'s str> {
+    let text = text.trim();
+    let close = match open {
+        '(' => ')',
+        _ => GROUP_END,
+    };
+    if !text.starts_with(open) {
+        return None;
+    }
+    let close = matching(text, 0, close, separator)?;
+    (close + 1 == text.len()).then(|| &text[1..close])
+}
+
+/// `count*(body)`
+fn repetition<'s>(item: &'s str, separator: &str) -> Option<(usize, &'s str)> {
+    let (count, body) = item.split_once('*')?;
+    let count = count.trim().parse().ok()?;
+    Some((count, group(body, '(', separator)?))
+}
+
+/// `variable in start..end: body`, following `for `.
+fn parse_loop(text: &str) -> Result<(&str, Range<i64>, &str), ParseError> {
+    let invalid = || ParseError::InvalidFormat(format!("invalid loop 'for {text}'"));
+    let (header, body) = text.split_once(':').ok_or_else(invalid)?;
+    let [variable, range] = header.split_array_exact(" in ").ok_or_else(invalid)?;
+    let variable = variable.trim();
+    if !is_identifier(variable) {
+        return Err(invalid());
+    }
+    let (start, end, inclusive) = match range.split_once("..=") {
+        Some((start, end)) => (start, end, true),
+        None => {
+            let (start, end) = range.split_once("..").ok_or_else(invalid)?;
+            (start, end, false)
+        }
+    };
+    let start: i64 = start.trim().parse().map_err(|_| invalid())?;
+    let end: i64 = end.trim().parse().map_err(|_| invalid())?;
+    let end = if inclusive {
+        end.checked_add(1).ok_or_else(|| {
+            ParseError::InvalidFormat(format!("loop 'for {text}' goes past the largest integer"))
+        })?
+    } else {
+        end
+    };
+    Ok((variable, start..end, body.trim()))
+}
+
+/// Replaces `{variable}`, `{variable+n}` and `{variable-n}` by their value.
+fn substitute(body: &str, variable: &str, value: i64) -> Result<String, ParseError> {
+    let mut out = String::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let replaced = after.find('}').and_then(|end| {
+            let offset = after[..end].trim().strip_prefix(variable)?.trim();
+            let offset: i64 = if offset.is_empty() {
+                0
+            } else if let Some(offset) = offset.strip_prefix('+') {
+                offset.trim().parse().ok()?
+            } else {
+                -offset.strip_prefix('-')?.trim().parse::<i64>().ok()?
+            };
+            Some((value.checked_add(offset), end))
+        });
+        match replaced {
+            Some((Some(value), end)) => {
+                out.push_str(&value.to_string());
+                rest = &after[end + 1..];
+            }
+            Some((None, end)) => {
+                return Err(ParseError::InvalidFormat(format!(
+                    "'{{{}}}' goes past the integer range at {variable} = {value}",
+                    &after[..end]
+                )));
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// `name!` or `name!(a, b)`
+fn macro_call<'s>(item: &'s str, separator: &str) -> Option<(&'s str, Vec<&'s str>)> {
+    let (name, arguments) = item.split_once(MACRO_CALL)?;
+    if !is_identifier(name) {
+        return None;
+    }
+    if arguments.trim().is_empty() {
+        return Some((name, Vec::new()));
+    }
+    Some((name, group(arguments, '(', separator)?.split_escaped(",")))
+}
+
+/// Steps start with a number in every domain, names cannot.
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Range of `inner`, a slice of `outer` found at `range`.
+fn locate(range: &Range<usize>, outer: &str, inner: &str, exact: bool) -> Range<usize> {
+    if !exact {
+        return range.clone();
+    }
+    let start = range.start + (inner.as_ptr() as usize - outer.as_ptr() as usize);
+    start..start + inner.len()
+}
+
+#[cfg(test)]
+mod test {
+    use indexmap::IndexMap;
+
+    use super::*;
+    use crate::core::input::processors::Leaf;
+
+    fn expand(steps: &str, configuration: &Configuration) -> Result<Vec<String>, ParseError> {
+        let expansion = expand_steps(steps, &Span::default(), configuration)?;
+        Ok(expansion.steps.into_iter().map(|s| s.step).collect())
+    }
+
+    #[test]
+    fn test_expand_steps() {
+        let configuration = Configuration::default().with_macros(IndexMap::from([
+            ("mark".to_string(), Leaf::new("{0}__x__red__ | {0}__x____")),
+            ("twice".to_string(), Leaf::new("2*(mark!({0}))")),
+            ("loop".to_string(), Leaf::new("loop!")),
+        ]));
+        let expand = |steps| expand(steps, &configuration);
+
+        assert_eq!(
+            expand("a | 2*(b | c) | d").unwrap(),
+            ["a", "b", "c", "b", "c", "d"]
+        );
+        assert_eq!(
+            expand("for i in 0..3: {i}__{i+1}____").unwrap(),
+            ["0__1____", "1__2____", "2__3____"]
+        );
+        assert_eq!(
+            expand("for i in 1..=2: ({i}__a____ | {i-1}__b____)").unwrap(),
+            ["1__a____", "0__b____", "2__a____", "1__b____"]
+        );
+        assert_eq!(
+            expand("mark!(3) | twice!(1)").unwrap(),
+            [
+                "3__x__red__",
+                "3__x____",
+                "1__x__red__",
+                "1__x____",
+                "1__x__red__",
+                "1__x____"
+            ]
+        );
+        // Parentheses and stars of ordinary steps are left alone
+        assert_eq!(
+            expand(r"0__(a | b)____ | 0__a:(b____ | 2*3____").unwrap(),
+            ["0__(a", "b)____", "0__a:(b____", "2*3____"]
+        );
+        assert_eq!(expand(r"2*(a\) | b)").unwrap(), [r"a\)", "b", r"a\)", "b"]);
+
+        assert!(matches!(
+            expand("nope!").unwrap_err().inner(),
+            ParseError::UnknownMacro(_)
+        ));
+        assert!(matches!(
+            expand("loop!").unwrap_err().inner(),
+            ParseError::MacroCycle(_)
+        ));
+        assert!(matches!(
+            expand("for i in 0..x: a").unwrap_err().inner(),
+            ParseError::InvalidFormat(_)
+        ));
+        // Values past the integer range are errors, not overflows
+        assert_eq!(
+            expand("for i in 9223372036854775806..9223372036854775807: {i}__x____").unwrap(),
+            ["9223372036854775806__x____"]
+        );
+        for overflowing in [
+            "for i in 9223372036854775807..=9223372036854775807: {i}__x____",
+            "for i in 9223372036854775806..9223372036854775807: {i+5}__x____",
+            "for i in -9223372036854775808..-9223372036854775807: {i-1}__x____",
+        ] {
+            assert!(matches!(
+                expand(overflowing).unwrap_err().inner(),
+                ParseError::InvalidFormat(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_expand_limits() {
+        let configuration = Configuration::default()
+            .with_macros(IndexMap::from([
+                ("one".to_string(), Leaf::new("two!")),
+                ("two".to_string(), Leaf::new("three!")),
+                ("three".to_string(), Leaf::new("a")),
+            ]))
+            .with_expansion_limits(ExpansionLimits {
+                max_steps: 10,
+                max_macro_depth: 2,
+            });
+        let expand = |steps| expand(steps, &configuration);
+
+        assert_eq!(expand("5*(a | b)").unwrap().len(), 10);
+        assert_eq!(expand("two!").unwrap(), ["a"]);
+        // Rounds expanding to nothing count as well
+        assert_eq!(expand("4*(for i in 0..0: a) | 6*(b)").unwrap().len(), 6);
+        for too_many in [
+            "5*(a | b) | c",
+            "1000000000*(a)",
+            "for i in 0..1000000000000: a",
+            "1000000*(1000000*(for i in 0..0: a))",
+            "11*(#mark)",
+        ] {
+            assert!(matches!(
+                expand(too_many).unwrap_err().inner(),
+                ParseError::TooManySteps(10)
+            ));
+        }
+        assert!(matches!(
+            expand("one!").unwrap_err().inner(),
+            ParseError::MacroTooDeep(2)
+        ));
+
+        // Limits hold without being set
+        assert!(matches!(
+            super::expand("1000000000*(a)", true, &Configuration::default())
+                .unwrap_err()
+                .0,
+            ParseError::TooManySteps(_)
+        ));
+    }
+
+    #[test]
+    fn test_expand_step_groups() {
+        let configuration = Configuration::default();
+        let grouped = |steps| -> Vec<(String, bool)> {
+            expand_steps(steps, &Span::default(), &configuration)
+                .unwrap()
+                .steps
+                .into_iter()
+                .map(|expanded| (expanded.step, expanded.grouped))
+                .collect()
+        };
+        let step = |step: &str, grouped| (step.to_string(), grouped);
+
+        assert_eq!(
+            grouped("a | [b | c | d] | e"),
+            [
+                step("a", false),
+                step("b", false),
+                step("c", true),
+                step("d", true),
+                step("e", false)
+            ]
+        );
+        assert_eq!(
+            grouped("2*([a | b])"),
+            [
+                step("a", false),
+                step("b", true),
+                step("a", false),
+                step("b", true)
+            ]
+        );
+        // Brackets within a step are left alone
+        assert_eq!(grouped("0__[a____"), [step("0__[a____", false)]);
+
+        assert!(matches!(
+            expand("a | []", &configuration).unwrap_err().inner(),
+            ParseError::InvalidFormat(_)
+        ));
+    }
+
+    #[test]
+    fn test_expand_captions() {
+        let configuration = Configuration::default().with_macros(IndexMap::from([(
+            "visit".to_string(),
+            Leaf::new(r#"{0}__v____ @"visit {0}""#),
+        )]));
+        let captioned = |steps| -> Vec<(String, Option<String>)> {
+            expand_steps(steps, &Span::default(), &configuration)
+                .unwrap()
+                .steps
+                .into_iter()
+                .map(|expanded| (expanded.step, expanded.caption.map(|c| c.caption)))
+                .collect()
+        };
+        let step =
+            |step: &str, caption: Option<&str>| (step.to_string(), caption.map(str::to_string));
+
+        assert_eq!(
+            captioned(r#"a @"first | one" | for i in 0..2: {i}__x____ @"at {i}" | b"#),
+            [
+                step("a", Some("first | one")),
+                step("0__x____", Some("at 0")),
+                step("1__x____", Some("at 1")),
+                step("b", None)
+            ]
+        );
+        assert_eq!(
+            captioned(r#"[a | b] @"both" | visit!(2) | visit!(3) @"again""#),
+            [
+                step("a", Some("both")),
+                step("b", None),
+                step("2__v____", Some("visit 2")),
+                step("3__v____", Some("again"))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expand_markers() {
+        let configuration = Configuration::default();
+        let expansion = expand_steps(
+            "#start | a | for i in 1..3: (#loop {i} | b) | #end",
+            &Span::default(),
+            &configuration,
+        )
+        .unwrap();
+        let markers: Vec<(&str, usize)> = expansion
+            .markers
+            .iter()
+            .map(|marker| (marker.name.as_str(), marker.before))
+            .collect();
+        assert_eq!(
+            markers,
+            [("start", 0), ("loop 1", 1), ("loop 2", 2), ("end", 3)]
+        );
+        assert_eq!(expansion.steps.len(), 3);
+        assert_eq!(expansion.markers[0].range, 0..6);
+
+        assert!(matches!(
+            expand("a | #", &configuration).unwrap_err().inner(),
+            ParseError::InvalidFormat(_)
+        ));
+    }
+}
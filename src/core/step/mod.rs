@@ -5,7 +5,14 @@
 use std::any::Any;
 use std::fmt::Debug;
 
-use crate::error::ParseError;
+pub mod binary;
+pub mod caption;
+pub mod expand;
+
+use crate::{
+    core::{configuration::Configuration, input::processors::Field},
+    error::ParseError,
+};
 /// Represents a single modification that can be applied to visualization state
 pub trait StepAction: Send + Sync + 'static
 where
@@ -26,4 +33,58 @@ where
     fn from_str(string: &str) -> Result<Self, ParseError>
     where
         Self: Sized;
+
+    /// Parse a raw string into a step, with the configuration of the part it belongs to. Step
+    /// types reading separators from the configuration override this; by default it is
+    /// `from_str`
+    fn from_str_with(string: &str, configuration: &Configuration) -> Result<Self, ParseError>
+    where
+        Self: Sized,
+    {
+        let _ = configuration;
+        Self::from_str(string)
+    }
+
+    /// Parse a structured entry of a step list, such as a JSON object. Step types opt in by
+    /// overriding this; by default only the string form is accepted
+    fn from_field(field: &Field) -> Result<Self, ParseError>
+    where
+        Self: Sized,
+    {
+        let _ = field;
+        Err(ParseError::InvalidFormat(
+            "this step type only accepts steps written as strings".to_string(),
+        ))
+    }
+
+    /// Write the step back to the string form read by `from_str`, so that parsing the result
+    /// gives back an equal step
+    fn to_str(&self) -> String;
+
+    /// Write the step back to the string form read by `from_str_with` with the same
+    /// configuration. By default it is `to_str`
+    fn to_str_with(&self, configuration: &Configuration) -> String {
+        let _ = configuration;
+        self.to_str()
+    }
+
+    /// Append the step to a record of a [`binary::StepContainer`]. Step types opt in by
+    /// overriding this along with `decode`; by default steps can only be written as text
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), ParseError> {
+        let _ = out;
+        Err(ParseError::InvalidFormat(
+            "this step type cannot be written as binary".to_string(),
+        ))
+    }
+
+    /// Read a step back from a record written by `encode`
+    fn decode(record: &[u8]) -> Result<Self, ParseError>
+    where
+        Self: Sized,
+    {
+        let _ = record;
+        Err(ParseError::InvalidFormat(
+            "this step type cannot be read from binary".to_string(),
+        ))
+    }
 }
@@ -1,4 +1,12 @@
-use crate::{StepAction, core::split::SplitArray, error::ParseError};
+use crate::{
+    StepAction,
+    core::{
+        configuration::Configuration,
+        split::{Separators, SplitArray, unescape_trimmed},
+        step::binary::{RecordReader, put_f32, put_opt_str, put_varint},
+    },
+    error::ParseError,
+};
 
 pub mod state;
 
@@ -29,42 +37,28 @@ impl StepAction for SimpleF32GridStep {
     where
         Self: Sized,
     {
-        //FIXME: use some provided separators
-        // let mut list = string.split("__");
+        Self::from_str_with(string, &Configuration::default())
+    }
+
+    /// `x_y__content__color`, with separators overridden by `config.field_separator` and
+    /// `config.token_separator`.
+    fn from_str_with(string: &str, configuration: &Configuration) -> Result<Self, ParseError>
+    where
+        Self: Sized,
+    {
+        let separators = Separators::from_configuration(configuration);
         let [positions, content, color] = string
-            .split_array("__")
+            .split_array_escaped_exact(&separators.field)
             .ok_or(ParseError::InvalidFormat(string.to_string()))?;
-        // let positions = list
-        //     .next()
-        //     .ok_or(ParseError::InvalidFormat(string.to_string()))?;
-        //
-        // let content = list
-        //     .next()
-        //     .ok_or(ParseError::InvalidFormat(string.to_string()))?
-        //     .to_string();
-        //
-        // let color = list
-        //     .next()
-        //     .ok_or(ParseError::InvalidFormat(string.to_string()))?
-        //     .to_string();
-        //
-        // TODO: Separators... This is a simple dash
         let [x, y] = positions
-            .split_array("_")
+            .split_array_escaped_exact(&separators.token)
             .ok_or(ParseError::InvalidFormat(string.to_string()))?;
-        // let mut positions = positions.split("_");
-        //
-        // let x = positions
-        //     .next()
-        //     .ok_or(ParseError::InvalidFormat(string.to_string()))?;
-        // let y = positions
-        //     .next()
-        //     .ok_or(ParseError::InvalidFormat(string.to_string()))?;
-        //
         let position: [usize; 2] = [
-            x.parse()
+            x.trim()
+                .parse()
                 .map_err(|_| ParseError::InvalidFormat(x.to_string()))?,
-            y.parse()
+            y.trim()
+                .parse()
                 .map_err(|_| ParseError::InvalidFormat(y.to_string()))?,
         ];
 
@@ -78,11 +72,59 @@ impl StepAction for SimpleF32GridStep {
                     .map_err(|_| ParseError::InvalidFormat(content.to_string()))?,
             )
         };
-        let color = if color.trim().is_empty() {
-            None
-        } else {
-            Some(color.trim().to_string())
+        let color = unescape_trimmed(color)?;
+        let color = (!color.is_empty()).then(|| color.into_owned());
+        Ok(Self {
+            position,
+            content,
+            color,
+        })
+    }
+
+    fn to_str(&self) -> String {
+        self.to_str_with(&Configuration::default())
+    }
+
+    fn to_str_with(&self, configuration: &Configuration) -> String {
+        let separators = Separators::from_configuration(configuration);
+        let [x, y] = self.position;
+        [
+            [x.to_string(), y.to_string()].join(&separators.token),
+            self.content.map(|c| c.to_string()).unwrap_or_default(),
+            separators.escape(self.color.as_deref().unwrap_or_default()),
+        ]
+        .join(&separators.field)
+    }
+
+    /// `x`, `y` as varints, then a byte telling whether a content follows as an `f32`, then the
+    /// optional color.
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), ParseError> {
+        let [x, y] = self.position;
+        put_varint(out, x as u64);
+        put_varint(out, y as u64);
+        match self.content {
+            Some(content) => {
+                out.push(1);
+                put_f32(out, content);
+            }
+            None => out.push(0),
+        }
+        put_opt_str(out, self.color.as_deref());
+        Ok(())
+    }
+
+    fn decode(record: &[u8]) -> Result<Self, ParseError>
+    where
+        Self: Sized,
+    {
+        let mut reader = RecordReader::new(record);
+        let position = [reader.varint()? as usize, reader.varint()? as usize];
+        let content = match reader.u8()? {
+            0 => None,
+            _ => Some(reader.f32()?),
         };
+        let color = reader.opt_str()?.map(str::to_string);
+        reader.finish()?;
         Ok(Self {
             position,
             content,
@@ -129,20 +171,16 @@ mod test {
     fn test_simple_grid() {
         let content = r#"
         title: Test Puzzle
-        part.tokenize.name: Tokenization
-        part.tokenize.step_type: simple_f32_grid_step
-        part.tokenize.input: hello world
-        part.tokenize.steps: 0__goodbye__ __
+        config.columns: 2
+        config.rows: 2
+        part.fill.name: Fill
+        part.fill.step_type: simple_f32_grid_step
+        part.fill.input: 0:0:0:0
+        part.fill.steps: 0_1__2.5__red | 1_1____
     "#;
         let s = crate::engine::VisualizationEngine::from_source(
             crate::algorithm::PuzzleSource::String(content.to_string()),
         );
-        match s {
-            Ok(_) => todo!(),
-            Err(e) => {
-                dbg!(e);
-                panic!();
-            }
-        }
+        assert!(s.is_ok());
     }
 }
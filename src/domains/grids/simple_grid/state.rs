@@ -1,7 +1,12 @@
 use crate::{
     core::{
         configuration::Configuration,
-        state::{VisualizationState, snapshot::StateSnapshot},
+        split::{Separators, SplitArray, unescape},
+        state::{
+            VisualizationState,
+            schema::{ConfigField, ConfigType},
+            snapshot::StateSnapshot,
+        },
     },
     error::ParseError,
 };
@@ -13,9 +18,20 @@ pub struct SimpleGridCell {
     pub color: Option<String>,
 }
 
-#[derive(Debug)]
+const CONFIG_SCHEMA: &[ConfigField] = &[
+    ConfigField::new("columns", ConfigType::Integer)
+        .with_range(Some(1.0), None)
+        .with_description("Number of columns of the grid"),
+    ConfigField::new("rows", ConfigType::Integer)
+        .with_range(Some(1.0), None)
+        .with_description("Number of rows of the grid"),
+];
+
+#[derive(Debug, Clone)]
 pub struct SimpleGridState {
     content: Vec<SimpleGridCell>,
+    /// The cells seeks replay from
+    initial: Vec<SimpleGridCell>,
     col: usize,
     row: usize,
 }
@@ -38,6 +54,18 @@ pub struct SimpleGridSnapshot {
     row: usize,
 }
 
+impl SimpleGridSnapshot {
+    /// Number of cells in a row, for renderers laying out the cells
+    pub fn columns(&self) -> usize {
+        self.col
+    }
+
+    /// Number of rows of cells
+    pub fn rows(&self) -> usize {
+        self.row
+    }
+}
+
 impl Deref for SimpleGridSnapshot {
     type Target = Vec<SimpleGridCell>;
 
@@ -86,7 +114,22 @@ impl VisualizationState for SimpleGridState {
         step_index: usize,
         all_steps: &[&Self::Step],
     ) -> Result<(), crate::error::StepError> {
-        todo!()
+        let replayed = all_steps
+            .get(..step_index)
+            .ok_or(crate::error::StepError::InvalidIndex(step_index))?;
+        self.content = self.initial.clone();
+        for step in replayed {
+            self.apply_step(step)?;
+        }
+        Ok(())
+    }
+
+    fn mark_initial(&mut self) {
+        self.initial = self.content.clone();
+    }
+
+    fn duplicate(&self) -> Option<Self> {
+        Some(self.clone())
     }
     fn create_snapshot(&self) -> Box<Self::Snapshot> {
         Box::new(SimpleGridSnapshot::from(self))
@@ -98,38 +141,38 @@ impl VisualizationState for SimpleGridState {
         "simple_grid_state"
     }
 
+    fn config_schema() -> &'static [ConfigField] {
+        CONFIG_SCHEMA
+    }
+
     fn parse(input: &str, configuration: &Configuration) -> Result<Self, crate::error::ParseError>
     where
         Self: Sized,
     {
-        //TODO : Make the separators an option to parse
-        let separator = ":";
+        // Cells are `:`-separated unless `config.token_separator` says otherwise
+        let separators = Separators {
+            token: ":".to_string(),
+            ..Separators::default()
+        }
+        .overridden_by(configuration);
         let content: Result<Vec<SimpleGridCell>, ParseFloatError> = input
-            .split(separator)
+            .split_escaped(&separators.token)
+            .into_iter()
             .map(|txt| {
                 Ok(SimpleGridCell {
-                    content: txt.parse()?,
+                    content: unescape(txt).trim().parse()?,
                     color: None,
                 })
             })
             .collect();
-        let col = configuration
-            .get("columns")
-            .and_then(|f| f.as_leaf())
-            .ok_or_else(|| ParseError::RequiredConfigField("columns".to_string()))?
-            .parse()
-            .map_err(|_| ParseError::InvalidFormat("Columns field malformed".to_string()))?;
-
-        let row = configuration
-            .get("rows")
-            .and_then(|f| f.as_leaf())
-            .ok_or_else(|| ParseError::RequiredConfigField("rows".to_string()))?
-            .parse()
-            .map_err(|_| ParseError::InvalidFormat("Row field malformed".to_string()))?;
+        let col = configuration.get_parsed("columns")?;
+        let row = configuration.get_parsed("rows")?;
 
         // FIXME: I need to start implementing the err types From std to avoid these map_err
+        let content = content.map_err(|_| ParseError::InvalidFormat(input.to_string()))?;
         Ok(Self {
-            content: content.map_err(|_| ParseError::InvalidFormat(input.to_string()))?,
+            initial: content.clone(),
+            content,
             col,
             row,
         })
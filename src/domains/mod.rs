@@ -1,5 +1,24 @@
+use std::sync::Arc;
+
 use crate::{
-    core::{input::processors::StepParserFn, state::VisualizationState, step::StepAction},
+    algorithm::{RawSteps, steps_file_lines},
+    core::{
+        configuration::Configuration,
+        input::{
+            processors::{
+                Field, StepDecoderFn, StepParserFn, StepsFile, StructuredStepParserFn,
+                read_steps_file,
+            },
+            span::{SourceText, Span},
+        },
+        state::VisualizationState,
+        step::{
+            StepAction,
+            binary::StepContainer,
+            caption::StepCaption,
+            expand::{ExpandedStep, Expansion, ExpansionLimits, expand, expand_step, expand_steps},
+        },
+    },
     error::ParseError,
 };
 
@@ -10,19 +29,27 @@ pub mod text;
 pub struct DomainRegistry {
     step_type_to_id: fn(&str) -> Result<&'static str, ParseError>,
     get_parser: fn(&str) -> Option<StepParserFn>,
+    get_structured_parser: fn(&str) -> Option<StructuredStepParserFn>,
+    get_decoder: fn(&str) -> Option<StepDecoderFn>,
     pub step_types: Vec<String>,
+    expansion_limits: ExpansionLimits,
 }
 
 impl DomainRegistry {
     pub fn new(
         step_types: Vec<String>,
         get_parser: fn(&str) -> Option<StepParserFn>,
+        get_structured_parser: fn(&str) -> Option<StructuredStepParserFn>,
+        get_decoder: fn(&str) -> Option<StepDecoderFn>,
         step_type_to_id: fn(&str) -> Result<&'static str, ParseError>,
     ) -> Self {
         Self {
             step_types,
             get_parser,
+            get_structured_parser,
+            get_decoder,
             step_type_to_id,
+            expansion_limits: ExpansionLimits::default(),
         }
     }
     pub fn get_supported_types(&self) -> Vec<String> {
@@ -31,34 +58,312 @@ impl DomainRegistry {
     pub fn step_type_to_id(&self, step_type: &str) -> Result<&'static str, ParseError> {
         (self.step_type_to_id)(step_type)
     }
+    /// Bounds the step lists of the puzzles loaded afterwards, which is worth lowering for
+    /// puzzles from untrusted sources.
+    pub fn set_expansion_limits(&mut self, limits: ExpansionLimits) {
+        self.expansion_limits = limits;
+    }
+    pub fn expansion_limits(&self) -> ExpansionLimits {
+        self.expansion_limits
+    }
+    /// Parses the steps of a part. Failing steps are reported with their index and position in
+    /// the source.
+    /// `configuration` is the part's, it sets the separators of the step list and reaches every
+    /// step parser.
     pub fn parse_steps(
         &self,
         step_type_id: &str,
-        steps_str: &str,
+        raw_steps: &RawSteps,
+        configuration: &Configuration,
     ) -> Result<Vec<Box<dyn StepAction>>, ParseError> {
-        let parser =
-            (self.get_parser)(step_type_id).ok_or_else(|| ParseError::UnknownStepType {
-                step_type: step_type_id.to_string(),
-                supported_step_types: self.step_types.clone(),
-            })?;
+        self.step_parsers(step_type_id)
+            .map(|parsers| parse_raw_steps(parsers, raw_steps, configuration).collect())?
+    }
+
+    /// Parses every step of a list, returning all the errors found instead of the first one.
+    pub fn check_steps(
+        &self,
+        step_type_id: &str,
+        raw_steps: &RawSteps,
+        configuration: &Configuration,
+    ) -> Vec<ParseError> {
+        match self.step_parsers(step_type_id) {
+            Ok(parsers) => parse_raw_steps(parsers, raw_steps, configuration)
+                .filter_map(Result::err)
+                .collect(),
+            Err(error) => vec![error],
+        }
+    }
+
+    pub(crate) fn step_parsers(&self, step_type_id: &str) -> Result<StepParsers, ParseError> {
+        let unknown = || ParseError::UnknownStepType {
+            step_type: step_type_id.to_string(),
+            supported_step_types: self.step_types.clone(),
+        };
+        Ok(StepParsers {
+            step_type_id: self.step_type_to_id(step_type_id)?,
+            text: (self.get_parser)(step_type_id).ok_or_else(unknown)?,
+            structured: (self.get_structured_parser)(step_type_id).ok_or_else(unknown)?,
+            decoder: (self.get_decoder)(step_type_id).ok_or_else(unknown)?,
+        })
+    }
+}
+
+/// Everything needed to read the steps of a step type, whichever way they are written.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct StepParsers {
+    pub step_type_id: &'static str,
+    pub text: StepParserFn,
+    pub structured: StructuredStepParserFn,
+    pub decoder: StepDecoderFn,
+}
+
+pub(crate) fn parse_raw_steps<'a>(
+    parsers: StepParsers,
+    raw_steps: &'a RawSteps,
+    configuration: &'a Configuration,
+) -> Box<dyn Iterator<Item = Result<Box<dyn StepAction>, ParseError>> + 'a> {
+    let entries = match raw_steps {
+        RawSteps::File { path, span } => match read_steps_file(path) {
+            Ok(StepsFile::Text(source)) => file_step_entries(&source, configuration),
+            Ok(StepsFile::Binary(container)) => {
+                let results: Vec<_> = match check_container(&parsers, &container, span) {
+                    Ok(()) => (0..container.len())
+                        .map(|index| decode_step(parsers.decoder, &container, index, span))
+                        .collect(),
+                    Err(error) => vec![Err(error)],
+                };
+                return Box::new(results.into_iter());
+            }
+            Err(error) => Err(error.at(span.clone())),
+        },
+        raw_steps => step_entries(raw_steps, configuration),
+    };
+    match entries {
+        Ok(entries) => Box::new(parse_entries(parsers, entries.steps, configuration)),
+        Err(error) => Box::new(std::iter::once(Err(error))),
+    }
+}
+
+/// A step of a list once its repetitions, loops and macros are expanded, before it is parsed.
+pub(crate) enum StepEntry<'a> {
+    /// A step in its string form, written at `step.range` within `span`. Its own span is only
+    /// worked out if it fails to parse
+    Text { step: ExpandedStep, span: Span },
+    /// An entry of a structured list, handed as is to the structured parser
+    Structured { field: &'a Field, grouped: bool },
+}
+
+impl StepEntry<'_> {
+    /// Whether the step is applied along with the one before it, in the same frame.
+    pub(crate) fn grouped(&self) -> bool {
+        match self {
+            StepEntry::Text { step, .. } => step.grouped,
+            StepEntry::Structured { grouped, .. } => *grouped,
+        }
+    }
+
+    /// The caption written after the step, entries of a structured list having none.
+    pub(crate) fn take_caption(&mut self) -> Option<StepCaption> {
+        match self {
+            StepEntry::Text { step, .. } => step.caption.take(),
+            StepEntry::Structured { .. } => None,
+        }
+    }
 
-        parse_steps_with_parser(parser, steps_str)
+    fn set_grouped(&mut self) {
+        match self {
+            StepEntry::Text { step, .. } => step.grouped = true,
+            StepEntry::Structured { grouped, .. } => *grouped = true,
+        }
     }
 }
 
-fn parse_steps_with_parser(
-    parser: StepParserFn,
-    steps_str: &str,
-) -> Result<Vec<Box<dyn StepAction>>, ParseError> {
-    let step_strings: Vec<&str> = steps_str.split('|').map(|s| s.trim()).collect();
-    let mut steps = Vec::new();
+/// The expanded steps of a list, with the bookmarks written among them.
+#[derive(Default)]
+pub(crate) struct StepEntries<'a> {
+    pub steps: Vec<StepEntry<'a>>,
+    pub markers: Vec<EntryMarker>,
+}
+
+/// A bookmark written among the steps, before the step at `before`.
+pub(crate) struct EntryMarker {
+    pub name: String,
+    pub before: usize,
+    pub span: Span,
+}
+
+impl<'a> StepEntries<'a> {
+    fn extend(&mut self, expansion: Expansion, span: &Span, offset: usize) {
+        let first = self.steps.len();
+        self.markers
+            .extend(expansion.markers.into_iter().map(|marker| EntryMarker {
+                name: marker.name,
+                before: first + marker.before,
+                span: span.sub_span(offset + marker.range.start..offset + marker.range.end),
+            }));
+        self.steps
+            .extend(expansion.steps.into_iter().map(|mut step| {
+                step.range = offset + step.range.start..offset + step.range.end;
+                StepEntry::Text {
+                    step,
+                    span: span.clone(),
+                }
+            }));
+    }
 
-    for step_str in step_strings {
-        let step = parser(step_str)?;
-        steps.push(step);
+    /// Index of the first step of every frame, steps of a group making a single frame.
+    pub(crate) fn frame_starts(&self) -> Vec<usize> {
+        self.steps
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| !entry.grouped())
+            .map(|(index, _)| index)
+            .collect()
     }
+}
+
+/// Expands the steps written in the source. The steps of a `steps_file` are expanded by
+/// [`file_step_entries`] once it is read.
+pub(crate) fn step_entries<'a>(
+    raw_steps: &'a RawSteps,
+    configuration: &Configuration,
+) -> Result<StepEntries<'a>, ParseError> {
+    match raw_steps {
+        RawSteps::Delimited(leaf) => {
+            let mut entries = StepEntries::default();
+            let expansion = expand_steps(&leaf.value, &leaf.span, configuration)?;
+            entries.extend(expansion, &leaf.span, 0);
+            Ok(entries)
+        }
+        RawSteps::List(items) => {
+            let mut entries = StepEntries::default();
+            list_entries(items, configuration, &mut entries)?;
+            Ok(entries)
+        }
+        RawSteps::File { path, span } => Err(ParseError::InvalidFormat(format!(
+            "steps of {} have not been read",
+            path.display()
+        ))
+        .at(span.clone())),
+    }
+}
+
+/// Entries of a structured list, where a nested list is a group.
+fn list_entries<'a>(
+    items: &'a [Field],
+    configuration: &Configuration,
+    entries: &mut StepEntries<'a>,
+) -> Result<(), ParseError> {
+    for item in items {
+        match item {
+            Field::Leaf(leaf) => {
+                let expansion = expand_step(&leaf.value, &leaf.span, configuration)?;
+                entries.extend(expansion, &leaf.span, 0);
+            }
+            Field::List(group) => {
+                let first = entries.steps.len();
+                list_entries(group, configuration, entries)?;
+                if entries.steps.len() == first {
+                    let span = item.first_key_span().cloned().unwrap_or_default();
+                    return Err(ParseError::InvalidFormat("empty step group".to_string()).at(span));
+                }
+                entries.steps[first + 1..]
+                    .iter_mut()
+                    .for_each(StepEntry::set_grouped);
+            }
+            field => entries.steps.push(StepEntry::Structured {
+                field,
+                grouped: false,
+            }),
+        }
+    }
+    Ok(())
+}
+
+/// Expands the lines of a text steps file, each of them being a single entry.
+pub(crate) fn file_step_entries(
+    source: &Arc<SourceText>,
+    configuration: &Configuration,
+) -> Result<StepEntries<'static>, ParseError> {
+    let file_span = Span::new(source, 0..source.text.len());
+    let mut entries = StepEntries::default();
+    for line in steps_file_lines(&source.text) {
+        let offset = line.as_ptr() as usize - source.text.as_ptr() as usize;
+        let expansion = expand(line, false, configuration).map_err(|located| {
+            let (error, range) = *located;
+            error.at(file_span.sub_span(offset + range.start..offset + range.end))
+        })?;
+        entries.extend(expansion, &file_span, offset);
+    }
+    Ok(entries)
+}
+
+/// Parses expanded steps, reporting failing ones with their index and position in the source.
+pub(crate) fn parse_entries<'a>(
+    parsers: StepParsers,
+    entries: Vec<StepEntry<'a>>,
+    configuration: &'a Configuration,
+) -> impl Iterator<Item = Result<Box<dyn StepAction>, ParseError>> + 'a {
+    entries
+        .into_iter()
+        .enumerate()
+        .map(move |(index, entry)| match entry {
+            StepEntry::Text { step, span } => {
+                (parsers.text)(&step.step, configuration).map_err(|error| ParseError::InvalidStep {
+                    index,
+                    error: Box::new(error),
+                    span: span.sub_span(step.range),
+                })
+            }
+            StepEntry::Structured { field, .. } => {
+                (parsers.structured)(field).map_err(|error| ParseError::InvalidStep {
+                    index,
+                    error: Box::new(error),
+                    span: field
+                        .span()
+                        .or_else(|| field.first_key_span())
+                        .cloned()
+                        .unwrap_or_default(),
+                })
+            }
+        })
+}
+
+/// Fails if a binary steps file holds steps of another type than its part's. `span` is the one
+/// of the `steps_file` entry.
+pub(crate) fn check_container(
+    parsers: &StepParsers,
+    container: &StepContainer,
+    span: &Span,
+) -> Result<(), ParseError> {
+    if container.step_type_id() == parsers.step_type_id {
+        return Ok(());
+    }
+    Err(ParseError::InvalidFormat(format!(
+        "binary steps file holds steps of type '{}', expected '{}'",
+        container.step_type_id(),
+        parsers.step_type_id
+    ))
+    .at(span.clone()))
+}
 
-    Ok(steps)
+/// Decodes one record of a binary steps file, failing like an invalid text step would.
+pub(crate) fn decode_step(
+    decoder: StepDecoderFn,
+    container: &StepContainer,
+    index: usize,
+    span: &Span,
+) -> Result<Box<dyn StepAction>, ParseError> {
+    container
+        .record(index)
+        .ok_or_else(|| ParseError::InvalidFormat(format!("binary steps: no step {index}")))
+        .and_then(decoder)
+        .map_err(|error| ParseError::InvalidStep {
+            index,
+            error: Box::new(error),
+            span: span.clone(),
+        })
 }
 
 crate::register_domain_types!(
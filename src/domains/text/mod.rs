@@ -1,6 +1,17 @@
 pub mod state;
 
-use crate::{core::step::StepAction, error::ParseError};
+use crate::{
+    core::{
+        configuration::Configuration,
+        input::processors::Field,
+        split::{Separators, SplitArray, unescape_trimmed},
+        step::{
+            StepAction,
+            binary::{RecordReader, put_opt_str, put_varint},
+        },
+    },
+    error::ParseError,
+};
 #[derive(Debug)]
 pub struct TextStep {
     position: usize,
@@ -30,46 +41,106 @@ impl StepAction for TextStep {
     where
         Self: Sized,
     {
-        //FIXME: use some provided separators
-        let mut list = string.split("__");
-        let position = list
-            .next()
+        Self::from_str_with(string, &Configuration::default())
+    }
+
+    /// `position__content__background__foreground`, with the separator overridden by
+    /// `config.field_separator`. Escaped separators (`\_`) and escaped whitespace around a field
+    /// (`a\ `) are kept in it.
+    fn from_str_with(string: &str, configuration: &Configuration) -> Result<Self, ParseError>
+    where
+        Self: Sized,
+    {
+        let separators = Separators::from_configuration(configuration);
+        // Fields past the fourth are ignored
+        let fields = string.split_escaped(&separators.field);
+        let [position, content, bg, fg] = fields
+            .get(..4)
+            .and_then(|fields| <[&str; 4]>::try_from(fields).ok())
             .ok_or(ParseError::InvalidFormat(string.to_string()))?;
-        let content = list
-            .next()
-            .ok_or(ParseError::InvalidFormat(string.to_string()))?
-            .to_string();
-        let bg = list
-            .next()
-            .ok_or(ParseError::InvalidFormat(string.to_string()))?
-            .to_string();
-        let fg = list
-            .next()
-            .ok_or(ParseError::InvalidFormat(string.to_string()))?
-            .to_string();
         let position: usize = position
+            .trim()
             .parse()
             .map_err(|_| ParseError::InvalidFormat(position.to_string()))?;
-        let content = if content.trim().is_empty() {
-            None
-        } else {
-            Some(content.trim().to_string())
-        };
-        let bg = if bg.trim().is_empty() {
-            None
-        } else {
-            Some(bg.trim().to_string())
+        let optional = |field: &str| {
+            let field = unescape_trimmed(field)?;
+            Ok::<_, ParseError>((!field.is_empty()).then(|| field.into_owned()))
         };
-        let fg = if fg.trim().is_empty() {
-            None
-        } else {
-            Some(fg.trim().to_string())
+        Ok(Self {
+            position,
+            content: optional(content)?,
+            background_color: optional(bg)?,
+            foreground_color: optional(fg)?,
+        })
+    }
+
+    /// Structured form: `{ "position": 3, "content": "a", "background": "red", "foreground": … }`,
+    /// where everything but `position` is optional.
+    fn from_field(field: &Field) -> Result<Self, ParseError>
+    where
+        Self: Sized,
+    {
+        let text = |key: &str| {
+            field
+                .get_path(&[key])
+                .and_then(Field::as_leaf)
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
         };
+        let position = text("position")
+            .ok_or_else(|| ParseError::InvalidFormat("step is missing 'position'".to_string()))?;
+        let position = position
+            .parse()
+            .map_err(|_| ParseError::InvalidFormat(position.to_string()))?;
+        Ok(Self {
+            position,
+            content: text("content"),
+            background_color: text("background"),
+            foreground_color: text("foreground"),
+        })
+    }
+
+    fn to_str(&self) -> String {
+        self.to_str_with(&Configuration::default())
+    }
+
+    fn to_str_with(&self, configuration: &Configuration) -> String {
+        let separators = Separators::from_configuration(configuration);
+        let escape =
+            |value: &Option<String>| separators.escape(value.as_deref().unwrap_or_default());
+        [
+            self.position.to_string(),
+            escape(&self.content),
+            escape(&self.background_color),
+            escape(&self.foreground_color),
+        ]
+        .join(&separators.field)
+    }
+
+    /// The position as a varint, then the optional content and colors.
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), ParseError> {
+        put_varint(out, self.position as u64);
+        put_opt_str(out, self.content.as_deref());
+        put_opt_str(out, self.background_color.as_deref());
+        put_opt_str(out, self.foreground_color.as_deref());
+        Ok(())
+    }
+
+    fn decode(record: &[u8]) -> Result<Self, ParseError>
+    where
+        Self: Sized,
+    {
+        let mut reader = RecordReader::new(record);
+        let position = reader.varint()? as usize;
+        let mut text = || Ok::<_, ParseError>(reader.opt_str()?.map(str::to_string));
+        let (content, background_color, foreground_color) = (text()?, text()?, text()?);
+        reader.finish()?;
         Ok(Self {
             position,
             content,
-            background_color: bg,
-            foreground_color: fg,
+            background_color,
+            foreground_color,
         })
     }
 }
@@ -1,5 +1,6 @@
 use crate::core::{
     configuration::Configuration,
+    split::{Separators, SplitArray, unescape},
     state::{VisualizationState, snapshot::StateSnapshot},
 };
 use std::ops::Deref;
@@ -11,9 +12,11 @@ pub struct TextRepresentation {
     pub foreground_color: Option<String>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TextState {
     content: Vec<TextRepresentation>,
+    /// The content seeks replay from
+    initial: Vec<TextRepresentation>,
 }
 impl StateSnapshot for TextSnapshot {
     fn snapshot_type_id() -> &'static str
@@ -71,7 +74,22 @@ impl VisualizationState for TextState {
         step_index: usize,
         all_steps: &[&Self::Step],
     ) -> Result<(), crate::error::StepError> {
-        todo!()
+        let replayed = all_steps
+            .get(..step_index)
+            .ok_or(crate::error::StepError::InvalidIndex(step_index))?;
+        self.content = self.initial.clone();
+        for step in replayed {
+            self.apply_step(step)?;
+        }
+        Ok(())
+    }
+
+    fn mark_initial(&mut self) {
+        self.initial = self.content.clone();
+    }
+
+    fn duplicate(&self) -> Option<Self> {
+        Some(self.clone())
     }
     fn create_snapshot(&self) -> Box<Self::Snapshot> {
         Box::new(TextSnapshot(self.content.clone()))
@@ -84,20 +102,23 @@ impl VisualizationState for TextState {
     }
 
     //TODO: Could implement a configuration for bg/fg color
-    fn parse(input: &str, _configuration: &Configuration) -> Result<Self, crate::error::ParseError>
+    fn parse(input: &str, configuration: &Configuration) -> Result<Self, crate::error::ParseError>
     where
         Self: Sized,
     {
-        //TODO : Make the separators an option to parse
-        let separator = "_";
+        let separators = Separators::from_configuration(configuration);
         let content = input
-            .split(separator)
+            .split_escaped(&separators.token)
+            .into_iter()
             .map(|txt| TextRepresentation {
-                content: txt.to_string(),
+                content: unescape(txt).into_owned(),
                 background_color: None,
                 foreground_color: None,
             })
-            .collect();
-        Ok(Self { content })
+            .collect::<Vec<_>>();
+        Ok(Self {
+            initial: content.clone(),
+            content,
+        })
     }
 }
@@ -4,15 +4,21 @@
 
 pub mod registry;
 pub mod selectors;
-use std::{any::TypeId, marker::PhantomData};
+use std::{any::TypeId, marker::PhantomData, ops::Range};
 
 use crate::{
     HasContextTag, RenderContext,
-    algorithm::{AlgorithmInstance, Current, Metadata, PartInfo, PuzzleSource},
-    core::{render::RendererProxy, state::StateInfo},
+    algorithm::{
+        AlgorithmInstance, Bookmark, Chapter, Current, Metadata, PartInfo, PartSteps, PuzzleSource,
+    },
+    core::{
+        render::RendererProxy,
+        state::{StateInfo, StateProxy, schema::PartConfigProblems},
+        step::caption::StepCaption,
+    },
     error::{ParseError, VisualizationError},
 };
-use registry::Registry;
+use registry::{Registry, StateRegistry};
 use selectors::{PartSelector, RendererSelector, StateSelector};
 // #[derive(Debug, Clone, Eq, PartialEq)]
 /// Manages renderers and coordinates visualization
@@ -49,12 +55,43 @@ impl VisualizationEngine {
         Ok(engine)
     }
     fn load_puzzle_from_source(&mut self, source: PuzzleSource) -> Result<(), ParseError> {
-        let puzzle = AlgorithmInstance::from_source(source, self.registry.domain_registry())?;
+        let puzzle = AlgorithmInstance::from_source_with_formats(
+            source,
+            self.registry.domain_registry(),
+            self.registry.format_registry(),
+        )?;
+        self.validate_configurations(&puzzle)?;
         self.algorithm = Some(puzzle);
         // TODO: Set up initial current state...
         Ok(())
     }
 
+    /// Checks every part's configuration, inheritance included, against its default state.
+    fn validate_configurations(&self, puzzle: &AlgorithmInstance) -> Result<(), ParseError> {
+        let state_registry = self.registry.state_registry();
+        let problems: Vec<PartConfigProblems> = puzzle
+            .parts
+            .iter()
+            .filter_map(|part| {
+                let state = state_registry
+                    .get(part.step_type_id)?
+                    .iter()
+                    .find(|state| state.is_default)?;
+                let problems = state.validate(&part.configuration);
+                (!problems.is_empty()).then(|| PartConfigProblems {
+                    part: part.id.clone(),
+                    state: state.type_id,
+                    problems,
+                })
+            })
+            .collect();
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ParseError::InvalidConfiguration(problems))
+        }
+    }
+
     pub fn configure_for_current_context<C: RenderContext + HasContextTag + 'static>(
         &'_ mut self,
     ) -> ContextConfiguration<'_, C> {
@@ -121,6 +158,10 @@ impl VisualizationEngine {
         let select = selector
             .resolve_selection()
             .ok_or(VisualizationError::NoPartLoaded)?;
+        let puzzle = self.algorithm.as_ref().unwrap();
+        if let Some(part) = puzzle.get_part(&select.id) {
+            part.steps().map_err(VisualizationError::InvalidSteps)?;
+        }
         self.algorithm.as_mut().unwrap().current = Some(Current {
             step: 0,
             part_id: select.id.clone(),
@@ -132,7 +173,7 @@ impl VisualizationEngine {
     // ============================================================================
     // STEPPING CONTROL METHODS
     // ============================================================================
-    /// Execute the next step in the sequence
+    /// Execute the next step in the sequence, every action of it for a step group
     pub fn next_step(&mut self) -> Result<(), VisualizationError> {
         let puzzle = self
             .algorithm
@@ -149,9 +190,14 @@ impl VisualizationEngine {
             .state
             .as_mut()
             .ok_or(VisualizationError::MissingState)?;
-        if current.step < part.steps.len() {
-            let step = &part.steps[current.step];
-            state.inner.apply_step_erased(step.as_ref())?;
+        let steps = part.steps().map_err(VisualizationError::InvalidSteps)?;
+        if current.step < steps.len() {
+            let frame = steps
+                .range(steps.frame(current.step))
+                .map_err(VisualizationError::InvalidSteps)?;
+            for step in frame.iter() {
+                state.inner.apply_step_erased(step.as_ref())?;
+            }
             current.step += 1;
             Ok(())
         } else {
@@ -178,7 +224,8 @@ impl VisualizationEngine {
             .ok_or(VisualizationError::NoPartLoaded)?;
         if current.step > 0 {
             let target_step = current.step - 1;
-            state.inner.seek_to_step_erased(target_step, &part.steps)?;
+            let steps = part.steps().map_err(VisualizationError::InvalidSteps)?;
+            seek(state.inner.as_mut(), steps, steps.frame_start(target_step))?;
             current.step = target_step;
             Ok(())
         } else {
@@ -203,8 +250,9 @@ impl VisualizationEngine {
         let part = current
             .current_part(&puzzle.parts)
             .ok_or(VisualizationError::NoPartLoaded)?;
-        if step_index <= part.steps.len() {
-            state.inner.seek_to_step_erased(step_index, &part.steps)?;
+        let steps = part.steps().map_err(VisualizationError::InvalidSteps)?;
+        if step_index <= steps.len() {
+            seek(state.inner.as_mut(), steps, steps.frame_start(step_index))?;
             current.step = step_index;
             Ok(())
         } else {
@@ -212,6 +260,42 @@ impl VisualizationEngine {
         }
     }
 
+    /// Jump to the step a bookmark of the current part is at
+    pub fn goto_bookmark(&mut self, name: &str) -> Result<(), VisualizationError> {
+        let step = self
+            .bookmarks()?
+            .iter()
+            .find(|bookmark| bookmark.name == name)
+            .ok_or_else(|| VisualizationError::UnknownBookmark(name.to_string()))?
+            .step;
+        self.goto_step(step)
+    }
+
+    /// Jump to the first bookmark after the current step
+    pub fn next_bookmark(&mut self) -> Result<(), VisualizationError> {
+        let (current, _) = self.current_step_info()?;
+        let step = self
+            .bookmarks()?
+            .iter()
+            .find(|bookmark| bookmark.step > current)
+            .ok_or(VisualizationError::NoMoreBookmarks)?
+            .step;
+        self.goto_step(step)
+    }
+
+    /// Jump to the last bookmark before the current step
+    pub fn previous_bookmark(&mut self) -> Result<(), VisualizationError> {
+        let (current, _) = self.current_step_info()?;
+        let step = self
+            .bookmarks()?
+            .iter()
+            .rev()
+            .find(|bookmark| bookmark.step < current)
+            .ok_or(VisualizationError::NoMoreBookmarks)?
+            .step;
+        self.goto_step(step)
+    }
+
     /// Reset to the beginning
     pub fn reset(&mut self) -> Result<(), VisualizationError> {
         let puzzle = self
@@ -229,10 +313,12 @@ impl VisualizationEngine {
         let part = current
             .current_part(&puzzle.parts)
             .ok_or(VisualizationError::NoPartLoaded)?;
-        state
-            .reset(&part.input_data, &part.configuration)
-            .ok()
-            .ok_or(VisualizationError::MissingState)?;
+        state.inner = initial_state(
+            &puzzle.parts,
+            part,
+            &state.info,
+            self.registry.state_registry(),
+        )?;
         current.step = 0;
         Ok(())
     }
@@ -262,7 +348,8 @@ impl VisualizationEngine {
         let part = current
             .current_part(&puzzle.parts)
             .ok_or(VisualizationError::NoPartLoaded)?;
-        Ok(current.step < part.steps.len())
+        let steps = part.steps().map_err(VisualizationError::InvalidSteps)?;
+        Ok(current.step < steps.len())
     }
 
     /// Check if we can step backward
@@ -279,7 +366,8 @@ impl VisualizationEngine {
         Ok(current.step > 0)
     }
 
-    /// Get current step information
+    /// Get current step information, as the current step and the step count. A step group
+    /// counts as a single step.
     pub fn current_step_info(&self) -> Result<(usize, usize), VisualizationError> {
         let puzzle = self
             .algorithm
@@ -293,7 +381,44 @@ impl VisualizationEngine {
             .current_part(&puzzle.parts)
             .ok_or(VisualizationError::NoPartLoaded)?;
 
-        Ok((current.step, part.steps.len()))
+        let steps = part.steps().map_err(VisualizationError::InvalidSteps)?;
+        Ok((current.step, steps.len()))
+    }
+
+    /// Get the caption of the step last played, if it has one. There is none before the first
+    /// step.
+    pub fn current_step_caption(&self) -> Result<Option<&StepCaption>, VisualizationError> {
+        let puzzle = self
+            .algorithm
+            .as_ref()
+            .ok_or(VisualizationError::NoPuzzleLoaded)?;
+        let current = puzzle
+            .current
+            .as_ref()
+            .ok_or(VisualizationError::NoPartLoaded)?;
+        let part = current
+            .current_part(&puzzle.parts)
+            .ok_or(VisualizationError::NoPartLoaded)?;
+
+        let steps = part.steps().map_err(VisualizationError::InvalidSteps)?;
+        Ok(current
+            .step
+            .checked_sub(1)
+            .and_then(|step| steps.caption(step)))
+    }
+
+    /// Get the bookmarks of the current part, sorted by step
+    pub fn bookmarks(&self) -> Result<&[Bookmark], VisualizationError> {
+        self.current_part()?
+            .bookmarks()
+            .map_err(VisualizationError::InvalidSteps)
+    }
+
+    /// Get the chapters the bookmarks split the current part into, for a segmented timeline
+    pub fn chapters(&self) -> Result<Vec<Chapter>, VisualizationError> {
+        self.current_part()?
+            .chapters()
+            .map_err(VisualizationError::InvalidSteps)
     }
 
     // ============================================================================
@@ -338,6 +463,121 @@ impl VisualizationEngine {
     }
 }
 
+/// Builds the state a part starts from: parsed from its input, or the final state of the part its
+/// `input_from` names, converted to `info` if that part is played on another state type. That
+/// final state is played once and kept on the part, for states that can be copied or converted.
+fn initial_state(
+    parts: &[PartInfo],
+    part: &PartInfo,
+    info: &StateInfo,
+    states: &StateRegistry,
+) -> Result<Box<dyn StateProxy>, VisualizationError> {
+    let Some(source_id) = &part.input_from else {
+        return (info.factory)(&part.input_data, &part.configuration)
+            .map_err(VisualizationError::StateCreation);
+    };
+    let source = parts
+        .iter()
+        .find(|source| &source.id == source_id)
+        .ok_or_else(|| {
+            VisualizationError::StateCreation(ParseError::UnknownInputPart(source_id.clone()))
+        })?;
+    // The source is played on the same state type when its steps allow it
+    let candidates = states.get(source.step_type_id).into_iter().flatten();
+    let (source_info, conversion) = candidates
+        .clone()
+        .find(|candidate| candidate.type_id == info.type_id)
+        .map(|candidate| (candidate, None))
+        .or_else(|| {
+            candidates.clone().find_map(|candidate| {
+                let conversion = states.get_conversion(candidate.type_id, info.type_id)?;
+                Some((candidate, Some(conversion)))
+            })
+        })
+        .ok_or_else(|| VisualizationError::NoStateConversion {
+            part: source.id.clone(),
+            state: info.type_id,
+        })?;
+
+    let from_final = |final_state: &dyn StateProxy| match conversion {
+        Some(conversion) => conversion(final_state, &part.configuration)
+            .map(Some)
+            .map_err(VisualizationError::StateCreation),
+        None => Ok(final_state.duplicate_erased()),
+    };
+    let cached = source
+        .final_state
+        .get()
+        .filter(|(type_id, _)| *type_id == source_info.type_id);
+    let copied = match cached {
+        Some((_, final_state)) => from_final(final_state.as_ref())?,
+        None => None,
+    };
+    let mut state = match copied {
+        Some(state) => state,
+        None => {
+            let final_state = play(parts, source, source_info, states)?;
+            match from_final(final_state.as_ref())? {
+                Some(state) => {
+                    // A final state on another state type may be kept already, this one is not
+                    let _ = source.final_state.set((source_info.type_id, final_state));
+                    state
+                }
+                None => final_state,
+            }
+        }
+    };
+    state.mark_initial_erased();
+    Ok(state)
+}
+
+/// Plays every step of `part` on an `info` state.
+fn play(
+    parts: &[PartInfo],
+    part: &PartInfo,
+    info: &StateInfo,
+    states: &StateRegistry,
+) -> Result<Box<dyn StateProxy>, VisualizationError> {
+    let mut state = initial_state(parts, part, info, states)?;
+    let steps = part.steps().map_err(VisualizationError::InvalidSteps)?;
+    apply_steps(state.as_mut(), steps, 0..steps.step_count())?;
+    Ok(state)
+}
+
+/// Moves `state` to the step at `step_index`, every action of a step group counting as a step.
+/// Parsed steps are handed to the state's own seek. Steps of a binary steps file are not all
+/// decoded for it: the state seeks back to its start, then the records up to `step_index` are
+/// decoded and applied one at a time.
+fn seek(
+    state: &mut dyn StateProxy,
+    steps: &PartSteps,
+    step_index: usize,
+) -> Result<(), VisualizationError> {
+    match steps {
+        PartSteps::Parsed { steps, .. } => state.seek_to_step_erased(step_index, steps)?,
+        PartSteps::Binary { .. } => {
+            state.seek_to_step_erased(0, &[])?;
+            apply_steps(state, steps, 0..step_index)?;
+        }
+    }
+    Ok(())
+}
+
+/// Applies the steps within `range` in order, decoding those of a binary steps file one at a time.
+fn apply_steps(
+    state: &mut dyn StateProxy,
+    steps: &PartSteps,
+    range: Range<usize>,
+) -> Result<(), VisualizationError> {
+    for index in range {
+        let step = steps
+            .range(index..index + 1)
+            .map_err(VisualizationError::InvalidSteps)?;
+        state.apply_step_erased(step[0].as_ref())?;
+    }
+    Ok(())
+}
+
 impl Default for VisualizationEngine {
     fn default() -> Self {
         Self::new()
@@ -413,9 +653,22 @@ impl<C: RenderContext + 'static> ContextConfiguration<'_, C> {
         let selected_state: StateInfo = selection
             .resolve_selection()
             .ok_or(VisualizationError::NoRendererSelected)?;
-        let state = (selected_state.factory)(&part.input_data, &part.configuration)
-            .ok()
-            .ok_or(VisualizationError::NoRendererSelected)?; //FIXME:
+        let problems = selected_state.validate(&part.configuration);
+        if !problems.is_empty() {
+            return Err(VisualizationError::InvalidConfiguration(
+                PartConfigProblems {
+                    part: part.id.clone(),
+                    state: selected_state.type_id,
+                    problems,
+                },
+            ));
+        }
+        let state = initial_state(
+            &puzzle.parts,
+            part,
+            &selected_state,
+            self.engine.registry.state_registry(),
+        )?;
         puzzle.state = Some(crate::algorithm::State {
             inner: state,
             info: selected_state,
@@ -6,8 +6,11 @@ use std::any::TypeId;
 use std::collections::HashMap;
 
 use super::RendererProxy;
-use crate::core::state::StateInfo;
+pub use crate::core::input::format::FormatRegistry;
 use crate::core::state::snapshot::StateSnapshot;
+use crate::core::state::{
+    FromState, StateConversionFn, StateInfo, VisualizationState, convert_state,
+};
 pub use crate::domains::DomainRegistry;
 use crate::{HasContextTag, Renderer};
 // Registry for managing available renderers
@@ -98,6 +101,8 @@ impl RendererKey {
 
 pub struct StateRegistry {
     states: HashMap<&'static str, Vec<StateInfo>>,
+    /// Conversions between state types, by source and target state type ids
+    conversions: HashMap<(&'static str, &'static str), StateConversionFn>,
 }
 
 impl StateRegistry {
@@ -107,17 +112,41 @@ impl StateRegistry {
     pub fn from_mappings(mapping: &HashMap<&'static str, Vec<StateInfo>>) -> Self {
         Self {
             states: mapping.clone(),
+            conversions: HashMap::new(),
         }
     }
+
+    /// Declares that a part played on an `S` state can feed a part using a `T` state, through
+    /// `input_from`.
+    pub fn register_conversion<S, T>(&mut self)
+    where
+        S: VisualizationState,
+        T: FromState<S> + Sync,
+    {
+        self.conversions.insert(
+            (S::state_type_id(), T::state_type_id()),
+            convert_state::<S, T>,
+        );
+    }
+
+    /// The conversion from states of type `from` to states of type `to`, if one is declared.
+    pub fn get_conversion(&self, from: &str, to: &str) -> Option<StateConversionFn> {
+        self.conversions
+            .iter()
+            .find(|((source, target), _)| *source == from && *target == to)
+            .map(|(_, conversion)| *conversion)
+    }
 }
 
 pub struct Registry {
     renderer_registry: RendererRegistry,
     state_registry: StateRegistry,
     domain_registry: DomainRegistry,
+    format_registry: FormatRegistry,
 }
 
 impl Registry {
+    /// Creates a registry reading the built-in puzzle formats, see [`FormatRegistry::default`].
     pub fn new(
         renderer_registry: RendererRegistry,
         state_registry: StateRegistry,
@@ -127,6 +156,7 @@ impl Registry {
             renderer_registry,
             state_registry,
             domain_registry,
+            format_registry: FormatRegistry::default(),
         }
     }
 
@@ -139,6 +169,9 @@ impl Registry {
     pub fn domain_registry_mut(&mut self) -> &mut DomainRegistry {
         &mut self.domain_registry
     }
+    pub fn format_registry_mut(&mut self) -> &mut FormatRegistry {
+        &mut self.format_registry
+    }
 
     pub fn renderer_registry(&self) -> &RendererRegistry {
         &self.renderer_registry
@@ -149,4 +182,7 @@ impl Registry {
     pub fn domain_registry(&self) -> &DomainRegistry {
         &self.domain_registry
     }
+    pub fn format_registry(&self) -> &FormatRegistry {
+        &self.format_registry
+    }
 }
@@ -1,6 +1,9 @@
 use crate::{
     algorithm::PartInfo,
-    core::{render::RendererProxy, state::StateInfo},
+    core::{
+        render::RendererProxy,
+        state::{StateInfo, schema::ConfigField},
+    },
 };
 
 use super::registry::StateRegistry;
@@ -35,6 +38,10 @@ impl StateOption {
     pub fn snapshot_id(&self) -> &'static str {
         self.info.snapshot_type_id
     }
+    /// The configuration entries the state reads, to build configuration forms from.
+    pub fn config_schema(&self) -> &'static [ConfigField] {
+        self.info.config_schema
+    }
 }
 
 pub struct StateSelector {
@@ -1,4 +1,10 @@
-use std::{fmt, string::FromUtf8Error};
+use std::{fmt, string::FromUtf8Error, time::Duration};
+
+use crate::core::{
+    configuration::ConfigLevel,
+    input::{processors::Field, span::Span},
+    state::schema::PartConfigProblems,
+};
 
 // ============================================================================
 // ERROR TYPES
@@ -23,6 +29,94 @@ pub enum ParseError {
         supported_step_types: Vec<String>,
     },
     EmptyPath,
+    UnknownFormat {
+        format: String,
+        supported_formats: Vec<String>,
+    },
+    UnterminatedBlock {
+        key: String,
+        line: usize,
+    },
+    /// An `include` directive names a file that cannot be read.
+    Include {
+        path: String,
+        error: std::io::Error,
+    },
+    /// Files including each other, listed from the first one back to itself.
+    IncludeCycle(Vec<String>),
+    /// A `${path}` reference to a key that does not exist.
+    UnknownReference(String),
+    /// Values referencing each other, listed from the first one back to itself.
+    ReferenceCycle(Vec<String>),
+    /// A step list calls a `macro.<name>` that is not defined.
+    UnknownMacro(String),
+    /// Macros calling each other, listed from the first one back to itself.
+    MacroCycle(Vec<String>),
+    /// A step list expands to more steps than its limit, see
+    /// [`ExpansionLimits`](crate::core::step::expand::ExpansionLimits).
+    TooManySteps(usize),
+    /// Macro calls nested deeper than their limit.
+    MacroTooDeep(usize),
+    /// A part takes its `input_from` a part that does not exist.
+    UnknownInputPart(String),
+    /// Parts taking their input from each other, listed from the first one back to itself.
+    InputCycle(Vec<String>),
+    /// Parts whose configuration does not fit their state, with every problem of each part.
+    InvalidConfiguration(Vec<PartConfigProblems>),
+    /// A configuration value is missing or cannot be converted.
+    Config(ConfigError),
+    /// The program of an executable source failed to print a puzzle.
+    Executable(ExecutableError),
+    /// A network source could not be downloaded nor served from the cache.
+    Network(NetworkError),
+    /// A step of a part's step list could not be parsed.
+    InvalidStep {
+        index: usize,
+        error: Box<ParseError>,
+        span: Span,
+    },
+    /// Any other error, tied to the place in the source it comes from.
+    Spanned {
+        error: Box<ParseError>,
+        span: Span,
+    },
+}
+
+impl ParseError {
+    /// Attaches a source location to the error. Errors that already carry one are kept as is.
+    pub fn at(self, span: Span) -> Self {
+        match self {
+            ParseError::Spanned { .. } | ParseError::InvalidStep { .. } => self,
+            error => ParseError::Spanned {
+                error: Box::new(error),
+                span,
+            },
+        }
+    }
+
+    /// Returns the underlying error, without its source location.
+    pub fn inner(&self) -> &ParseError {
+        match self {
+            ParseError::Spanned { error, .. } => error.inner(),
+            _ => self,
+        }
+    }
+
+    /// Returns where in the source the error happened, if known.
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            ParseError::Spanned { span, .. } | ParseError::InvalidStep { span, .. } => Some(span),
+            ParseError::Config(error) => Some(&error.span).filter(|span| !span.is_detached()),
+            _ => None,
+        }
+    }
+}
+
+fn write_snippet(f: &mut fmt::Formatter<'_>, span: &Span) -> fmt::Result {
+    match span.snippet() {
+        Some(snippet) => write!(f, "\n{snippet}"),
+        None => Ok(()),
+    }
 }
 
 impl fmt::Display for ParseError {
@@ -44,6 +138,72 @@ impl fmt::Display for ParseError {
                 )
             }
             ParseError::EmptyPath => write!(f, "Cannot set value with empty path"),
+            ParseError::UnknownFormat {
+                format,
+                supported_formats,
+            } => {
+                write!(
+                    f,
+                    "Unknown puzzle format '{format}'. Supported formats: {supported_formats:?}"
+                )
+            }
+            ParseError::UnterminatedBlock { key, line } => {
+                write!(
+                    f,
+                    "Block value for '{key}' opened on line {line} is never closed"
+                )
+            }
+            ParseError::Include { path, error } => write!(f, "Cannot include '{path}': {error}"),
+            ParseError::IncludeCycle(chain) => {
+                write!(f, "Include cycle: {}", chain.join(" -> "))
+            }
+            ParseError::UnknownReference(reference) => {
+                write!(f, "Reference to unknown key '{reference}'")
+            }
+            ParseError::ReferenceCycle(chain) => {
+                write!(f, "Reference cycle: {}", chain.join(" -> "))
+            }
+            ParseError::UnknownMacro(name) => write!(f, "Call to unknown macro '{name}'"),
+            ParseError::MacroCycle(chain) => {
+                write!(f, "Macro cycle: {}", chain.join(" -> "))
+            }
+            ParseError::TooManySteps(limit) => {
+                write!(f, "Step list expands to more than {limit} steps")
+            }
+            ParseError::MacroTooDeep(limit) => {
+                write!(f, "Macro calls nested more than {limit} deep")
+            }
+            ParseError::UnknownInputPart(part) => {
+                write!(f, "Input taken from unknown part '{part}'")
+            }
+            ParseError::InputCycle(chain) => {
+                write!(
+                    f,
+                    "Parts taking their input from each other: {}",
+                    chain.join(" -> ")
+                )
+            }
+            ParseError::InvalidConfiguration(parts) => {
+                write!(f, "Invalid configuration")?;
+                for part in parts {
+                    write!(f, "\n{part}")?;
+                }
+                Ok(())
+            }
+            ParseError::Config(error) => {
+                write!(f, "{error}")?;
+                write_snippet(f, &error.span)
+            }
+            ParseError::Executable(error) => write!(f, "{error}"),
+            ParseError::Network(error) => write!(f, "{error}"),
+            ParseError::InvalidStep { index, error, span } => {
+                write!(f, "Step {index} is invalid: {error}")?;
+                write_snippet(f, span)
+            }
+            ParseError::Spanned { error, span } => {
+                write!(f, "{error}")?;
+                write_snippet(f, span)
+            }
             ParseError::InvalidFormat(msg) => write!(f, "Invalid format: {}", msg),
             ParseError::IoError(err) => write!(f, "IO error: {}", err),
             ParseError::FromUtf8Error(err) => {
@@ -72,8 +232,14 @@ impl fmt::Display for ParseError {
 impl std::error::Error for ParseError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
-            ParseError::IoError(err) => Some(err),
+            ParseError::IoError(err) | ParseError::Include { error: err, .. } => Some(err),
             ParseError::FromUtf8Error(err) => Some(err),
+            ParseError::InvalidStep { error, .. } | ParseError::Spanned { error, .. } => {
+                Some(error.as_ref())
+            }
+            ParseError::Config(error) => Some(error),
+            ParseError::Executable(error) => Some(error),
+            ParseError::Network(error) => Some(error),
             _ => None,
         }
     }
@@ -88,6 +254,188 @@ impl From<std::io::Error> for ParseError {
         ParseError::IoError(err)
     }
 }
+impl From<ConfigError> for ParseError {
+    fn from(err: ConfigError) -> Self {
+        ParseError::Config(err)
+    }
+}
+impl From<ExecutableError> for ParseError {
+    fn from(err: ExecutableError) -> Self {
+        ParseError::Executable(err)
+    }
+}
+impl From<NetworkError> for ParseError {
+    fn from(err: NetworkError) -> Self {
+        ParseError::Network(err)
+    }
+}
+
+// ============================================================================
+
+/// A configuration value that is missing or cannot be converted to the requested type.
+#[derive(Debug, Clone)]
+pub struct ConfigError {
+    pub key: String,
+    /// Where the value was found; `None` when it is missing everywhere.
+    pub level: Option<ConfigLevel>,
+    pub kind: ConfigErrorKind,
+    /// Where the value is written; detached when it is missing.
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum ConfigErrorKind {
+    Missing,
+    Invalid { expected: String, value: String },
+}
+
+impl ConfigError {
+    pub(crate) fn missing(key: &str) -> Self {
+        Self {
+            key: key.to_string(),
+            level: None,
+            kind: ConfigErrorKind::Missing,
+            span: Span::default(),
+        }
+    }
+
+    pub(crate) fn invalid(key: &str, level: ConfigLevel, expected: &str, field: &Field) -> Self {
+        let value = match field {
+            Field::Leaf(leaf) => leaf.value.trim().to_string(),
+            Field::Node(_) => "a group of keys".to_string(),
+            Field::List(_) => "a list".to_string(),
+        };
+        Self {
+            key: key.to_string(),
+            level: Some(level),
+            kind: ConfigErrorKind::Invalid {
+                expected: expected.to_string(),
+                value,
+            },
+            span: field
+                .span()
+                .or(field.first_key_span())
+                .cloned()
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn is_missing(&self) -> bool {
+        matches!(self.kind, ConfigErrorKind::Missing)
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.kind, self.level) {
+            (ConfigErrorKind::Invalid { expected, value }, Some(level)) => write!(
+                f,
+                "Configuration key '{}' set at {level} level should be {expected}, got '{value}'",
+                self.key
+            ),
+            _ => write!(f, "Configuration key '{}' is missing", self.key),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// ============================================================================
+
+/// A program run as a puzzle source that did not print a puzzle.
+#[derive(Debug, Clone)]
+pub struct ExecutableError {
+    pub program: String,
+    pub kind: ExecutableErrorKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum ExecutableErrorKind {
+    /// The program exited with a non-zero status. `code` is `None` when a signal ended it.
+    Failed { code: Option<i32>, stderr: String },
+    /// The program ran longer than allowed and was killed.
+    TimedOut { timeout: Duration, stderr: String },
+    /// The program printed more than `limit` bytes and was killed.
+    OutputTooLarge { limit: usize },
+}
+
+impl ExecutableError {
+    /// The end of what the program wrote on stderr, if it got to run its course or out of time.
+    pub fn stderr(&self) -> Option<&str> {
+        match &self.kind {
+            ExecutableErrorKind::Failed { stderr, .. }
+            | ExecutableErrorKind::TimedOut { stderr, .. } => Some(stderr),
+            ExecutableErrorKind::OutputTooLarge { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for ExecutableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let program = &self.program;
+        match &self.kind {
+            ExecutableErrorKind::Failed {
+                code: Some(code), ..
+            } => write!(f, "Program '{program}' exited with code {code}")?,
+            ExecutableErrorKind::Failed { code: None, .. } => {
+                write!(f, "Program '{program}' was killed by a signal")?
+            }
+            ExecutableErrorKind::TimedOut { timeout, .. } => {
+                write!(f, "Program '{program}' did not finish within {timeout:?}")?
+            }
+            ExecutableErrorKind::OutputTooLarge { limit } => {
+                write!(f, "Program '{program}' printed more than {limit} bytes")?
+            }
+        }
+        match self.stderr().map(str::trim_end) {
+            Some(stderr) if !stderr.is_empty() => write!(f, ", stderr ends with:\n{stderr}"),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for ExecutableError {}
+
+// ============================================================================
+
+/// A network source that could not be downloaded nor served from the cache.
+#[derive(Debug, Clone)]
+pub struct NetworkError {
+    pub url: String,
+    pub kind: NetworkErrorKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum NetworkErrorKind {
+    /// The server could not be reached or the download broke off, for the given reason.
+    Request(String),
+    /// The server answered with a status other than `200 OK`.
+    Status(u16),
+    /// The content is larger than `limit` bytes.
+    TooLarge { limit: u64 },
+    /// Offline, and the URL was never downloaded to the cache.
+    NotCached,
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let url = &self.url;
+        match &self.kind {
+            NetworkErrorKind::Request(reason) => write!(f, "Cannot download '{url}': {reason}"),
+            NetworkErrorKind::Status(status) => {
+                write!(f, "Cannot download '{url}': server answered {status}")
+            }
+            NetworkErrorKind::TooLarge { limit } => {
+                write!(f, "Content of '{url}' is larger than {limit} bytes")
+            }
+            NetworkErrorKind::NotCached => {
+                write!(f, "'{url}' is not cached, cannot read it offline")
+            }
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {}
 
 // ============================================================================
 
@@ -184,6 +532,22 @@ pub enum VisualizationError {
     AlreadyAtBeginning,
     InvalidStepIndex(usize),
     MissingState,
+    /// The part's configuration does not fit the selected state.
+    InvalidConfiguration(PartConfigProblems),
+    /// The selected state could not be built from the part's input.
+    StateCreation(ParseError),
+    /// The steps of the selected part could not be parsed.
+    InvalidSteps(ParseError),
+    /// The selected part has no bookmark by that name.
+    UnknownBookmark(String),
+    /// There is no bookmark further in the asked direction.
+    NoMoreBookmarks,
+    /// A part takes its input from `part`, which cannot be played on `state` nor on a state
+    /// converting to it.
+    NoStateConversion {
+        part: String,
+        state: &'static str,
+    },
 }
 
 impl fmt::Display for VisualizationError {
@@ -209,7 +573,16 @@ impl fmt::Display for VisualizationError {
                 f,
                 "State type is implemented, but does not have any loaded renderer : {step}"
             ),
-            VisualizationError::MissingState => todo!(),
+            VisualizationError::MissingState => write!(f, "No state set for the current part"),
+            VisualizationError::InvalidConfiguration(problems) => write!(f, "{problems}"),
+            VisualizationError::StateCreation(err) => write!(f, "Cannot create state: {err}"),
+            VisualizationError::InvalidSteps(err) => write!(f, "Invalid steps: {err}"),
+            VisualizationError::UnknownBookmark(name) => write!(f, "Unknown bookmark '{name}'"),
+            VisualizationError::NoMoreBookmarks => write!(f, "No bookmark in that direction"),
+            VisualizationError::NoStateConversion { part, state } => write!(
+                f,
+                "Part '{part}' has no state converting to '{state}' to take the input from"
+            ),
         }
     }
 }
@@ -219,6 +592,9 @@ impl std::error::Error for VisualizationError {
         match self {
             VisualizationError::RenderError(err) => Some(err),
             VisualizationError::StepError(err) => Some(err),
+            VisualizationError::StateCreation(err) | VisualizationError::InvalidSteps(err) => {
+                Some(err)
+            }
             _ => None,
         }
     }
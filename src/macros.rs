@@ -75,20 +75,24 @@ $(
     )*
 )*
   lazy_static::lazy_static! {
-            static ref STEP_TYPE_MAPPINGS: std::collections::HashMap<&'static str, (&'static str, $crate::core::input::processors::StepParserFn )> = {
+            static ref STEP_TYPE_MAPPINGS: std::collections::HashMap<&'static str, (&'static str, $crate::core::input::processors::StepParserFn, $crate::core::input::processors::StructuredStepParserFn, $crate::core::input::processors::StepDecoderFn)> = {
                 let mut map = std::collections::HashMap::new();
 
                 $(
                     let type_id = <$step_type>::type_id();
-                    let parser: fn(&str) -> Result<Box<dyn $crate::core::step::StepAction>, ParseError> =
-                        |s| Ok(Box::new(<$step_type>::from_str(s)?));
+                    let parser: $crate::core::input::processors::StepParserFn =
+                        |s, configuration| Ok(Box::new(<$step_type>::from_str_with(s, configuration)?));
+                    let structured_parser: $crate::core::input::processors::StructuredStepParserFn =
+                        |field| Ok(Box::new(<$step_type>::from_field(field)?));
+                    let decoder: $crate::core::input::processors::StepDecoderFn =
+                        |record| Ok(Box::new(<$step_type>::decode(record)?));
 
                     // Insert canonical type_id
-                    map.insert(type_id, (type_id, parser));
+                    map.insert(type_id, (type_id, parser, structured_parser, decoder));
 
                     // Insert aliases
                     $(
-                        map.insert($alias, (type_id, parser));
+                        map.insert($alias, (type_id, parser, structured_parser, decoder));
                     )*
                 )*
 
@@ -109,6 +113,7 @@ $(
                     snapshot_type_id: <$state_type>::snapshot_type_id(),
                     factory: |input, configuration| Ok(Box::new(<$state_type>::parse(input, configuration)?)),
                     required_config_fields: &[$($($req),*)?],
+                    config_schema: <$state_type>::config_schema(),
                     is_default: false, // Will be set below
                 },
             )*
@@ -127,12 +132,18 @@ $(
 
         }
         fn get_step_parser(step_type_id: &str) -> Option<$crate::core::input::processors::StepParserFn> {
-            STEP_TYPE_MAPPINGS.get(step_type_id).map(|(_, parser)| *parser)
+            STEP_TYPE_MAPPINGS.get(step_type_id).map(|(_, parser, _, _)| *parser)
+        }
+        fn get_structured_step_parser(step_type_id: &str) -> Option<$crate::core::input::processors::StructuredStepParserFn> {
+            STEP_TYPE_MAPPINGS.get(step_type_id).map(|(_, _, parser, _)| *parser)
+        }
+        fn get_step_decoder(step_type_id: &str) -> Option<$crate::core::input::processors::StepDecoderFn> {
+            STEP_TYPE_MAPPINGS.get(step_type_id).map(|(_, _, _, decoder)| *decoder)
         }
 
         pub fn step_type_to_id(step_type: &str) -> Result<&'static str, ParseError> {
             STEP_TYPE_MAPPINGS.get(step_type)
-                .map(|(canonical_id, _)| *canonical_id)
+                .map(|(canonical_id, _, _, _)| *canonical_id)
                 .ok_or_else(|| ParseError::UnknownStepType {
                     step_type: step_type.to_string(),
                     supported_step_types: get_supported_step_types(),
@@ -151,6 +162,8 @@ pub fn create_registry() -> $crate::engine::registry::Registry {
         $crate::engine::registry::DomainRegistry::new(
              get_supported_step_types(),
              get_step_parser,
+             get_structured_step_parser,
+             get_step_decoder,
             step_type_to_id,
         )
         )
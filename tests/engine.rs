@@ -1,12 +1,31 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 use storyframe::{
     StepAction,
     algorithm::PuzzleSource,
     core::{
         configuration::Configuration,
-        state::{VisualizationState, snapshot::StateSnapshot},
+        input::{
+            collection::{CollectionFilter, PuzzleCollection, SortKey},
+            get_metadata_from_source,
+        },
+        render::Renderer,
+        state::{
+            FromState, VisualizationState,
+            schema::{ConfigProblem, ConfigType},
+            snapshot::StateSnapshot,
+        },
+        step::{
+            binary::{RecordLayout, StepWriter},
+            expand::ExpansionLimits,
+        },
+    },
+    domains::text::{
+        TextStep,
+        state::{TextSnapshot, TextState},
     },
-    engine::VisualizationEngine,
-    error::ParseError,
+    engine::{VisualizationEngine, selectors::PartSelector},
+    error::{ParseError, VisualizationError},
     impl_render_context, register_domain_types,
 };
 
@@ -14,6 +33,46 @@ struct Ctx;
 
 impl_render_context!(Ctx => CtxTag);
 
+/// Collects the words of a text state, to check what the engine is at.
+#[derive(Default)]
+struct Words(Vec<String>);
+
+impl_render_context!(Words => WordsTag);
+
+#[derive(Clone)]
+struct WordsRenderer;
+
+impl Renderer for WordsRenderer {
+    type StateSnapshot = TextSnapshot;
+    type Context<'a> = Words;
+
+    fn render_state(&mut self, snapshot: &TextSnapshot, context: &mut Words) {
+        context.0 = snapshot.iter().map(|word| word.content.clone()).collect();
+    }
+
+    fn renderer_name(&self) -> &'static str {
+        "words"
+    }
+}
+
+/// Selects the first part of `content` on a text state, rendered with [`WordsRenderer`].
+fn text_engine(content: &str) -> VisualizationEngine {
+    let mut engine =
+        VisualizationEngine::from_source(PuzzleSource::String(content.to_string())).unwrap();
+    engine.register_renderer(WordsRenderer);
+    engine.select_part(|_| {}).unwrap();
+    let mut config = engine.configure_for_current_context::<Words>();
+    config.set_state(|_| {}).unwrap();
+    config.set_renderer(|_| {}).unwrap();
+    engine
+}
+
+fn words(engine: &mut VisualizationEngine) -> Vec<String> {
+    let mut words = Words::default();
+    engine.render(&mut words).unwrap();
+    words.0
+}
+
 #[test]
 fn test_engine_basic_puzzle_parsing() {
     let content = r#"
@@ -55,6 +114,602 @@ fn test_engine_basic_puzzle_parsing() {
     //        });
 }
 
+#[test]
+fn test_engine_part_order() {
+    let content = r#"
+        title: Ordered
+        part.second.name: Second
+        part.second.step_type: text_step
+        part.second.input: a_b
+        part.second.steps: 0__x____
+        part.first.name: First
+        part.first.step_type: text_step
+        part.first.input: a_b
+        part.first.steps: 1__y____
+        part.first.order: 1
+        part.third.name: Third
+        part.third.step_type: text_step
+        part.third.input: a_b
+        part.third.steps: 1__y____
+    "#;
+    let expected = ["first", "second", "third"];
+
+    let mut engine =
+        VisualizationEngine::from_source(PuzzleSource::String(content.to_string())).unwrap();
+    let ids: Vec<&str> = engine
+        .get_parts()
+        .unwrap()
+        .iter()
+        .map(|p| p.id.as_str())
+        .collect();
+    assert_eq!(ids, expected);
+
+    engine.select_part(|_| {}).unwrap();
+    assert_eq!(engine.current_part().unwrap().id, "first");
+
+    let metadata = get_metadata_from_source(PuzzleSource::String(content.to_string())).unwrap();
+    let ids: Vec<&str> = metadata.parts_info.iter().map(|p| p.id.as_str()).collect();
+    assert_eq!(ids, expected);
+}
+
+#[test]
+fn test_engine_parses_steps_on_selection() {
+    let content = r#"
+        title: Test Puzzle
+        part.good.name: Good
+        part.good.step_type: text_step
+        part.good.input: hello
+        part.good.steps: 0__a____ | 1__b____
+        part.bad.name: Bad
+        part.bad.step_type: text_step
+        part.bad.input: hello
+        part.bad.steps: 0__a____ | x__b____
+    "#;
+    // Broken steps only surface once their part is selected
+    let mut engine =
+        VisualizationEngine::from_source(PuzzleSource::String(content.to_string())).unwrap();
+    engine.select_part(|_| {}).unwrap();
+    assert_eq!(engine.current_step_info().unwrap(), (0, 2));
+    let err = engine
+        .select_part(|selector| selector.options_mut()[1].select())
+        .unwrap_err();
+    assert!(matches!(err, VisualizationError::InvalidSteps(_)));
+}
+
+#[test]
+fn test_engine_steps_through_binary_steps() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut writer =
+        StepWriter::new(Vec::new(), TextStep::type_id(), RecordLayout::Varint).unwrap();
+    for index in 0..600 {
+        let step = TextStep::from_str(&format!("{}__{}____", index % 5, index % 10)).unwrap();
+        writer.push(&step).unwrap();
+    }
+    let mut steps = writer.finish().unwrap();
+    // A last record that does not decode, only noticed when stepped onto
+    steps.extend([1, 0xff]);
+    std::fs::write(dir.path().join("steps.bin"), steps).unwrap();
+    let puzzle = dir.path().join("puzzle.sf");
+    std::fs::write(
+        &puzzle,
+        "title: Binary\n\
+         part.a.name: A\n\
+         part.a.step_type: text\n\
+         part.a.input: a_b_c_d_e\n\
+         part.a.steps_file: steps.bin\n",
+    )
+    .unwrap();
+
+    let metadata = get_metadata_from_source(PuzzleSource::File(puzzle.clone())).unwrap();
+    assert_eq!(metadata.parts_info[0].step_count, 601);
+
+    let mut engine = VisualizationEngine::from_source(PuzzleSource::File(puzzle)).unwrap();
+    engine.select_part(|_| {}).unwrap();
+    engine
+        .configure_for_current_context::<Ctx>()
+        .set_state(|_| {})
+        .unwrap();
+    assert_eq!(engine.current_step_info().unwrap(), (0, 601));
+    for _ in 0..600 {
+        engine.next_step().unwrap();
+    }
+    assert!(engine.can_step_forward().unwrap());
+    let err = engine.next_step().unwrap_err();
+    assert!(matches!(
+        err,
+        VisualizationError::InvalidSteps(ParseError::InvalidStep { index: 600, .. })
+    ));
+    assert_eq!(engine.current_step_info().unwrap(), (600, 601));
+
+    // Seeks only decode the records before their target, never reaching the broken one
+    let mut engine = text_engine(&format!(
+        "title: Binary\n\
+         part.a.name: A\n\
+         part.a.step_type: text\n\
+         part.a.input: a_b_c_d_e\n\
+         part.a.steps_file: {}\n",
+        dir.path().join("steps.bin").display()
+    ));
+    engine.goto_step(600).unwrap();
+    assert_eq!(words(&mut engine), ["5", "6", "7", "8", "9"]);
+    engine.previous_step().unwrap();
+    assert_eq!(words(&mut engine), ["5", "6", "7", "8", "4"]);
+    engine.goto_step(3).unwrap();
+    assert_eq!(words(&mut engine), ["0", "1", "2", "d", "e"]);
+    assert!(matches!(
+        engine.goto_step(601),
+        Err(VisualizationError::InvalidSteps(ParseError::InvalidStep {
+            index: 600,
+            ..
+        }))
+    ));
+}
+
+#[test]
+fn test_engine_opens_collection_entries() {
+    let dir = tempfile::tempdir().unwrap();
+    for (day, difficulty) in [(1, 4), (2, 2), (3, 5)] {
+        std::fs::write(
+            dir.path().join(format!("day{day:02}.txt")),
+            format!(
+                "title: Day {day}\n\
+                 difficulty: {difficulty}\n\
+                 part.a.name: A\n\
+                 part.a.step_type: text\n\
+                 part.a.input: a_b\n\
+                 part.a.steps: 0__x____ | 1__y____\n"
+            ),
+        )
+        .unwrap();
+    }
+    let collection =
+        PuzzleCollection::from_source(PuzzleSource::Directory(dir.path().to_path_buf())).unwrap();
+    let filter = CollectionFilter {
+        difficulty: Some(3..=5),
+        ..Default::default()
+    };
+    let entries = collection.query(&filter, Some(SortKey::Difficulty));
+    let titles: Vec<&str> = entries.iter().map(|e| e.metadata.title.as_str()).collect();
+    assert_eq!(titles, ["Day 1", "Day 3"]);
+
+    let mut engine = entries[1].open().unwrap();
+    engine.select_part(|_| {}).unwrap();
+    engine
+        .configure_for_current_context::<Ctx>()
+        .set_state(|_| {})
+        .unwrap();
+    engine.play_to_end().unwrap();
+    assert_eq!(engine.current_step_info().unwrap(), (2, 2));
+}
+
+#[test]
+fn test_engine_expands_step_lists() {
+    let content = r#"
+        title: Test Puzzle
+        macro.mark: {0}__x__red__ | {0}__x____
+        part.a.name: A
+        part.a.step_type: text_step
+        part.a.input: a_b_c_d
+        part.a.steps: 2*(0__y____) | for i in 1..4: mark!({i}) | 3__z____
+        part.b.name: B
+        part.b.step_type: text_step
+        part.b.input: a_b_c_d
+        part.b.steps: 0__y____ | mark!(x)
+    "#;
+    let metadata = get_metadata_from_source(PuzzleSource::String(content.to_string())).unwrap();
+    assert_eq!(metadata.parts_info[0].step_count, 9);
+
+    let mut engine =
+        VisualizationEngine::from_source(PuzzleSource::String(content.to_string())).unwrap();
+    engine.select_part(|_| {}).unwrap();
+    engine
+        .configure_for_current_context::<Ctx>()
+        .set_state(|_| {})
+        .unwrap();
+    engine.play_to_end().unwrap();
+    assert_eq!(engine.current_step_info().unwrap(), (9, 9));
+
+    // Steps coming from a macro are reported at the call
+    let err = engine
+        .select_part(|selector| selector.options_mut()[1].select())
+        .unwrap_err();
+    let VisualizationError::InvalidSteps(ParseError::InvalidStep { index, span, .. }) = err else {
+        panic!("unexpected error {err:?}");
+    };
+    assert_eq!(index, 1);
+    assert_eq!(span.column, 34);
+}
+
+#[test]
+fn test_engine_limits_step_expansion() {
+    let content = r#"
+        title: Test Puzzle
+        part.a.name: A
+        part.a.step_type: text
+        part.a.input: a_b
+        part.a.steps: 3*(0__x____)
+    "#;
+    let mut registry = create_registry();
+    registry
+        .domain_registry_mut()
+        .set_expansion_limits(ExpansionLimits {
+            max_steps: 2,
+            ..ExpansionLimits::default()
+        });
+    let mut engine = VisualizationEngine::from_source_with_registry(
+        PuzzleSource::String(content.to_string()),
+        registry,
+    )
+    .unwrap();
+    let err = engine.select_part(|_| {}).unwrap_err();
+    let VisualizationError::InvalidSteps(err) = err else {
+        panic!("unexpected error {err:?}");
+    };
+    assert!(matches!(err.inner(), ParseError::TooManySteps(2)));
+}
+
+#[test]
+fn test_engine_plays_step_groups_as_one_step() {
+    let content = r#"
+        title: Test Puzzle
+        part.a.name: A
+        part.a.step_type: text_step
+        part.a.input: a_b_c_d
+        part.a.steps: 0__w____ | [1__x____ | 2__y____ | 3__z____] | 0__v____
+    "#;
+    let metadata = get_metadata_from_source(PuzzleSource::String(content.to_string())).unwrap();
+    assert_eq!(metadata.parts_info[0].step_count, 3);
+
+    let mut engine =
+        VisualizationEngine::from_source(PuzzleSource::String(content.to_string())).unwrap();
+    engine.select_part(|_| {}).unwrap();
+    engine
+        .configure_for_current_context::<Ctx>()
+        .set_state(|_| {})
+        .unwrap();
+    assert_eq!(engine.current_step_info().unwrap(), (0, 3));
+    engine.next_step().unwrap();
+    engine.next_step().unwrap();
+    assert_eq!(engine.current_step_info().unwrap(), (2, 3));
+    engine.next_step().unwrap();
+    assert!(!engine.can_step_forward().unwrap());
+    assert!(matches!(
+        engine.next_step(),
+        Err(VisualizationError::AlreadyAtEnd)
+    ));
+}
+
+#[test]
+fn test_engine_seeks_steps() {
+    let mut engine = text_engine(
+        r#"
+        title: Test Puzzle
+        part.a.name: A
+        part.a.step_type: text_step
+        part.a.input: a_b_c_d
+        part.a.steps: 0__w____ | [1__x____ | 2__y____] | 3__z____
+    "#,
+    );
+    assert_eq!(words(&mut engine), ["a", "b", "c", "d"]);
+    engine.next_step().unwrap();
+    assert_eq!(words(&mut engine), ["w", "b", "c", "d"]);
+    engine.next_step().unwrap();
+    assert_eq!(words(&mut engine), ["w", "x", "y", "d"]);
+    engine.next_step().unwrap();
+    assert_eq!(words(&mut engine), ["w", "x", "y", "z"]);
+
+    // Going back undoes a whole group at once
+    engine.previous_step().unwrap();
+    assert_eq!(engine.current_step_info().unwrap(), (2, 3));
+    assert_eq!(words(&mut engine), ["w", "x", "y", "d"]);
+    engine.previous_step().unwrap();
+    assert_eq!(words(&mut engine), ["w", "b", "c", "d"]);
+    engine.previous_step().unwrap();
+    assert_eq!(words(&mut engine), ["a", "b", "c", "d"]);
+    assert!(matches!(
+        engine.previous_step(),
+        Err(VisualizationError::AlreadyAtBeginning)
+    ));
+
+    engine.goto_step(3).unwrap();
+    assert_eq!(engine.current_step_info().unwrap(), (3, 3));
+    assert_eq!(words(&mut engine), ["w", "x", "y", "z"]);
+    engine.goto_step(1).unwrap();
+    assert_eq!(engine.current_step_info().unwrap(), (1, 3));
+    assert_eq!(words(&mut engine), ["w", "b", "c", "d"]);
+    engine.goto_step(2).unwrap();
+    assert_eq!(words(&mut engine), ["w", "x", "y", "d"]);
+    engine.goto_step(0).unwrap();
+    assert_eq!(words(&mut engine), ["a", "b", "c", "d"]);
+    assert!(matches!(
+        engine.goto_step(4),
+        Err(VisualizationError::InvalidStepIndex(4))
+    ));
+    assert_eq!(words(&mut engine), ["a", "b", "c", "d"]);
+}
+
+#[test]
+fn test_engine_step_captions() {
+    let content = r#"
+        title: Test Puzzle
+        part.a.name: A
+        part.a.step_type: text_step
+        part.a.input: a_b_c
+        part.a.steps: 0__x____ @"replace a" @"the first word goes" | 1__y____ | [2__z____ | 0__w____ @"twice"]
+    "#;
+    let mut engine =
+        VisualizationEngine::from_source(PuzzleSource::String(content.to_string())).unwrap();
+    engine.select_part(|_| {}).unwrap();
+    engine
+        .configure_for_current_context::<Ctx>()
+        .set_state(|_| {})
+        .unwrap();
+    assert_eq!(engine.current_step_caption().unwrap(), None);
+
+    engine.next_step().unwrap();
+    let caption = engine.current_step_caption().unwrap().unwrap();
+    assert_eq!(caption.caption, "replace a");
+    assert_eq!(caption.note.as_deref(), Some("the first word goes"));
+    engine.next_step().unwrap();
+    assert_eq!(engine.current_step_caption().unwrap(), None);
+    // The caption of a group is the first one among its steps
+    engine.next_step().unwrap();
+    let caption = engine.current_step_caption().unwrap().unwrap();
+    assert_eq!(caption.caption, "twice");
+}
+
+#[test]
+fn test_engine_bookmarks() {
+    let content = r#"
+        title: Test Puzzle
+        part.a.name: A
+        part.a.step_type: text_step
+        part.a.input: a_b_c
+        part.a.bookmarks.start: 0
+        part.a.steps: 0__x____ | 1__y____ | #found | 2__z____
+    "#;
+    let mut engine = text_engine(content);
+    let names: Vec<&str> = engine
+        .bookmarks()
+        .unwrap()
+        .iter()
+        .map(|bookmark| bookmark.name.as_str())
+        .collect();
+    assert_eq!(names, ["start", "found"]);
+    let chapters: Vec<_> = engine
+        .chapters()
+        .unwrap()
+        .into_iter()
+        .map(|chapter| chapter.steps)
+        .collect();
+    assert_eq!(chapters, [0..2, 2..3]);
+
+    assert!(matches!(
+        engine.goto_bookmark("missing"),
+        Err(VisualizationError::UnknownBookmark(name)) if name == "missing"
+    ));
+
+    engine.goto_bookmark("found").unwrap();
+    assert_eq!(engine.current_step_info().unwrap(), (2, 3));
+    assert_eq!(words(&mut engine), ["x", "y", "c"]);
+    engine.goto_bookmark("start").unwrap();
+    assert_eq!(engine.current_step_info().unwrap(), (0, 3));
+    assert_eq!(words(&mut engine), ["a", "b", "c"]);
+    assert!(matches!(
+        engine.previous_bookmark(),
+        Err(VisualizationError::NoMoreBookmarks)
+    ));
+
+    engine.next_step().unwrap();
+    engine.next_bookmark().unwrap();
+    assert_eq!(engine.current_step_info().unwrap(), (2, 3));
+    assert_eq!(words(&mut engine), ["x", "y", "c"]);
+    engine.play_to_end().unwrap();
+    assert_eq!(words(&mut engine), ["x", "y", "z"]);
+    assert!(matches!(
+        engine.next_bookmark(),
+        Err(VisualizationError::NoMoreBookmarks)
+    ));
+    engine.previous_bookmark().unwrap();
+    assert_eq!(engine.current_step_info().unwrap(), (2, 3));
+    assert_eq!(words(&mut engine), ["x", "y", "c"]);
+    engine.previous_bookmark().unwrap();
+    assert_eq!(engine.current_step_info().unwrap(), (0, 3));
+    assert_eq!(words(&mut engine), ["a", "b", "c"]);
+}
+
+#[test]
+fn test_engine_input_from_other_part() {
+    let content = r#"
+        title: Test Puzzle
+        part.a.name: A
+        part.a.step_type: text
+        part.a.input: a_b
+        part.a.steps: 0__x____
+        part.b.name: B
+        part.b.step_type: t
+        part.b.input_from: a
+        part.b.steps: anything
+        part.c.name: C
+        part.c.step_type: text
+        part.c.input_from: a
+        part.c.steps: 1__y____
+    "#;
+    let source = || PuzzleSource::String(content.to_string());
+    let select = |engine: &mut VisualizationEngine, selection: fn(&mut PartSelector)| {
+        engine.select_part(selection).unwrap();
+        engine
+            .configure_for_current_context::<Ctx>()
+            .set_state(|_| {})
+            .err()
+    };
+
+    // Without a declared conversion, a text part cannot feed a test state
+    let mut engine =
+        VisualizationEngine::from_source_with_registry(source(), create_registry()).unwrap();
+    assert!(matches!(
+        select(&mut engine, |s| s.options_mut()[1].select()),
+        Some(VisualizationError::NoStateConversion { part, state: "test_state" }) if part == "a"
+    ));
+    // Parts sharing a state type start from the final state as is, seeks included
+    engine.register_renderer(WordsRenderer);
+    assert!(select(&mut engine, |s| s.options_mut()[2].select()).is_none());
+    engine
+        .configure_for_current_context::<Words>()
+        .set_renderer(|_| {})
+        .unwrap();
+    engine.play_to_end().unwrap();
+    assert_eq!(words(&mut engine), ["x", "y"]);
+    engine.previous_step().unwrap();
+    assert_eq!(words(&mut engine), ["x", "b"]);
+
+    let mut registry = create_registry();
+    registry
+        .state_registry_mut()
+        .register_conversion::<TextState, TestState>();
+    let mut engine = VisualizationEngine::from_source_with_registry(source(), registry).unwrap();
+    assert!(select(&mut engine, |s| s.options_mut()[1].select()).is_none());
+    engine.reset().unwrap();
+}
+
+#[test]
+fn test_engine_plays_input_part_once() {
+    let content = r#"
+        title: Test Puzzle
+        part.s.name: S
+        part.s.step_type: t
+        part.s.input: x
+        part.s.steps: a | b
+        part.d.name: D
+        part.d.step_type: t
+        part.d.input_from: s
+        part.d.steps: c
+    "#;
+    let mut engine = VisualizationEngine::from_source_with_registry(
+        PuzzleSource::String(content.to_string()),
+        create_registry(),
+    )
+    .unwrap();
+    let select_derived = |engine: &mut VisualizationEngine| {
+        engine
+            .select_part(|selector| selector.options_mut()[1].select())
+            .unwrap();
+        engine
+            .configure_for_current_context::<Ctx>()
+            .set_state(|_| {})
+            .unwrap();
+    };
+    select_derived(&mut engine);
+    assert_eq!(APPLIED_TEST_STEPS.load(Ordering::Relaxed), 2);
+    engine.reset().unwrap();
+    engine.next_step().unwrap();
+    engine.reset().unwrap();
+    select_derived(&mut engine);
+    // Only the step of the part itself was played again
+    assert_eq!(APPLIED_TEST_STEPS.load(Ordering::Relaxed), 3);
+}
+
+#[test]
+fn test_engine_metadata() {
+    let content = r#"
+        title: Test Puzzle
+        description: Counting words
+        meta.tags: text, beginner
+        meta.source: https://example.com/day1
+        meta.language: en
+        config.speed: 2
+        part.a.name: A
+        part.a.step_type: text_step
+        part.a.input: hello
+        part.a.steps: 0__a____
+    "#;
+    let engine =
+        VisualizationEngine::from_source(PuzzleSource::String(content.to_string())).unwrap();
+    let metadata = engine.get_metadata().unwrap();
+    assert_eq!(metadata.description.as_deref(), Some("Counting words"));
+    assert_eq!(metadata.tags(), ["text", "beginner"]);
+    assert_eq!(metadata.meta("source"), Some("https://example.com/day1"));
+    assert_eq!(metadata.meta("language"), Some("en"));
+    assert_eq!(metadata.configuration.get_integer("speed").unwrap(), 2);
+
+    let metadata = get_metadata_from_source(PuzzleSource::String(content.to_string())).unwrap();
+    assert_eq!(metadata.description.as_deref(), Some("Counting words"));
+    assert_eq!(metadata.meta.len(), 3);
+    assert_eq!(metadata.configuration.get_str("speed"), Some("2"));
+}
+
+#[test]
+fn test_engine_validates_configuration() {
+    let content = r#"
+        title: Grid Puzzle
+        config.columns: 2
+        part.a.name: A
+        part.a.step_type: grid
+        part.a.input: 1:2:3:4
+        part.a.steps: 0_0__5__
+        part.a.config.rows: two
+        part.b.name: B
+        part.b.step_type: grid
+        part.b.input: 1:2
+        part.b.steps: 0_0__5__
+        part.b.config.rows: 0
+        part.c.name: C
+        part.c.step_type: grid
+        part.c.input: 1:2
+        part.c.steps: 0_0__5__
+        part.c.config.rows: 1
+    "#;
+    let result = VisualizationEngine::from_source(PuzzleSource::String(content.to_string()));
+    let Err(ParseError::InvalidConfiguration(parts)) = result else {
+        panic!("expected a configuration error");
+    };
+    let message = ParseError::InvalidConfiguration(parts.clone()).to_string();
+    assert!(
+        message.contains(
+            "configuration of part a does not fit state simple_grid_state:\n  \
+             - 'rows' should be an integer, got 'two' (<string>:8:29)"
+        ),
+        "{message}"
+    );
+    assert!(
+        message.contains("- 'rows' should be at least 1, got 0 (<string>:13:29)"),
+        "{message}"
+    );
+    let problems: Vec<(&str, &str)> = parts
+        .iter()
+        .flat_map(|part| part.problems.iter().map(|p| (part.part.as_str(), p.key())))
+        .collect();
+    assert_eq!(problems, [("a", "rows"), ("b", "rows")]);
+    assert!(matches!(
+        parts[0].problems[0],
+        ConfigProblem::WrongType {
+            expected: ConfigType::Integer,
+            ..
+        }
+    ));
+    assert!(matches!(
+        parts[1].problems[0],
+        ConfigProblem::OutOfRange { .. }
+    ));
+
+    // Columns come from the puzzle configuration
+    let valid = content
+        .replace("rows: two", "rows: 2")
+        .replace("rows: 0", "rows: 1");
+    let mut engine = VisualizationEngine::from_source(PuzzleSource::String(valid)).unwrap();
+    engine
+        .select_part(|selector| selector.options_mut()[0].select())
+        .unwrap();
+    engine
+        .configure_for_current_context::<Ctx>()
+        .set_state(|selector| {
+            let schema = selector.options_mut()[0].config_schema();
+            let names: Vec<&str> = schema.iter().map(|field| field.name).collect();
+            assert_eq!(names, ["columns", "rows"]);
+        })
+        .unwrap();
+}
+
 #[test]
 fn test_engine_invalid_step_type() {
     let content = r#"
@@ -67,7 +722,7 @@ fn test_engine_invalid_step_type() {
 
     let result = VisualizationEngine::from_source(PuzzleSource::String(content.to_string()));
     assert!(matches!(
-        result,
+        result.as_ref().map_err(ParseError::inner),
         Err(ParseError::UnknownStepType {
             step_type: _,
             supported_step_types: _
@@ -114,7 +769,10 @@ fn test_engine_missing_puzzle_fields() {
 fn test_engine_missing_field(content: &str) {
     let result = VisualizationEngine::from_source(PuzzleSource::String(content.to_string()));
     // TODO: we could also test for correct fmt
-    assert!(matches!(result, Err(ParseError::MissingPartField(_, _))));
+    assert!(matches!(
+        result.as_ref().map_err(ParseError::inner),
+        Err(ParseError::MissingPartField(_, _))
+    ));
 }
 
 #[test]
@@ -138,7 +796,10 @@ fn test_engine_invalid_part(content: &str) {
     let result = VisualizationEngine::from_source(PuzzleSource::String(content.to_string()));
     // let result = PuzzleInstance::from_source(PuzzleSource::String(content.to_string()));
     // TODO: we could also test for correct fmt
-    assert!(matches!(result, Err(ParseError::InvalidPartStructure(_))));
+    assert!(matches!(
+        result.as_ref().map_err(ParseError::inner),
+        Err(ParseError::InvalidPartStructure(_))
+    ));
 }
 // register_domain_types!();
 
@@ -162,6 +823,10 @@ impl StepAction for TestStep {
     {
         Ok(Self)
     }
+
+    fn to_str(&self) -> String {
+        String::new()
+    }
 }
 
 struct TestSnapshot;
@@ -178,7 +843,10 @@ impl StateSnapshot for TestSnapshot {
     }
 }
 
-#[derive(Debug)]
+/// Steps applied on test states, only played by `test_engine_plays_input_part_once`.
+static APPLIED_TEST_STEPS: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone)]
 struct TestState;
 impl VisualizationState for TestState {
     type Step = TestStep;
@@ -186,6 +854,7 @@ impl VisualizationState for TestState {
     type Snapshot = TestSnapshot;
 
     fn apply_step(&mut self, _step: &Self::Step) -> Result<(), storyframe::error::StepError> {
+        APPLIED_TEST_STEPS.fetch_add(1, Ordering::Relaxed);
         Ok(())
     }
 
@@ -201,6 +870,10 @@ impl VisualizationState for TestState {
         Box::new(TestSnapshot)
     }
 
+    fn duplicate(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+
     fn state_type_id() -> &'static str
     where
         Self: Sized,
@@ -216,7 +889,25 @@ impl VisualizationState for TestState {
     }
 }
 
-register_domain_types!(TestStep {
-    aliases: ["t"],
-    states: [TestState]
-});
+/// Only builds from a text state whose first word was replaced by `x`.
+impl FromState<TextState> for TestState {
+    fn from_state(state: &TextState, _configuration: &Configuration) -> Result<Self, ParseError> {
+        match state.create_snapshot().first() {
+            Some(word) if word.content == "x" => Ok(TestState),
+            _ => Err(ParseError::InvalidFormat(
+                "source part was not played".to_string(),
+            )),
+        }
+    }
+}
+
+register_domain_types!(
+    TestStep {
+        aliases: ["t"],
+        states: [TestState]
+    },
+    TextStep {
+        aliases: ["text"],
+        states: [TextState]
+    }
+);